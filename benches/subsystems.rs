@@ -0,0 +1,15 @@
+//! Criterion benchmarks of the engine's subsystems, over the positions of the node benchmark.
+//! `viridithas bench <subsystem>` gives a quicker, rougher measurement of the same thing.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use viridithas::{Microbench, Subsystem};
+
+fn subsystems(c: &mut Criterion) {
+    for subsystem in Subsystem::ALL {
+        let mut bench = Microbench::new(subsystem);
+        c.bench_function(&subsystem.to_string(), |b| b.iter(|| bench.run()));
+    }
+}
+
+criterion_group!(benches, subsystems);
+criterion_main!(benches);
@@ -1,3 +1,20 @@
+// The positions used by the node benchmark, and microbenchmarks that time individual parts of
+// the engine over the same positions, so that a slowdown can be pinned on one subsystem
+// instead of only showing up as a drop in search speed.
+
+use std::{
+    fmt::{self, Display},
+    hint::black_box,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    board::{movegen::MoveList, Board},
+    chessmove::Move,
+    nnue::network::NNUEState,
+};
+
 pub const BENCH_POSITIONS: [&str; 50] = [
     "r3k2r/2pb1ppp/2pp1q2/p7/1nP1B3/1P2P3/P2N1PPP/R2QK2R w KQkq a6 0 14",
     "4rrk1/2p1b1p1/p1p3q1/4p3/2P2n1p/1P1NR2P/PB3PP1/3R1QK1 b - - 2 24",
@@ -50,3 +67,149 @@ pub const BENCH_POSITIONS: [&str; 50] = [
     "3br1k1/p1pn3p/1p3n2/5pNq/2P1p3/1PN3PP/P2Q1PB1/4R1K1 w - - 0 23",
     "2r2b2/5p2/5k2/p1r1pP2/P2pB3/1P3P2/K1P3R1/7R w - - 23 93",
 ];
+
+/// A part of the engine that can be benchmarked on its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Subsystem {
+    /// Legal move generation.
+    Movegen,
+    /// Neural network evaluation, from a freshly refreshed accumulator.
+    Eval,
+    /// Making and unmaking every legal move.
+    MakeMove,
+    /// Static exchange evaluation of every legal move.
+    See,
+}
+
+impl Subsystem {
+    /// Every subsystem, in the order that they're benchmarked.
+    pub const ALL: [Self; 4] = [Self::Movegen, Self::Eval, Self::MakeMove, Self::See];
+}
+
+impl FromStr for Subsystem {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "movegen" => Ok(Self::Movegen),
+            "eval" => Ok(Self::Eval),
+            "makemove" => Ok(Self::MakeMove),
+            "see" => Ok(Self::See),
+            _ => Err(format!("unknown benchmark \"{s}\", expected one of movegen, eval, makemove, see")),
+        }
+    }
+}
+
+impl Display for Subsystem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Movegen => "movegen",
+            Self::Eval => "eval",
+            Self::MakeMove => "makemove",
+            Self::See => "see",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// A microbenchmark of one subsystem over the bench positions.
+pub struct Microbench {
+    subsystem: Subsystem,
+    boards: Vec<Board>,
+    /// The legal moves of each position.
+    moves: Vec<Vec<Move>>,
+    nnue: Box<NNUEState>,
+}
+
+impl Microbench {
+    /// Sets up a microbenchmark of `subsystem`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if one of the bench positions is invalid.
+    #[must_use]
+    pub fn new(subsystem: Subsystem) -> Self {
+        let boards = BENCH_POSITIONS.iter().map(|fen| Board::from_fen(fen).unwrap()).collect::<Vec<_>>();
+        let moves = boards.iter().map(Board::legal_moves).collect();
+        let nnue = NNUEState::new(&boards[0]);
+        Self { subsystem, boards, moves, nnue }
+    }
+
+    /// Runs the benchmark once over every position, and returns the number of operations performed.
+    pub fn run(&mut self) -> u64 {
+        let mut operations = 0;
+        match self.subsystem {
+            Subsystem::Movegen => {
+                for board in &self.boards {
+                    let mut move_list = MoveList::new();
+                    board.generate_moves(black_box(&mut move_list));
+                    operations += 1;
+                }
+            }
+            Subsystem::Eval => {
+                for board in &self.boards {
+                    self.nnue.reinit_from(board);
+                    black_box(self.nnue.evaluate(board.turn()));
+                    operations += 1;
+                }
+            }
+            Subsystem::MakeMove => {
+                for (board, moves) in self.boards.iter_mut().zip(&self.moves) {
+                    for &m in moves {
+                        board.make_move_simple(m);
+                        board.unmake_move_base();
+                        operations += 1;
+                    }
+                }
+            }
+            Subsystem::See => {
+                for (board, moves) in self.boards.iter().zip(&self.moves) {
+                    for &m in moves {
+                        black_box(board.static_exchange_eval(m, 0));
+                        operations += 1;
+                    }
+                }
+            }
+        }
+        operations
+    }
+}
+
+/// Runs the microbenchmark of `subsystem` for about a second, and reports its throughput.
+pub fn run_microbench(subsystem: Subsystem) {
+    #![allow(clippy::cast_precision_loss)]
+    let mut bench = Microbench::new(subsystem);
+    // warm up the caches.
+    bench.run();
+    let mut operations = 0;
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(1) {
+        operations += bench.run();
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+    println!(
+        "{subsystem:<8} {operations:>10} ops in {elapsed:.3}s ({:.0} ops/s, {:.1} ns/op)",
+        operations as f64 / elapsed,
+        elapsed * 1e9 / operations as f64
+    );
+}
+
+mod tests {
+    #[test]
+    fn microbenchmarks_count_operations() {
+        use super::{Microbench, Subsystem, BENCH_POSITIONS};
+
+        let positions = BENCH_POSITIONS.len() as u64;
+        assert_eq!(Microbench::new(Subsystem::Movegen).run(), positions);
+        assert_eq!(Microbench::new(Subsystem::Eval).run(), positions);
+        let mut makemove = Microbench::new(Subsystem::MakeMove);
+        let moves = makemove.run();
+        assert!(moves > positions);
+        // making and unmaking every move leaves the positions as they were.
+        assert_eq!(makemove.run(), moves);
+        assert_eq!(Microbench::new(Subsystem::See).run(), moves);
+        for subsystem in Subsystem::ALL {
+            assert_eq!(subsystem.to_string().parse::<Subsystem>(), Ok(subsystem));
+        }
+    }
+}
@@ -9,7 +9,7 @@ use std::{
 };
 
 use rand::prelude::SliceRandom;
-use rand::rngs::ThreadRng;
+use rand::Rng;
 
 use crate::{
     board::movegen::{
@@ -17,7 +17,8 @@ use crate::{
         MoveList,
     },
     chessmove::Move,
-    errors::{FenParseError, MoveParseError},
+    cuckoo,
+    errors::{FenField, FenParseError, MoveParseError},
     historytable::ContHistIndex,
     makemove::{hash_castling, hash_ep, hash_piece, hash_side},
     nnue::network::{FeatureUpdate, MovedPiece, UpdateBuffer},
@@ -26,7 +27,7 @@ use crate::{
     squareset::{self, SquareSet},
     threadlocal::ThreadData,
     uci::CHESS960,
-    util::{CastlingRights, CheckState, File, Rank, Square, Undo, RAY_BETWEEN},
+    util::{CastlingRights, CheckState, File, Rank, Square, Undo, RAY_BETWEEN, RAY_FULL},
 };
 
 use self::movegen::{
@@ -53,6 +54,8 @@ pub struct Board {
 
     /// The Zobrist hash of the board.
     key: u64,
+    /// The Zobrist hash of the pawns on the board.
+    pawn_key: u64,
 
     /// Squares that the opponent attacks
     threats: Threats,
@@ -82,6 +85,7 @@ pub fn check_eq(lhs: &Board, rhs: &Board, msg: &str) {
     assert_eq!(lhs.fifty_move_counter, rhs.fifty_move_counter, "fifty_move_counter {msg}");
     assert_eq!(lhs.ply, rhs.ply, "ply {msg}");
     assert_eq!(lhs.key, rhs.key, "key {msg}");
+    assert_eq!(lhs.pawn_key, rhs.pawn_key, "pawn_key {msg}");
     assert_eq!(lhs.threats, rhs.threats, "threats {msg}");
     assert_eq!(lhs.height, rhs.height, "height {msg}");
     assert_eq!(lhs.history, rhs.history, "history {msg}");
@@ -97,6 +101,7 @@ impl Debug for Board {
             .field("height", &self.height)
             .field("ply", &self.ply)
             .field("key", &self.key)
+            .field("pawn_key", &self.pawn_key)
             .field("threats", &self.threats)
             .field("castle_perm", &self.castle_perm)
             .finish_non_exhaustive()
@@ -117,6 +122,7 @@ impl Board {
             height: 0,
             ply: 0,
             key: 0,
+            pawn_key: 0,
             threats: Threats::default(),
             castle_perm: CastlingRights::NONE,
             history: Vec::new(),
@@ -149,6 +155,10 @@ impl Board {
         self.key
     }
 
+    pub const fn pawn_key(&self) -> u64 {
+        self.pawn_key
+    }
+
     pub fn n_men(&self) -> u8 {
         #![allow(clippy::cast_possible_truncation)]
         self.pieces.occupied().count() as u8
@@ -184,6 +194,11 @@ impl Board {
         self.height
     }
 
+    /// The moves made since the root of the search, including any null moves.
+    pub fn line_from_root(&self) -> impl Iterator<Item = Move> + '_ {
+        self.history[self.history.len() - self.height..].iter().map(|undo| undo.mov)
+    }
+
     pub const fn turn(&self) -> Colour {
         self.side
     }
@@ -221,8 +236,20 @@ impl Board {
         key
     }
 
+    pub fn generate_pawn_key(&self) -> u64 {
+        let mut key = 0;
+        for colour in [Colour::WHITE, Colour::BLACK] {
+            let piece = Piece::new(colour, PieceType::PAWN);
+            for sq in self.pieces.all_pawns() & self.pieces.occupied_co(colour) {
+                hash_piece(&mut key, piece, sq);
+            }
+        }
+        key
+    }
+
     pub fn regenerate_zobrist(&mut self) {
         self.key = self.generate_pos_key();
+        self.pawn_key = self.generate_pawn_key();
     }
 
     pub fn regenerate_threats(&mut self) {
@@ -281,7 +308,23 @@ impl Board {
 
         checkers |= ortho_attacks & their_orthos;
 
-        Threats { all: threats, /* pawn: pawn_threats, minor: minor_threats, rook: rook_threats, */ checkers }
+        // compute pins, by looking through our own pieces from the king.
+        let mut pinned = SquareSet::EMPTY;
+        let their_pieces = self.pieces.occupied_co(C::COLOUR);
+        let pinners = (bishop_attacks(our_king, their_pieces) & their_diags)
+            | (rook_attacks(our_king, their_pieces) & their_orthos);
+        for pinner in pinners {
+            let between = RAY_BETWEEN[our_king.index()][pinner.index()] & blockers;
+            if between.count() == 1 && (between & their_pieces).is_empty() {
+                pinned |= between;
+            }
+        }
+
+        Threats {
+            all: threats,
+            /* pawn: pawn_threats, minor: minor_threats, rook: rook_threats, */ checkers,
+            pinned,
+        }
     }
 
     pub fn reset(&mut self) {
@@ -294,6 +337,7 @@ impl Board {
         self.ply = 0;
         self.castle_perm = CastlingRights::NONE;
         self.key = 0;
+        self.pawn_key = 0;
         self.threats = Threats::default();
         self.history.clear();
     }
@@ -332,6 +376,7 @@ impl Board {
             bq: Square::from_rank_file(Rank::RANK_8, queenside_file.try_into().unwrap()),
         };
         self.key = self.generate_pos_key();
+        self.pawn_key = self.generate_pawn_key();
         self.threats = self.generate_threats(self.side.flip());
     }
 
@@ -389,6 +434,7 @@ impl Board {
             bq: Square::from_rank_file(Rank::RANK_8, black_queenside_file.try_into().unwrap()),
         };
         self.key = self.generate_pos_key();
+        self.pawn_key = self.generate_pawn_key();
         self.threats = self.generate_threats(self.side.flip());
     }
 
@@ -467,73 +513,38 @@ impl Board {
         out
     }
 
+    /// Sets up the board from a FEN string, leaving it unchanged if the FEN is invalid
+    /// or describes an impossible position.
     pub fn set_from_fen(&mut self, fen: &str) -> Result<(), FenParseError> {
+        self.set_from_fen_sanitised(fen).map(|_| ())
+    }
+
+    /// As `set_from_fen`, but also returns a description of each castling right or
+    /// en passant square in the FEN that was dropped because the pieces don't allow it.
+    pub fn set_from_fen_sanitised(&mut self, fen: &str) -> Result<Vec<String>, FenParseError> {
         if !fen.is_ascii() {
-            return Err(format!("FEN string is not ASCII: {fen}"));
+            return Err(FenParseError::NotAscii);
         }
 
-        let mut rank = Rank::RANK_8;
-        let mut file = File::FILE_A;
-
-        self.reset();
-
-        let fen_chars = fen.as_bytes();
-        let split_idx =
-            fen_chars.iter().position(|&c| c == b' ').ok_or_else(|| format!("FEN string is missing space: {fen}"))?;
-        let (board_part, info_part) = fen_chars.split_at(split_idx);
-
-        for &c in board_part {
-            let mut count = 1;
-            let piece;
-            match c {
-                b'P' => piece = Piece::WP,
-                b'R' => piece = Piece::WR,
-                b'N' => piece = Piece::WN,
-                b'B' => piece = Piece::WB,
-                b'Q' => piece = Piece::WQ,
-                b'K' => piece = Piece::WK,
-                b'p' => piece = Piece::BP,
-                b'r' => piece = Piece::BR,
-                b'n' => piece = Piece::BN,
-                b'b' => piece = Piece::BB,
-                b'q' => piece = Piece::BQ,
-                b'k' => piece = Piece::BK,
-                b'1'..=b'8' => {
-                    piece = Piece::EMPTY;
-                    count = c - b'0';
-                }
-                b'/' => {
-                    rank -= 1;
-                    file = File::FILE_A;
-                    continue;
-                }
-                c => {
-                    return Err(format!("FEN string is invalid, got unexpected character: \"{}\"", c as char));
-                }
-            }
+        let mut board = Self::new();
+        let mut fields = fen.split_ascii_whitespace();
 
-            for _ in 0..count {
-                let sq = Square::from_rank_file(rank, file);
-                if piece != Piece::EMPTY {
-                    // this is only ever run once, as count is 1 for non-empty pieces.
-                    self.add_piece(sq, piece);
-                }
-                file += 1;
-            }
-        }
+        board.set_placement(fields.next())?;
+        board.set_side(fields.next())?;
+        board.set_castling(fields.next())?;
+        board.set_ep(fields.next())?;
+        board.set_halfmove(fields.next())?;
+        board.set_fullmove(fields.next())?;
 
-        let mut info_parts = info_part[1..].split(|&c| c == b' ');
+        board.check_legality().map_err(FenParseError::IllegalPosition)?;
+        let dropped = board.sanitise_rights();
 
-        self.set_side(info_parts.next())?;
-        self.set_castling(info_parts.next())?;
-        self.set_ep(info_parts.next())?;
-        self.set_halfmove(info_parts.next())?;
-        self.set_fullmove(info_parts.next())?;
+        board.key = board.generate_pos_key();
+        board.pawn_key = board.generate_pawn_key();
+        board.threats = board.generate_threats(board.side.flip());
 
-        self.key = self.generate_pos_key();
-        self.threats = self.generate_threats(self.side.flip());
-
-        Ok(())
+        *self = board;
+        Ok(dropped)
     }
 
     pub fn set_startpos(&mut self) {
@@ -541,7 +552,6 @@ impl Board {
         self.set_from_fen(starting_fen).expect("for some reason, STARTING_FEN is now broken.");
     }
 
-    #[cfg(test)]
     pub fn from_fen(fen: &str) -> Result<Self, FenParseError> {
         let mut out = Self::new();
         out.set_from_fen(fen)?;
@@ -569,36 +579,101 @@ impl Board {
         unsafe { String::from_utf8_unchecked(out) }
     }
 
-    fn set_side(&mut self, side_part: Option<&[u8]>) -> Result<(), FenParseError> {
-        self.side = match side_part {
-            Some([b'w']) => Colour::WHITE,
-            Some([b'b']) => Colour::BLACK,
-            Some(other) => {
-                return Err(format!(
-                    "FEN string is invalid, expected side to be 'w' or 'b', got \"{}\"",
-                    std::str::from_utf8(other).unwrap_or("<invalid utf8>")
-                ))
+    /// The same position with the colours swapped and the board flipped vertically,
+    /// such that the side to move is the other colour.
+    pub fn mirrored(&self) -> Self {
+        let swap_case = |s: &str| {
+            s.chars()
+                .map(|c| if c.is_ascii_uppercase() { c.to_ascii_lowercase() } else { c.to_ascii_uppercase() })
+                .collect::<String>()
+        };
+        let fen = self.fen();
+        let parts = fen.split(' ').collect::<Vec<_>>();
+        let board = parts[0].split('/').rev().collect::<Vec<_>>().join("/");
+        let side = if self.side == Colour::WHITE { "b" } else { "w" };
+        let ep = if self.ep_sq == Square::NO_SQUARE { "-".to_string() } else { self.ep_sq.flip_rank().to_string() };
+        let fen = format!("{} {side} {} {ep} {}", swap_case(&board), swap_case(parts[2]), parts[4..].join(" "));
+        Self::from_fen(&fen).expect("mirroring a valid position should give a valid position")
+    }
+
+    fn set_placement(&mut self, placement_part: Option<&str>) -> Result<(), FenParseError> {
+        let placement = placement_part.ok_or(FenParseError::MissingField(FenField::Placement))?;
+        let ranks = placement.split('/').collect::<Vec<_>>();
+        if ranks.len() != 8 {
+            return Err(FenParseError::invalid(FenField::Placement, placement, "eight ranks separated by '/'"));
+        }
+
+        for (rank, rank_text) in (Rank::RANK_1..=Rank::RANK_8).rev().zip(ranks) {
+            let mut file = File::FILE_A;
+            for c in rank_text.bytes() {
+                let (piece, count) = match c {
+                    b'P' => (Piece::WP, 1),
+                    b'R' => (Piece::WR, 1),
+                    b'N' => (Piece::WN, 1),
+                    b'B' => (Piece::WB, 1),
+                    b'Q' => (Piece::WQ, 1),
+                    b'K' => (Piece::WK, 1),
+                    b'p' => (Piece::BP, 1),
+                    b'r' => (Piece::BR, 1),
+                    b'n' => (Piece::BN, 1),
+                    b'b' => (Piece::BB, 1),
+                    b'q' => (Piece::BQ, 1),
+                    b'k' => (Piece::BK, 1),
+                    b'1'..=b'8' => (Piece::EMPTY, c - b'0'),
+                    _ => {
+                        return Err(FenParseError::invalid(
+                            FenField::Placement,
+                            (c as char).to_string(),
+                            "one of \"PNBRQKpnbrqk\", a digit from 1 to 8, or '/'",
+                        ))
+                    }
+                };
+                if file + count > 8 {
+                    return Err(FenParseError::invalid(FenField::Placement, rank_text, "eight squares in each rank"));
+                }
+                if piece != Piece::EMPTY {
+                    self.add_piece(Square::from_rank_file(rank, file), piece);
+                }
+                file += count;
+            }
+            if file != 8 {
+                return Err(FenParseError::invalid(FenField::Placement, rank_text, "eight squares in each rank"));
             }
-            None => return Err("FEN string is invalid, expected side part.".into()),
+        }
+
+        if self.pieces.king::<White>().count() != 1 || self.pieces.king::<Black>().count() != 1 {
+            return Err(FenParseError::invalid(FenField::Placement, placement, "exactly one king of each colour"));
+        }
+
+        Ok(())
+    }
+
+    fn set_side(&mut self, side_part: Option<&str>) -> Result<(), FenParseError> {
+        self.side = match side_part {
+            Some("w") => Colour::WHITE,
+            Some("b") => Colour::BLACK,
+            Some(other) => return Err(FenParseError::invalid(FenField::SideToMove, other, "\"w\" or \"b\"")),
+            None => return Err(FenParseError::MissingField(FenField::SideToMove)),
         };
         Ok(())
     }
 
-    fn set_castling(&mut self, castling_part: Option<&[u8]>) -> Result<(), FenParseError> {
+    fn set_castling(&mut self, castling_part: Option<&str>) -> Result<(), FenParseError> {
         match castling_part {
-            None => return Err("FEN string is invalid, expected castling part.".into()),
-            Some(b"-") => self.castle_perm = CastlingRights::NONE,
+            None => return Err(FenParseError::MissingField(FenField::Castling)),
+            Some("-") => self.castle_perm = CastlingRights::NONE,
             Some(castling) if !CHESS960.load(Ordering::SeqCst) => {
-                for &c in castling {
+                for c in castling.bytes() {
                     match c {
                         b'K' => self.castle_perm.wk = Square::H1,
                         b'Q' => self.castle_perm.wq = Square::A1,
                         b'k' => self.castle_perm.bk = Square::H8,
                         b'q' => self.castle_perm.bq = Square::A8,
                         _ => {
-                            return Err(format!(
-                                "FEN string is invalid, expected castling part to be of the form 'KQkq', got \"{}\"",
-                                std::str::from_utf8(castling).unwrap_or("<invalid utf8>")
+                            return Err(FenParseError::invalid(
+                                FenField::Castling,
+                                castling,
+                                "some of \"KQkq\" (or Shredder-FEN files with UCI_Chess960), or \"-\"",
                             ))
                         }
                     }
@@ -608,47 +683,48 @@ impl Board {
                 // valid shredder castling strings are of the form "AHah", "Bd"
                 let white_king = self.king_sq(Colour::WHITE);
                 let black_king = self.king_sq(Colour::BLACK);
-                if white_king.rank() != Rank::RANK_1 && shredder_castling.iter().any(u8::is_ascii_uppercase) {
-                    return Err(format!("FEN string is invalid, white king is not on the back rank, but got uppercase castling characters, implying present castling rights, got \"{}\"", std::str::from_utf8(shredder_castling).unwrap_or("<invalid utf8>")));
+                if white_king.rank() != Rank::RANK_1 && shredder_castling.bytes().any(|c| c.is_ascii_uppercase()) {
+                    return Err(FenParseError::invalid(
+                        FenField::Castling,
+                        shredder_castling,
+                        "no white castling rights, as the white king is not on the back rank",
+                    ));
                 }
-                if black_king.rank() != Rank::RANK_8 && shredder_castling.iter().any(u8::is_ascii_lowercase) {
-                    return Err(format!("FEN string is invalid, black king is not on the back rank, but got lowercase castling characters, implying present castling rights, got \"{}\"", std::str::from_utf8(shredder_castling).unwrap_or("<invalid utf8>")));
+                if black_king.rank() != Rank::RANK_8 && shredder_castling.bytes().any(|c| c.is_ascii_lowercase()) {
+                    return Err(FenParseError::invalid(
+                        FenField::Castling,
+                        shredder_castling,
+                        "no black castling rights, as the black king is not on the back rank",
+                    ));
                 }
-                for &c in shredder_castling {
-                    match c {
-                        c if c.is_ascii_uppercase() => {
-                            let file = c - b'A';
-                            let king_file = white_king.file();
-                            if file == king_file {
-                                return Err(format!("FEN string is invalid, white king is on file {}, but got castling rights on that file - got \"{}\"", king_file, std::str::from_utf8(shredder_castling).unwrap_or("<invalid utf8>")));
-                            }
-                            let sq = Square::from_rank_file(Rank::RANK_1, file);
-                            if file > king_file {
-                                // castling rights are to the right of the king, so it's "kingside" castling rights.
-                                self.castle_perm.wk = sq;
-                            } else {
-                                // castling rights are to the left of the king, so it's "queenside" castling rights.
-                                self.castle_perm.wq = sq;
-                            }
-                        }
-                        c if c.is_ascii_lowercase() => {
-                            let file = c - b'a';
-                            let king_file = black_king.file();
-                            if file == king_file {
-                                return Err(format!("FEN string is invalid, black king is on file {}, but got castling rights on that file - got \"{}\"", king_file, std::str::from_utf8(shredder_castling).unwrap_or("<invalid utf8>")));
-                            }
-                            let sq = Square::from_rank_file(Rank::RANK_8, file);
-                            if file > king_file {
-                                // castling rights are to the right of the king, so it's "kingside" castling rights.
-                                self.castle_perm.bk = sq;
-                            } else {
-                                // castling rights are to the left of the king, so it's "queenside" castling rights.
-                                self.castle_perm.bq = sq;
-                            }
-                        }
+                for c in shredder_castling.bytes() {
+                    let (colour, file) = match c {
+                        b'A'..=b'H' => (Colour::WHITE, c - b'A'),
+                        b'a'..=b'h' => (Colour::BLACK, c - b'a'),
                         _ => {
-                            return Err(format!("FEN string is invalid, expected castling part to be of the form 'AHah', 'Bd', or '-', got \"{}\"", std::str::from_utf8(shredder_castling).unwrap_or("<invalid utf8>")));
+                            return Err(FenParseError::invalid(
+                                FenField::Castling,
+                                shredder_castling,
+                                "rook files from A to H for white and a to h for black, such as \"AHah\" or \"Bd\", or \"-\"",
+                            ))
                         }
+                    };
+                    let king_file = self.king_sq(colour).file();
+                    if file == king_file {
+                        return Err(FenParseError::invalid(
+                            FenField::Castling,
+                            shredder_castling,
+                            "castling rights on the files of rooks, not of the king",
+                        ));
+                    }
+                    let rank = if colour == Colour::WHITE { Rank::RANK_1 } else { Rank::RANK_8 };
+                    let sq = Square::from_rank_file(rank, file);
+                    // castling rights to the right of the king are "kingside" castling rights.
+                    match (colour == Colour::WHITE, file > king_file) {
+                        (true, true) => self.castle_perm.wk = sq,
+                        (true, false) => self.castle_perm.wq = sq,
+                        (false, true) => self.castle_perm.bk = sq,
+                        (false, false) => self.castle_perm.bq = sq,
                     }
                 }
             }
@@ -657,64 +733,43 @@ impl Board {
         Ok(())
     }
 
-    fn set_ep(&mut self, ep_part: Option<&[u8]>) -> Result<(), FenParseError> {
+    fn set_ep(&mut self, ep_part: Option<&str>) -> Result<(), FenParseError> {
         match ep_part {
-            None => return Err("FEN string is invalid, expected en passant part.".to_string()),
-            Some([b'-']) => self.ep_sq = Square::NO_SQUARE,
+            None => return Err(FenParseError::MissingField(FenField::EnPassant)),
+            Some("-") => self.ep_sq = Square::NO_SQUARE,
             Some(ep_sq) => {
-                if ep_sq.len() != 2 {
-                    return Err(format!(
-                        "FEN string is invalid, expected en passant part to be of the form 'a1', got \"{}\"",
-                        std::str::from_utf8(ep_sq).unwrap_or("<invalid utf8>")
-                    ));
-                }
-                let file = ep_sq[0] - b'a';
-                let rank = ep_sq[1] - b'1';
-                if !((File::FILE_A..=File::FILE_H).contains(&file) && (Rank::RANK_1..=Rank::RANK_8).contains(&rank)) {
-                    return Err(format!(
-                        "FEN string is invalid, expected en passant part to be of the form 'a1', got \"{}\"",
-                        std::str::from_utf8(ep_sq).unwrap_or("<invalid utf8>")
+                let &[file @ b'a'..=b'h', rank @ b'1'..=b'8'] = ep_sq.as_bytes() else {
+                    return Err(FenParseError::invalid(
+                        FenField::EnPassant,
+                        ep_sq,
+                        "a square such as \"e3\", or \"-\"",
                     ));
-                }
-                self.ep_sq = Square::from_rank_file(rank, file);
+                };
+                self.ep_sq = Square::from_rank_file(rank - b'1', file - b'a');
             }
         }
 
         Ok(())
     }
 
-    fn set_halfmove(&mut self, halfmove_part: Option<&[u8]>) -> Result<(), FenParseError> {
-        match halfmove_part {
-            None => return Err("FEN string is invalid, expected halfmove clock part.".to_string()),
-            Some(halfmove_clock) => {
-                self.fifty_move_counter = std::str::from_utf8(halfmove_clock)
-                    .map_err(|_| "FEN string is invalid, expected halfmove clock part to be valid UTF-8")?
-                    .parse::<u8>()
-                    .map_err(|err| {
-                        format!(
-                            "FEN string is invalid, expected halfmove clock part to be a number, got \"{}\", {err}",
-                            std::str::from_utf8(halfmove_clock).unwrap_or("<invalid utf8>")
-                        )
-                    })?;
-            }
-        }
+    fn set_halfmove(&mut self, halfmove_part: Option<&str>) -> Result<(), FenParseError> {
+        let halfmove_clock = halfmove_part.ok_or(FenParseError::MissingField(FenField::HalfmoveClock))?;
+        self.fifty_move_counter = halfmove_clock.parse().map_err(|_| {
+            FenParseError::invalid(FenField::HalfmoveClock, halfmove_clock, "a number of half moves from 0 to 255")
+        })?;
 
         Ok(())
     }
 
-    fn set_fullmove(&mut self, fullmove_part: Option<&[u8]>) -> Result<(), FenParseError> {
-        match fullmove_part {
-            None => return Err("FEN string is invalid, expected fullmove number part.".to_string()),
-            Some(fullmove_number) => {
-                let fullmove_number = std::str::from_utf8(fullmove_number)
-                    .map_err(|_| "FEN string is invalid, expected fullmove number part to be valid UTF-8")?
-                    .parse::<usize>()
-                    .map_err(|_| "FEN string is invalid, expected fullmove number part to be a number")?;
-                self.ply = (fullmove_number - 1) * 2;
-                if self.side == Colour::BLACK {
-                    self.ply += 1;
-                }
-            }
+    fn set_fullmove(&mut self, fullmove_part: Option<&str>) -> Result<(), FenParseError> {
+        let fullmove_number = fullmove_part.ok_or(FenParseError::MissingField(FenField::FullmoveNumber))?;
+        let fullmove_number = fullmove_number.parse::<usize>().map_err(|_| {
+            FenParseError::invalid(FenField::FullmoveNumber, fullmove_number, "a move number, starting from 1")
+        })?;
+        // some programs write 0 for the first move, which is harmless enough to accept.
+        self.ply = fullmove_number.saturating_sub(1) * 2;
+        if self.side == Colour::BLACK {
+            self.ply += 1;
         }
 
         Ok(())
@@ -910,6 +965,53 @@ impl Board {
             && !self.any_attacked(king_path | m.from().as_set(), self.side.flip())
     }
 
+    /// Checks whether a pseudo-legal move is legal, i.e. that it doesn't leave our king in check.
+    pub fn is_legal(&self, m: Move) -> bool {
+        let from = m.from();
+        let to = m.to();
+        let king_sq = self.king_sq(self.side);
+        let their_pieces = self.pieces.occupied_co(self.side.flip());
+
+        if m.is_castle() {
+            // the squares that the king passes through are checked for attacks
+            // when castling is generated, which leaves the destination square.
+            // this needs looking at without the castling rook, as in FRC the
+            // rook can shield the destination from a rook or queen behind it.
+            let king_dst = if to > from { Square::G1 } else { Square::C1 }.relative_to(self.side);
+            let occupied = self.pieces.occupied() ^ from.as_set() ^ to.as_set();
+            return (self.pieces.all_attackers_to_sq(king_dst, occupied) & their_pieces).is_empty();
+        }
+
+        if from == king_sq {
+            // sliders that are checking the king can see through it.
+            let occupied = self.pieces.occupied() ^ from.as_set();
+            return (self.pieces.all_attackers_to_sq(to, occupied) & their_pieces).is_empty();
+        }
+
+        if m.is_ep() {
+            // en passant removes two pieces from a line at once, so we just look
+            // at the position after the capture.
+            let captured = to.pawn_push(self.side.flip()).as_set();
+            let occupied = (self.pieces.occupied() ^ from.as_set() ^ captured) | to.as_set();
+            return (self.pieces.all_attackers_to_sq(king_sq, occupied) & their_pieces & !captured).is_empty();
+        }
+
+        if self.threats.checkers.count() > 1 {
+            // in double-check, only the king can move.
+            return false;
+        }
+        if self.in_check() {
+            // we have to capture the checker, or block the check.
+            let checker = self.threats.checkers.first();
+            if !(RAY_BETWEEN[king_sq.index()][checker.index()] | self.threats.checkers).contains_square(to) {
+                return false;
+            }
+        }
+
+        // pinned pieces can only move along the line of the pin.
+        !self.threats.pinned.contains_square(from) || RAY_FULL[king_sq.index()][from.index()].contains_square(to)
+    }
+
     pub fn any_attacked(&self, squares: SquareSet, by: Colour) -> bool {
         if by == self.side.flip() {
             (squares & self.threats.all).non_empty()
@@ -975,6 +1077,24 @@ impl Board {
         m.is_promo() || m.is_ep() || self.is_capture(m)
     }
 
+    /// Determines whether this move would recapture on the square where the opponent just captured.
+    pub fn is_recapture(&self, m: Move) -> bool {
+        let Some(Undo { cont_hist_index, captured, .. }) = self.history.last() else {
+            return false;
+        };
+        // a null move captures nothing, so this is always false after a null move.
+        cont_hist_index.square == m.to() && *captured != Piece::EMPTY && self.is_capture(m)
+    }
+
+    /// Determines whether this move would push a passed pawn to the sixth or seventh rank
+    /// (relative to the side to move). Captures are not counted as pushes.
+    pub fn is_passed_pawn_push(&self, m: Move) -> bool {
+        self.moved_piece(m).piece_type() == PieceType::PAWN
+            && !self.is_capture(m)
+            && matches!(m.to().relative_to(self.side).rank(), Rank::RANK_6 | Rank::RANK_7)
+            && self.is_passed_pawn(m.to(), self.side)
+    }
+
     /// Gets the piece at the given square.
     pub fn piece_at(&self, sq: Square) -> Piece {
         debug_assert!(sq.on_board());
@@ -996,6 +1116,10 @@ impl Board {
         #[cfg(debug_assertions)]
         self.check_validity().unwrap();
 
+        if !self.is_legal(m) {
+            return false;
+        }
+
         let from = m.from();
         let mut to = m.to();
         let side = self.side;
@@ -1008,9 +1132,10 @@ impl Board {
             fifty_move_counter: self.fifty_move_counter,
             threats: self.threats,
             cont_hist_index: ContHistIndex { piece, square: m.history_to_square() },
-            bitboard: self.pieces,
-            piece_array: self.piece_array,
+            captured,
             key: self.key,
+            pawn_key: self.pawn_key,
+            mov: m,
         };
 
         // from, to, and piece are valid unless this is a castling move,
@@ -1108,27 +1233,8 @@ impl Board {
 
         self.side = self.side.flip();
 
-        // reversed in_check fn, as we have now swapped sides
-        if self.sq_attacked(self.king_sq(self.side.flip()), self.side) {
-            // this would be a function but we run into borrow checker issues
-            // because it's currently not smart enough to realize that we're
-            // borrowing disjoint parts of the board.
-            let Undo { ep_square, fifty_move_counter, bitboard, .. } = saved_state;
-
-            // self.height -= 1;
-            // self.ply -= 1;
-            self.side = self.side.flip();
-            // self.key = key;
-            // self.castle_perm = castle_perm;
-            self.ep_sq = ep_square;
-            self.fifty_move_counter = fifty_move_counter;
-            // self.threats = threats;
-            self.pieces = bitboard;
-            // self.piece_array = piece_array;
-            return false;
-        }
-
         let mut key = self.key;
+        let mut pawn_key = self.pawn_key;
 
         // remove a previous en passant square from the hash
         if saved_state.ep_square != Square::NO_SQUARE {
@@ -1170,14 +1276,21 @@ impl Board {
         for &FeatureUpdate { sq, piece } in update_buffer.subs() {
             self.piece_array[sq.index()] = Piece::EMPTY;
             hash_piece(&mut key, piece, sq);
+            if piece.piece_type() == PieceType::PAWN {
+                hash_piece(&mut pawn_key, piece, sq);
+            }
         }
         for &FeatureUpdate { sq, piece } in update_buffer.adds() {
             self.piece_array[sq.index()] = piece;
             hash_piece(&mut key, piece, sq);
+            if piece.piece_type() == PieceType::PAWN {
+                hash_piece(&mut pawn_key, piece, sq);
+            }
         }
         // reinsert the castling rights
         hash_castling(&mut key, self.castle_perm);
         self.key = key;
+        self.pawn_key = pawn_key;
 
         self.ply += 1;
         self.height += 1;
@@ -1186,40 +1299,90 @@ impl Board {
 
         self.history.push(saved_state);
 
-        #[cfg(debug_assertions)]
-        self.check_validity().unwrap();
+        #[cfg(any(debug_assertions, feature = "verify"))]
+        self.verify_incremental_state();
 
         true
     }
 
     pub fn unmake_move_base(&mut self) {
-        // we remove this check because the board actually *can*
-        // be in an inconsistent state when we call this, as we
-        // may be unmaking a move that was determined to be
-        // illegal, and as such the full make_move hasn't been
-        // run yet.
-        // #[cfg(debug_assertions)]
-        // self.check_validity().unwrap();
+        #[cfg(debug_assertions)]
+        self.check_validity().unwrap();
 
-        let undo = self.history.last().expect("No move to unmake!");
+        let undo = self.history.pop().expect("No move to unmake!");
+        self.restore(&undo);
+        self.unmake_pieces(undo.mov, undo.captured);
 
-        let Undo { castle_perm, ep_square, fifty_move_counter, threats, bitboard, piece_array, key, .. } = undo;
+        #[cfg(debug_assertions)]
+        self.check_validity().unwrap();
+    }
 
+    /// Restores the irreversible state saved before a move (or null move) was made.
+    const fn restore(&mut self, undo: &Undo) {
         self.height -= 1;
         self.ply -= 1;
         self.side = self.side.flip();
-        self.key = *key;
-        self.castle_perm = *castle_perm;
-        self.ep_sq = *ep_square;
-        self.fifty_move_counter = *fifty_move_counter;
-        self.threats = *threats;
-        self.pieces = *bitboard;
-        self.piece_array = *piece_array;
+        self.key = undo.key;
+        self.pawn_key = undo.pawn_key;
+        self.castle_perm = undo.castle_perm;
+        self.ep_sq = undo.ep_square;
+        self.fifty_move_counter = undo.fifty_move_counter;
+        self.threats = undo.threats;
+    }
 
-        self.history.pop();
+    /// Moves the pieces back to where they were before `m` was made.
+    /// This must be called after the castling rights and side to move have been restored.
+    fn unmake_pieces(&mut self, m: Move, captured: Piece) {
+        let side = self.side;
+        let from = m.from();
+        let to = m.to();
+        if m.is_castle() {
+            // castling is encoded as king-captures-rook, so `to` is where the rook started.
+            let (king_to, rook_to) = match to {
+                _ if to == self.castle_perm.wk => (Square::G1, Square::F1),
+                _ if to == self.castle_perm.wq => (Square::C1, Square::D1),
+                _ if to == self.castle_perm.bk => (Square::G8, Square::F8),
+                _ if to == self.castle_perm.bq => (Square::C8, Square::D8),
+                _ => panic!("Invalid castle move, to: {}, castle_perm: {}", to, self.castle_perm),
+            };
+            let king = Piece::new(side, PieceType::KING);
+            let rook = Piece::new(side, PieceType::ROOK);
+            // in chess960 the king and rook can land on each other's starting squares,
+            // so both are lifted off the board before either is put back.
+            self.pieces.clear_piece_at(king_to, king);
+            self.pieces.clear_piece_at(rook_to, rook);
+            self.piece_array[king_to.index()] = Piece::EMPTY;
+            self.piece_array[rook_to.index()] = Piece::EMPTY;
+            self.pieces.set_piece_at(from, king);
+            self.pieces.set_piece_at(to, rook);
+            self.piece_array[from.index()] = king;
+            self.piece_array[to.index()] = rook;
+            return;
+        }
+
+        let piece = if m.is_promo() {
+            let promo = Piece::new(side, m.promotion_type());
+            self.pieces.clear_piece_at(to, promo);
+            let pawn = Piece::new(side, PieceType::PAWN);
+            self.pieces.set_piece_at(from, pawn);
+            pawn
+        } else {
+            let piece = self.piece_array[to.index()];
+            self.pieces.move_piece(to, from, piece);
+            piece
+        };
+        self.piece_array[from.index()] = piece;
+        self.piece_array[to.index()] = captured;
+        if captured != Piece::EMPTY {
+            self.pieces.set_piece_at(to, captured);
+        }
 
-        #[cfg(debug_assertions)]
-        self.check_validity().unwrap();
+        if m.is_ep() {
+            let clear_at = if side == Colour::WHITE { to.sub(8) } else { to.add(8) };
+            let pawn = Piece::new(side.flip(), PieceType::PAWN);
+            self.pieces.set_piece_at(clear_at, pawn);
+            self.piece_array[clear_at.index()] = pawn;
+        }
     }
 
     pub fn make_nullmove(&mut self) {
@@ -1227,7 +1390,15 @@ impl Board {
         self.check_validity().unwrap();
         debug_assert!(!self.in_check());
 
-        self.history.push(Undo { ep_square: self.ep_sq, threats: self.threats, key: self.key, ..Default::default() });
+        self.history.push(Undo {
+            castle_perm: self.castle_perm,
+            ep_square: self.ep_sq,
+            fifty_move_counter: self.fifty_move_counter,
+            threats: self.threats,
+            key: self.key,
+            pawn_key: self.pawn_key,
+            ..Default::default()
+        });
 
         let mut key = self.key;
         if self.ep_sq != Square::NO_SQUARE {
@@ -1243,25 +1414,16 @@ impl Board {
 
         self.threats = self.generate_threats(self.side.flip());
 
-        #[cfg(debug_assertions)]
-        self.check_validity().unwrap();
+        #[cfg(any(debug_assertions, feature = "verify"))]
+        self.verify_incremental_state();
     }
 
     pub fn unmake_nullmove(&mut self) {
         #[cfg(debug_assertions)]
         self.check_validity().unwrap();
 
-        self.height -= 1;
-        self.ply -= 1;
-        self.side = self.side.flip();
-
-        let Undo { ep_square, threats, key, .. } = self.history.last().expect("No move to unmake!");
-
-        self.ep_sq = *ep_square;
-        self.threats = *threats;
-        self.key = *key;
-
-        self.history.pop();
+        let undo = self.history.pop().expect("No move to unmake!");
+        self.restore(&undo);
 
         #[cfg(debug_assertions)]
         self.check_validity().unwrap();
@@ -1298,12 +1460,14 @@ impl Board {
         self.unmake_move_nnue(t);
     }
 
+    /// The last move made on the board, or the null move if there isn't one.
+    #[cfg(feature = "treedump")]
+    pub fn last_move(&self) -> Move {
+        self.history.last().map_or(Move::NULL, |undo| undo.mov)
+    }
+
     pub fn last_move_was_nullmove(&self) -> bool {
-        if let Some(Undo { bitboard, .. }) = self.history.last() {
-            bitboard.all_kings().is_empty()
-        } else {
-            false
-        }
+        self.history.last().is_some_and(|undo| undo.mov.is_null())
     }
 
     /// Makes a guess about the new position key after a move.
@@ -1391,6 +1555,86 @@ impl Board {
         res
     }
 
+    /// Parses a move in Standard Algebraic Notation (e.g. `Nf3`, `exd8=Q+`, `O-O`)
+    /// and returns a move or a reason why it couldn't be parsed.
+    pub fn parse_san(&self, san: &str) -> Result<Move, MoveParseError> {
+        use crate::errors::MoveParseError::{
+            AmbiguousMove, IllegalMove, InvalidLength, InvalidPromotionPiece, InvalidToSquareFile, InvalidToSquareRank,
+        };
+        let body = san.trim_end_matches(['+', '#', '!', '?']);
+
+        let castle_side = match body {
+            "O-O" | "0-0" => Some(true),
+            "O-O-O" | "0-0-0" => Some(false),
+            _ => None,
+        };
+        if let Some(kingside) = castle_side {
+            return self
+                .legal_moves()
+                .into_iter()
+                .find(|m| m.is_castle() && (m.to() > m.from()) == kingside)
+                .ok_or_else(|| IllegalMove(san.to_string()));
+        }
+
+        let (piece_type, rest) = match body.as_bytes().first() {
+            Some(b'N') => (PieceType::KNIGHT, &body[1..]),
+            Some(b'B') => (PieceType::BISHOP, &body[1..]),
+            Some(b'R') => (PieceType::ROOK, &body[1..]),
+            Some(b'Q') => (PieceType::QUEEN, &body[1..]),
+            Some(b'K') => (PieceType::KING, &body[1..]),
+            _ => (PieceType::PAWN, body),
+        };
+
+        // split off the promotion piece, which may or may not be preceded by '='.
+        let (rest, promotion) = match rest.as_bytes() {
+            [.., b'=', p] | [.., b'1'..=b'8', p @ (b'N' | b'B' | b'R' | b'Q')] if piece_type == PieceType::PAWN => {
+                let promotion = match p.to_ascii_uppercase() {
+                    b'N' => PieceType::KNIGHT,
+                    b'B' => PieceType::BISHOP,
+                    b'R' => PieceType::ROOK,
+                    b'Q' => PieceType::QUEEN,
+                    _ => return Err(InvalidPromotionPiece(*p as char)),
+                };
+                (rest[..rest.len() - 1].trim_end_matches('='), promotion)
+            }
+            _ => (rest, PieceType::NONE),
+        };
+
+        let squares = rest.bytes().filter(|&c| c != b'x' && c != b'-' && c != b':').collect::<Vec<_>>();
+        if !(2..=4).contains(&squares.len()) {
+            return Err(InvalidLength(san.len()));
+        }
+        let (disambiguation, to) = squares.split_at(squares.len() - 2);
+        if !(b'a'..=b'h').contains(&to[0]) {
+            return Err(InvalidToSquareFile(to[0] as char));
+        }
+        if !(b'1'..=b'8').contains(&to[1]) {
+            return Err(InvalidToSquareRank(to[1] as char));
+        }
+        let to = Square::from_rank_file(to[1] - b'1', to[0] - b'a');
+        let from_file = disambiguation.iter().find(|c| (b'a'..=b'h').contains(c)).map(|c| c - b'a');
+        let from_rank = disambiguation.iter().find(|c| (b'1'..=b'8').contains(c)).map(|c| c - b'1');
+
+        let mut candidates = self.legal_moves().into_iter().filter(|&m| {
+            !m.is_castle()
+                && m.to() == to
+                && self.piece_at(m.from()).piece_type() == piece_type
+                && m.safe_promotion_type() == promotion
+                && from_file.is_none_or(|f| m.from().file() == f)
+                && from_rank.is_none_or(|r| m.from().rank() == r)
+        });
+        match (candidates.next(), candidates.next()) {
+            (Some(m), None) => Ok(m),
+            (Some(_), Some(_)) => Err(AmbiguousMove(san.to_string())),
+            (None, _) => Err(IllegalMove(san.to_string())),
+        }
+    }
+
+    /// Parses a move in either UCI or Standard Algebraic Notation.
+    pub fn parse_move(&self, text: &str) -> Result<Move, MoveParseError> {
+        self.parse_uci(text).or_else(|_| self.parse_san(text))
+    }
+
     pub fn san(&mut self, m: Move) -> Option<String> {
         let check_char = match self.gives(m) {
             CheckState::None => "",
@@ -1498,15 +1742,57 @@ impl Board {
         false
     }
 
+    /// Determines whether the side to move has a single reversible move that reaches
+    /// a position that has already occurred within the search tree, so that the
+    /// position is at worst a draw. See the `cuckoo` module for details.
+    pub fn has_game_cycle(&self, height: usize) -> bool {
+        let mut end = min!(usize::from(self.fifty_move_counter), self.history.len());
+        // positions from before a null move aren't really connected to this one.
+        for (i, undo) in self.history.iter().rev().take(end).enumerate() {
+            if undo.mov.is_null() {
+                end = i;
+                break;
+            }
+        }
+        if end < 3 {
+            return false;
+        }
+
+        let occupied = self.pieces.occupied();
+        // only positions with the other side to move can be reached in one move.
+        for i in (3..=end).step_by(2) {
+            // cycles that cross the root are handled by the normal repetition check.
+            if i >= height {
+                break;
+            }
+            let prior_key = self.history[self.history.len() - i].key;
+            if let Some((from, to)) = cuckoo::lookup(self.key ^ prior_key) {
+                if (RAY_BETWEEN[from.index()][to.index()] & occupied).is_empty() {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
     /// Should we consider the current position a draw?
     pub fn is_draw(&self) -> bool {
-        (self.fifty_move_counter >= 100 || self.is_repetition()) && self.height != 0
+        (self.fifty_move_counter >= 100
+            || self.is_repetition()
+            || !self.pieces.any_pawns() && self.is_insufficient_material())
+            && self.height != 0
     }
 
+    /// Formats a principal variation in SAN, with move numbers, by playing it out on the board.
     pub fn pv_san(&mut self, pv: &PVariation) -> Result<String, fmt::Error> {
         let mut out = String::new();
         let mut moves_made = 0;
         for &m in pv.moves() {
+            if self.turn() == Colour::WHITE {
+                write!(out, "{}. ", self.full_move_number())?;
+            } else if moves_made == 0 {
+                write!(out, "{}... ", self.full_move_number())?;
+            }
             write!(out, "{} ", self.san(m).unwrap_or_else(|| "???".to_string()))?;
             self.make_move_simple(m);
             moves_made += 1;
@@ -1517,17 +1803,10 @@ impl Board {
         Ok(out)
     }
 
-    pub fn legal_moves(&mut self) -> Vec<Move> {
+    pub fn legal_moves(&self) -> Vec<Move> {
         let mut move_list = MoveList::new();
         self.generate_moves(&mut move_list);
-        let mut legal_moves = Vec::new();
-        for &m in move_list.iter_moves() {
-            if self.make_move_simple(m) {
-                self.unmake_move_base();
-                legal_moves.push(m);
-            }
-        }
-        legal_moves
+        move_list.iter_moves().copied().collect()
     }
 
     pub const fn fifty_move_counter(&self) -> u8 {
@@ -1644,7 +1923,7 @@ impl Board {
     }
 
     #[allow(dead_code /* for datagen */)]
-    pub fn make_random_move(&mut self, rng: &mut ThreadRng, t: &mut ThreadData) -> Option<Move> {
+    pub fn make_random_move(&mut self, rng: &mut impl Rng, t: &mut ThreadData) -> Option<Move> {
         let mut ml = MoveList::new();
         self.generate_moves(&mut ml);
         let MoveListEntry { mov, .. } = ml.choose(rng)?;
@@ -1652,7 +1931,7 @@ impl Board {
         Some(*mov)
     }
 
-    #[allow(dead_code /* for datagen */)]
+    /// Determines whether neither side has enough material to deliver checkmate.
     pub fn is_insufficient_material(&self) -> bool {
         self.has_insufficient_material::<White>() && self.has_insufficient_material::<Black>()
     }
@@ -1793,6 +2072,118 @@ mod tests {
         assert_eq!(board_1, board_2);
     }
 
+    #[test]
+    fn malformed_fens_are_rejected() {
+        use super::Board;
+        use crate::errors::{FenField, FenParseError};
+
+        let field_of = |fen: &str| match Board::from_fen(fen) {
+            Err(FenParseError::InvalidField { field, .. } | FenParseError::MissingField(field)) => Some(field),
+            _ => None,
+        };
+        assert_eq!(field_of("8/8/8/8/8/8/8/8/8/k7/K7 w - - 0 1"), Some(FenField::Placement));
+        assert_eq!(field_of("rnbqkbnr/pppppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"), Some(FenField::Placement));
+        assert_eq!(field_of("4k3/8/8/8/8/8/8/4K2 w - - 0 1"), Some(FenField::Placement));
+        assert_eq!(field_of("4k3/8/8/8/8/8/8/8 w - - 0 1"), Some(FenField::Placement));
+        assert_eq!(field_of("4k3/8/8/8/8/8/8/4K3 x - - 0 1"), Some(FenField::SideToMove));
+        assert_eq!(field_of("4k3/8/8/8/8/8/8/4K3 w Z - 0 1"), Some(FenField::Castling));
+        assert_eq!(field_of("4k3/8/8/8/8/8/8/4K3 w - !3 0 1"), Some(FenField::EnPassant));
+        assert_eq!(field_of("4k3/8/8/8/8/8/8/4K3 w - - 300 1"), Some(FenField::HalfmoveClock));
+        assert_eq!(field_of("4k3/8/8/8/8/8/8/4K3 w - -"), Some(FenField::HalfmoveClock));
+        assert_eq!(Board::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 0").unwrap().full_move_number(), 1);
+
+        let mut board = Board::default();
+        let err = board.set_from_fen("4k3/8/8/8/8/8/8/4K3 w - e9 0 1").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid en passant square (field 4) in FEN at \"e9\", expected a square such as \"e3\", or \"-\""
+        );
+        assert_eq!(board, Board::default());
+    }
+
+    #[test]
+    fn impossible_positions_are_rejected() {
+        use super::Board;
+        use crate::{errors::FenParseError, errors::IllegalPositionError, piece::Colour, util::Square};
+
+        let error_of = |fen: &str| match Board::from_fen(fen) {
+            Err(FenParseError::IllegalPosition(e)) => Some(e),
+            _ => None,
+        };
+        assert_eq!(error_of("4k3/8/8/8/8/8/8/P3K3 w - - 0 1"), Some(IllegalPositionError::PawnOnBackRank(Square::A1)));
+        assert_eq!(
+            error_of("4k3/8/8/8/8/P7/PPPPPPPP/4K3 w - - 0 1"),
+            Some(IllegalPositionError::TooManyPawns(Colour::WHITE))
+        );
+        assert_eq!(
+            error_of("4k3/8/8/8/8/QQQ5/1PPPPPPP/4K3 w - - 0 1"),
+            Some(IllegalPositionError::TooManyPromotedPieces(Colour::WHITE))
+        );
+        assert_eq!(error_of("4k3/8/8/8/8/8/8/4K2R w - - 0 1"), None);
+        assert_eq!(error_of("4k3/8/8/8/8/8/8/4R1K1 w - - 0 1"), Some(IllegalPositionError::OpponentInCheck));
+        assert_eq!(error_of("4k3/8/8/8/8/4p3/8/4K3 w - e3 0 1"), Some(IllegalPositionError::EnPassantRank(Square::E3)));
+
+        // rights that the pieces don't support are dropped rather than rejected.
+        let mut board = Board::new();
+        let dropped = board.set_from_fen_sanitised("r3k3/8/8/8/8/8/8/4K2R w KQkq - 0 1").unwrap();
+        assert_eq!(dropped.len(), 2);
+        assert_eq!(board.fen(), "r3k3/8/8/8/8/8/8/4K2R w Kq - 0 1");
+        let dropped = board.set_from_fen_sanitised("4k3/8/8/8/8/8/8/4K3 b - e3 0 1").unwrap();
+        assert_eq!(dropped.len(), 1);
+        // an en passant square with no pawn to take on it is dropped silently.
+        let dropped =
+            board.set_from_fen_sanitised("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1").unwrap();
+        assert!(dropped.is_empty());
+        assert_eq!(board.fen(), "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1");
+    }
+
+    #[test]
+    fn parse_san_moves() {
+        use super::Board;
+        use crate::errors::MoveParseError;
+        use crate::{chessmove::Move, piece::PieceType, util::Square};
+
+        let board = Board::default();
+        assert_eq!(board.parse_san("Nf3"), Ok(Move::new(Square::G1, Square::F3)));
+        assert_eq!(board.parse_san("e4"), Ok(Move::new(Square::E2, Square::E4)));
+        assert_eq!(board.parse_move("e2e4"), board.parse_move("e4"));
+        assert!(matches!(board.parse_san("Nf6"), Err(MoveParseError::IllegalMove(_))));
+
+        // both knights can reach d2.
+        let board = Board::from_fen("6k1/3P4/8/8/8/5N2/8/RN2K2R w K - 0 1").unwrap();
+        assert!(matches!(board.parse_san("Nd2"), Err(MoveParseError::AmbiguousMove(_))));
+        assert_eq!(board.parse_san("Nbd2"), Ok(Move::new(Square::B1, Square::D2)));
+        assert_eq!(board.parse_san("N3d2"), Ok(Move::new(Square::F3, Square::D2)));
+        assert_eq!(board.parse_san("Nf3d2"), Ok(Move::new(Square::F3, Square::D2)));
+        assert_eq!(board.parse_san("Nbf3"), Err(MoveParseError::IllegalMove("Nbf3".into())));
+        assert!(board.parse_san("O-O").unwrap().is_castle());
+        assert!(board.parse_san("0-0-0").is_err());
+        let promo = board.parse_san("d8=Q+").unwrap();
+        assert_eq!(promo.safe_promotion_type(), PieceType::QUEEN);
+        assert_eq!(board.parse_san("d8N"), Ok(Move::new_with_promo(Square::D7, Square::D8, PieceType::KNIGHT)));
+    }
+
+    #[test]
+    fn pv_san_has_move_numbers() {
+        use super::Board;
+        use crate::search::pv::PVariation;
+
+        let mut board = Board::from_fen("rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2").unwrap();
+        let mut pv = PVariation::default();
+        for san in ["Nf3", "Nc6", "Bb5"] {
+            let m = board.parse_san(san).unwrap();
+            pv.moves.push(m);
+            board.make_move_simple(m);
+        }
+        for _ in 0..3 {
+            board.unmake_move_base();
+        }
+        assert_eq!(board.pv_san(&pv).unwrap(), "2. Nf3 Nc6 3. Bb5 ");
+        board.make_move_simple(pv.moves[0]);
+        pv.moves.remove(0);
+        assert_eq!(board.pv_san(&pv).unwrap(), "2... Nc6 3. Bb5 ");
+    }
+
     #[test]
     fn game_end_states() {
         use super::Board;
@@ -1904,6 +2295,22 @@ mod tests {
         assert_eq!(board.threats.all, SquareSet::from_inner(0xfcfa_bbbd_6ab9_2a28));
     }
 
+    #[test]
+    fn pawn_key_only_tracks_pawns() {
+        use super::Board;
+        use crate::chessmove::Move;
+        use crate::util::Square;
+        let mut board = Board::default();
+        let start = board.pawn_key;
+        board.make_move_simple(Move::new(Square::G1, Square::F3));
+        assert_eq!(board.pawn_key, start);
+        board.make_move_simple(Move::new(Square::E7, Square::E5));
+        assert_ne!(board.pawn_key, start);
+        assert_eq!(board.pawn_key, board.generate_pawn_key());
+        board.unmake_move_base();
+        assert_eq!(board.pawn_key, start);
+    }
+
     #[test]
     fn key_after_works_for_simple_moves() {
         use super::Board;
@@ -1980,4 +2387,177 @@ mod tests {
         assert!(board.make_move_simple(Move::new(Square::B7, Square::B5)));
         assert_eq!(board.ep_sq, Square::B6);
     }
+
+    #[test]
+    fn insufficient_material_is_drawn_in_search() {
+        use super::Board;
+        use crate::chessmove::Move;
+        use crate::util::Square;
+        let mut kbk = Board::from_fen("8/8/8/4k3/8/8/2B5/4K3 w - - 0 1").unwrap();
+        assert!(kbk.make_move_simple(Move::new(Square::E1, Square::E2)));
+        assert!(kbk.is_draw());
+        let mut kbkb_opposite = Board::from_fen("8/8/3b4/4k3/8/8/2B5/4K3 w - - 0 1").unwrap();
+        assert!(kbkb_opposite.make_move_simple(Move::new(Square::E1, Square::E2)));
+        assert!(!kbkb_opposite.is_draw());
+        let mut kbkb_same = Board::from_fen("8/8/4b3/4k3/8/8/2B5/4K3 w - - 0 1").unwrap();
+        assert!(kbkb_same.make_move_simple(Move::new(Square::E1, Square::E2)));
+        assert!(kbkb_same.is_draw());
+    }
+
+    #[test]
+    fn ocb_endgame_detection() {
+        use super::Board;
+        let ocb = Board::from_fen("8/5p2/3b4/4k3/8/2P5/2B5/4K3 w - - 0 1").unwrap();
+        assert!(ocb.is_ocb_endgame());
+        let same_colour = Board::from_fen("8/5p2/4b3/4k3/8/2P5/2B5/4K3 w - - 0 1").unwrap();
+        assert!(!same_colour.is_ocb_endgame());
+        assert!(!Board::default().is_ocb_endgame());
+    }
+
+    #[test]
+    fn fifty_move_damping_reaches_zero() {
+        use super::Board;
+        let fresh = Board::from_fen("4k3/8/8/8/8/8/8/3QK3 w - - 0 60").unwrap();
+        assert_eq!(fresh.damp_for_fifty_move_rule(500), 500);
+        let stale = Board::from_fen("4k3/8/8/8/8/8/8/3QK3 w - - 80 100").unwrap();
+        assert_eq!(stale.damp_for_fifty_move_rule(500), 300);
+        let doomed = Board::from_fen("4k3/8/8/8/8/8/8/3QK3 w - - 99 110").unwrap();
+        assert!(doomed.damp_for_fifty_move_rule(500).abs() < 20);
+    }
+
+    #[test]
+    fn passed_pawn_push_detection() {
+        use super::Board;
+        let white = Board::from_fen("4k3/5p2/8/P3P2P/7p/8/8/4K3 w - - 0 1").unwrap();
+        assert!(white.is_passed_pawn_push(white.parse_uci("a5a6").unwrap()));
+        // f7 still guards the way.
+        assert!(!white.is_passed_pawn_push(white.parse_uci("e5e6").unwrap()));
+        let black = Board::from_fen("4k3/5p2/8/P3P3/7p/8/8/4K3 b - - 0 1").unwrap();
+        assert!(black.is_passed_pawn_push(black.parse_uci("h4h3").unwrap()));
+        // not advanced far enough.
+        assert!(!black.is_passed_pawn_push(black.parse_uci("f7f6").unwrap()));
+    }
+
+    #[test]
+    fn endgame_scale_factors() {
+        use super::Board;
+        use crate::piece::Colour;
+        // an extra pawn in a rook ending with all the pawns on one wing.
+        let one_wing = Board::from_fen("8/5pk1/6p1/7p/4PP2/6PP/4r1K1/R7 w - - 0 1").unwrap();
+        assert!(one_wing.endgame_scale(Colour::WHITE) < 64);
+        let two_wings = Board::from_fen("8/p4pk1/6p1/8/5P2/P5PP/4r1K1/R7 w - - 0 1").unwrap();
+        assert_eq!(two_wings.endgame_scale(Colour::WHITE), 64);
+        // a rook against a bishop, with no pawns.
+        let rook_v_bishop = Board::from_fen("8/8/3kb3/8/8/3K4/8/R7 w - - 0 1").unwrap();
+        assert!(rook_v_bishop.endgame_scale(Colour::WHITE) < 64);
+        assert_eq!(Board::default().endgame_scale(Colour::WHITE), 64);
+    }
+
+    #[test]
+    fn mop_up_drives_king_to_the_right_corner() {
+        use super::Board;
+        use crate::piece::Colour;
+        // with a dark-squared bishop, a1 is the mating corner and h1 is not.
+        let right_corner = Board::from_fen("8/8/8/8/8/2K5/8/k2NB3 w - - 0 1").unwrap();
+        let wrong_corner = Board::from_fen("8/8/8/8/8/5K2/8/3NB2k w - - 0 1").unwrap();
+        assert!(right_corner.mop_up(Colour::WHITE) > wrong_corner.mop_up(Colour::WHITE));
+        // otherwise, any edge will do.
+        let edge = Board::from_fen("8/8/8/8/8/2K5/7Q/k7 w - - 0 1").unwrap();
+        let centre = Board::from_fen("8/8/8/3k4/8/3K4/7Q/8 w - - 0 1").unwrap();
+        assert!(edge.mop_up(Colour::WHITE) > centre.mop_up(Colour::WHITE));
+    }
+
+    #[test]
+    fn upcoming_repetition_detection() {
+        use super::Board;
+        use crate::chessmove::Move;
+        use crate::util::Square;
+        let mut board = Board::default();
+        assert!(board.make_move_simple(Move::new(Square::E2, Square::E4)));
+        assert!(board.make_move_simple(Move::new(Square::E7, Square::E5)));
+        assert!(board.make_move_simple(Move::new(Square::G1, Square::F3)));
+        assert!(board.make_move_simple(Move::new(Square::B8, Square::C6)));
+        assert!(!board.has_game_cycle(board.height()));
+        assert!(board.make_move_simple(Move::new(Square::F3, Square::G1)));
+        // black can now play Nb8, returning to the position after 1. e4 e5.
+        assert!(!board.is_draw());
+        assert!(board.has_game_cycle(board.height()));
+    }
+
+    #[test]
+    fn see_undefended_capture() {
+        use super::Board;
+        use crate::chessmove::Move;
+        use crate::piece::PieceType;
+        use crate::util::Square;
+        let board = Board::from_fen("4k3/8/8/3n4/4P3/8/8/4K3 w - - 0 1").unwrap();
+        let exd5 = Move::new(Square::E4, Square::D5);
+        assert!(board.static_exchange_eval(exd5, 0));
+        assert!(board.static_exchange_eval(exd5, PieceType::KNIGHT.see_value()));
+        assert!(!board.static_exchange_eval(exd5, PieceType::KNIGHT.see_value() + 1));
+    }
+
+    #[test]
+    fn see_defended_capture() {
+        use super::Board;
+        use crate::chessmove::Move;
+        use crate::util::Square;
+        let board = Board::from_fen("4k3/8/2p5/3p4/8/8/3Q4/4K3 w - - 0 1").unwrap();
+        let qxd5 = Move::new(Square::D2, Square::D5);
+        assert!(!board.static_exchange_eval(qxd5, 0));
+    }
+
+    #[test]
+    fn see_xray_attackers() {
+        use super::Board;
+        use crate::chessmove::Move;
+        use crate::util::Square;
+        let rxd5 = Move::new(Square::D2, Square::D5);
+        // the rook on d1 backs up the capture through the rook on d2.
+        let supported = Board::from_fen("4k3/3r4/8/3p4/8/8/3R4/3RK3 w - - 0 1").unwrap();
+        assert!(supported.static_exchange_eval(rxd5, 0));
+        let unsupported = Board::from_fen("4k3/3r4/8/3p4/8/8/3R4/4K3 w - - 0 1").unwrap();
+        assert!(!unsupported.static_exchange_eval(rxd5, 0));
+    }
+
+    #[test]
+    fn unmake_restores_every_kind_of_move() {
+        use super::{check_eq, Board};
+        use crate::board::movegen::MoveList;
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/8/8/8/3pP3/8/8/R3K2R b KQkq e3 0 1",
+            "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+        ] {
+            let mut board = Board::from_fen(fen).unwrap();
+            let before = board.clone();
+            let mut ml = MoveList::new();
+            board.generate_moves(&mut ml);
+            for &m in ml.iter_moves() {
+                if !board.make_move_simple(m) {
+                    continue;
+                }
+                board.unmake_move_base();
+                check_eq(&board, &before, &format!("after unmaking {m} in {fen}"));
+            }
+            board.make_nullmove();
+            board.unmake_nullmove();
+            check_eq(&board, &before, &format!("after unmaking a null move in {fen}"));
+        }
+    }
+
+    #[test]
+    #[cfg(any(debug_assertions, feature = "verify"))]
+    fn position_command_replays_the_game() {
+        use super::Board;
+        let mut board = Board::from_fen("r3k2r/8/8/8/3pP3/8/8/R3K2R b KQkq e3 0 1").unwrap();
+        for m in ["d4e3", "e1g1", "e8c8"] {
+            let m = board.parse_uci(m).unwrap();
+            assert!(board.make_move_simple(m));
+        }
+        assert_eq!(
+            board.position_command(),
+            "position fen r3k2r/8/8/8/3pP3/8/8/R3K2R b KQkq e3 0 1 moves d4e3 e1g1 e8c8"
+        );
+    }
 }
@@ -3,10 +3,12 @@
 use crate::{
     board::Board,
     chessmove::Move,
-    piece::{Colour, PieceType},
+    kpk,
+    piece::{Black, Colour, Piece, PieceType, White},
     search::draw_score,
+    squareset::SquareSet,
     threadlocal::ThreadData,
-    util::MAX_DEPTH,
+    util::{Square, MAX_DEPTH},
 };
 
 /// The value of checkmate.
@@ -50,6 +52,45 @@ pub const fn is_game_theoretic_score(score: i32) -> bool {
     score.abs() >= MINIMUM_TB_WIN_SCORE
 }
 
+/// The minimum evaluation of a KPK position that the bitbase marks as won.
+const KPK_WIN_SCORE: i32 = 400;
+
+/// Mop-up weights for positions against a bare king.
+const MOP_UP_EDGE_WEIGHT: i32 = 20;
+const MOP_UP_CORNER_WEIGHT: i32 = 20;
+const MOP_UP_PROXIMITY_WEIGHT: i32 = 10;
+
+/// The denominator of endgame scale factors.
+const SCALE_NORMAL: i32 = 64;
+
+/// The material held by one side, used to classify endgames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MaterialSignature {
+    pub pawns: u32,
+    pub knights: u32,
+    pub bishops: u32,
+    pub rooks: u32,
+    pub queens: u32,
+}
+
+impl MaterialSignature {
+    /// The value of the non-pawn material in this signature.
+    pub const fn non_pawn_material(self) -> i32 {
+        #![allow(clippy::cast_possible_wrap)]
+        PieceType::KNIGHT.see_value() * self.knights as i32
+            + PieceType::BISHOP.see_value() * self.bishops as i32
+            + PieceType::ROOK.see_value() * self.rooks as i32
+            + PieceType::QUEEN.see_value() * self.queens as i32
+    }
+}
+
+/// The result of a KPK bitbase probe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KpkOutcome {
+    Draw,
+    Win(Colour),
+}
+
 impl Board {
     const fn material_scale(&self) -> i32 {
         #![allow(clippy::cast_possible_wrap)]
@@ -60,9 +101,99 @@ impl Board {
             / 32
     }
 
-    pub fn evaluate_nnue(&self, t: &ThreadData) -> i32 {
-        // get the raw network output
-        let v = t.nnue.evaluate(self.side);
+    /// The game phase, counting down from 24 with all the minor and major pieces
+    /// on the board to 0 when only kings and pawns remain.
+    pub const fn phase(&self) -> i32 {
+        #![allow(clippy::cast_possible_wrap)]
+        let minors = self.pieces.all_knights().count() + self.pieces.all_bishops().count();
+        let rooks = self.pieces.all_rooks().count();
+        let queens = self.pieces.all_queens().count();
+        let phase = (minors + 2 * rooks + 4 * queens) as i32;
+        min!(phase, 24)
+    }
+
+    pub fn material_signature(&self, colour: Colour) -> MaterialSignature {
+        let count = |pt| self.pieces.piece_bb(Piece::new(colour, pt)).count();
+        MaterialSignature {
+            pawns: count(PieceType::PAWN),
+            knights: count(PieceType::KNIGHT),
+            bishops: count(PieceType::BISHOP),
+            rooks: count(PieceType::ROOK),
+            queens: count(PieceType::QUEEN),
+        }
+    }
+
+    /// How much of the evaluation to keep, out of `SCALE_NORMAL`, when `strong` is
+    /// the side that the evaluation favours. Known drawish material configurations
+    /// get a reduced scale factor.
+    pub fn endgame_scale(&self, strong: Colour) -> i32 {
+        let us = self.material_signature(strong);
+        let them = self.material_signature(strong.flip());
+
+        // opposite-coloured bishop endings are very hard to win,
+        // even with an extra pawn or two.
+        if self.is_ocb_endgame() {
+            return SCALE_NORMAL / 2;
+        }
+
+        // without pawns, being up less than a bishop's worth
+        // of material is rarely enough to win.
+        if us.pawns == 0 && us.non_pawn_material() - them.non_pawn_material() <= PieceType::BISHOP.see_value() {
+            return SCALE_NORMAL / 4;
+        }
+
+        // a single extra pawn in a rook ending, with all the pawns
+        // on one wing, is usually not enough.
+        let rook_ending = us.rooks == 1
+            && them.rooks == 1
+            && us.non_pawn_material() == them.non_pawn_material()
+            && (self.pieces.all_knights() | self.pieces.all_bishops() | self.pieces.all_queens()).is_empty();
+        if rook_ending && us.pawns == them.pawns + 1 {
+            let pawns = self.pieces.all_pawns();
+            let queenside = SquareSet::FILE_A | SquareSet::FILE_B | SquareSet::FILE_C | SquareSet::FILE_D;
+            if (pawns & queenside).is_empty() || (pawns & !queenside).is_empty() {
+                return SCALE_NORMAL * 5 / 8;
+            }
+        }
+
+        SCALE_NORMAL
+    }
+
+    /// Whether the only pieces on the board (other than kings and pawns)
+    /// are one bishop for each side, on opposite-coloured squares.
+    pub fn is_ocb_endgame(&self) -> bool {
+        let bishops = self.pieces.all_bishops();
+        (self.pieces.all_knights() | self.pieces.all_rooks() | self.pieces.all_queens()).is_empty()
+            && self.pieces.bishops::<White>().count() == 1
+            && self.pieces.bishops::<Black>().count() == 1
+            && (bishops & SquareSet::DARK_SQUARES).count() == 1
+    }
+
+    /// Whether a pawn of colour `colour` on `sq` would be passed, i.e. no enemy pawn
+    /// stands in front of it on its own file or either adjacent file.
+    pub fn is_passed_pawn(&self, sq: Square, colour: Colour) -> bool {
+        let file = SquareSet::from_inner(SquareSet::FILE_A.inner() << sq.file());
+        let files = file | file.east_one() | file.west_one();
+        let ahead = if colour == Colour::WHITE {
+            SquareSet::from_inner(!0u64 << 8 << (8 * sq.rank()))
+        } else {
+            SquareSet::from_inner((1u64 << (8 * sq.rank())) - 1)
+        };
+        let enemy_pawns = self.pieces.piece_bb(Piece::new(colour.flip(), PieceType::PAWN));
+        (enemy_pawns & files & ahead).is_empty()
+    }
+
+    pub fn evaluate_nnue(&self, t: &mut ThreadData) -> i32 {
+        // get the raw network output, either from the cache, or by applying
+        // all in-waiting updates to generate a valid accumulator state.
+        let v = if let Some(v) = t.eval_cache.probe(self.key) {
+            v
+        } else {
+            t.nnue.force(self);
+            let v = t.nnue.evaluate(self.side);
+            t.eval_cache.store(self.key, v);
+            v
+        };
 
         // scale down the value estimate when there's not much
         // material left - this will incentivize keeping material
@@ -70,10 +201,10 @@ impl Board {
         // material off if the position is worse for us.
         let v = v * self.material_scale() / 1024;
 
-        // scale down the value when the fifty-move counter is high.
-        // this goes some way toward making viri realise when he's not
-        // making progress in a position.
-        let v = v * (200 - i32::from(self.fifty_move_counter)) / 200;
+        // pull the value toward zero in endings that are
+        // hard to win despite a material advantage.
+        let strong = if v >= 0 { self.side } else { self.side.flip() };
+        let v = v * self.endgame_scale(strong) / SCALE_NORMAL;
 
         // clamp the value into the valid range.
         // this basically never comes up, but the network will
@@ -82,20 +213,101 @@ impl Board {
         v.clamp(-MINIMUM_TB_WIN_SCORE + 1, MINIMUM_TB_WIN_SCORE - 1)
     }
 
+    /// Scales an evaluation down as the fifty-move counter rises, so that
+    /// viri realises when he isn't making progress in a position. The
+    /// damping is gentle at first, then goes all the way to zero over the
+    /// last ten moves before the fifty-move rule draws the game.
+    ///
+    /// This isn't part of `evaluate`, because the static evaluations kept
+    /// in the transposition table can be reached with a different counter.
+    pub fn damp_for_fifty_move_rule(&self, v: i32) -> i32 {
+        let counter = i32::from(self.fifty_move_counter).min(100);
+        let remaining = if counter < 80 { 200 - counter } else { 6 * (100 - counter) };
+        v * remaining / 200
+    }
+
+    /// If this is a king-and-pawn-versus-king position, consult the KPK bitbase.
+    pub fn probe_kpk(&self) -> Option<KpkOutcome> {
+        let pawns = self.pieces.all_pawns();
+        if pawns.count() != 1 || self.pieces.occupied().count() != 3 {
+            return None;
+        }
+        let pawn = pawns.first();
+        let strong = self.piece_at(pawn).colour();
+        let kings = self.pieces.all_kings();
+        let strong_king = (kings & self.pieces.occupied_co(strong)).first();
+        let weak_king = (kings & self.pieces.occupied_co(strong.flip())).first();
+        if kpk::probe(strong, strong_king, pawn, weak_king, self.side) {
+            Some(KpkOutcome::Win(strong))
+        } else {
+            Some(KpkOutcome::Draw)
+        }
+    }
+
     pub fn evaluate(&self, t: &mut ThreadData, nodes: u64) -> i32 {
-        // detect draw by insufficient material
-        if !self.pieces.any_pawns() && self.pieces.is_material_draw() {
-            return if self.side == Colour::WHITE {
-                draw_score(t, nodes, self.turn())
-            } else {
-                -draw_score(t, nodes, self.turn())
-            };
+        let kpk = self.probe_kpk();
+        // detect draw by insufficient material, or a drawn KPK ending
+        if !self.pieces.any_pawns() && self.pieces.is_material_draw() || kpk == Some(KpkOutcome::Draw) {
+            return draw_score(t, nodes, self.turn());
         }
-        // apply all in-waiting updates to generate a valid
-        // neural network accumulator state.
-        t.nnue.force(self);
         // run the neural network evaluation
-        self.evaluate_nnue(t)
+        let v = self.evaluate_nnue(t);
+
+        // if the bitbase knows that the pawn wins, make sure that the
+        // evaluation agrees, with a bonus for advancing the pawn.
+        if let Some(KpkOutcome::Win(strong)) = kpk {
+            let pawn = self.pieces.all_pawns().first();
+            let floor = KPK_WIN_SCORE + 20 * i32::from(pawn.relative_to(strong).rank());
+            return if strong == self.side { v.max(floor) } else { v.min(-floor) };
+        }
+
+        // if one side is down to a bare king, help the other side to
+        // actually deliver mate, rather than shuffling until the fifty-move rule.
+        if !self.pieces.any_pawns() && !self.is_insufficient_material() {
+            let kings = self.pieces.all_kings();
+            for strong in [Colour::WHITE, Colour::BLACK] {
+                if self.pieces.occupied_co(strong.flip()) == kings & self.pieces.occupied_co(strong.flip()) {
+                    let bonus = self.mop_up(strong);
+                    let v = if strong == self.side { v + bonus } else { v - bonus };
+                    return v.clamp(-MINIMUM_TB_WIN_SCORE + 1, MINIMUM_TB_WIN_SCORE - 1);
+                }
+            }
+        }
+
+        v
+    }
+
+    /// A bonus for the side `strong`, playing against a bare king, for pushing the
+    /// defending king toward the edge of the board (or toward a corner that the
+    /// bishop controls, with bishop and knight) and for bringing the attacking king closer.
+    pub fn mop_up(&self, strong: Colour) -> i32 {
+        let kings = self.pieces.all_kings();
+        let strong_king = (kings & self.pieces.occupied_co(strong)).first();
+        let weak_king = (kings & self.pieces.occupied_co(strong.flip())).first();
+
+        let bishops = self.pieces.all_bishops();
+        let is_kbnk =
+            self.pieces.occupied().count() == 4 && bishops.count() == 1 && self.pieces.all_knights().count() == 1;
+
+        let edge_term = if is_kbnk {
+            // mate can only be forced in a corner of the same colour as the bishop.
+            let corners = if (bishops & SquareSet::DARK_SQUARES).non_empty() {
+                [Square::A1, Square::H8]
+            } else {
+                [Square::A8, Square::H1]
+            };
+            let corner_distance =
+                min!(Square::distance(weak_king, corners[0]), Square::distance(weak_king, corners[1]));
+            MOP_UP_CORNER_WEIGHT * (7 - i32::from(corner_distance))
+        } else {
+            let file = i32::from(weak_king.file());
+            let rank = i32::from(weak_king.rank());
+            let centre_distance = max!(3 - file, file - 4) + max!(3 - rank, rank - 4);
+            MOP_UP_EDGE_WEIGHT * centre_distance
+        };
+
+        let king_distance = i32::from(Square::distance(strong_king, weak_king));
+        edge_term + MOP_UP_PROXIMITY_WEIGHT * (7 - king_distance)
     }
 
     pub fn zugzwang_unlikely(&self) -> bool {
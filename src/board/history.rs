@@ -1,6 +1,7 @@
 use crate::{
+    board::evaluation::MINIMUM_TB_WIN_SCORE,
     chessmove::Move,
-    historytable::{update_history, ContHistIndex},
+    historytable::{update_history, ContHistIndex, CORRECTION_HISTORY_GRAIN},
     piece::{Piece, PieceType},
     threadlocal::ThreadData,
     util::{depth::Depth, Square, Undo, MAX_DEPTH},
@@ -26,18 +27,19 @@ impl ThreadData<'_> {
         }
     }
 
-    /// Get the history scores for a batch of moves.
-    pub(super) fn get_history_scores(&self, pos: &Board, ms: &mut [MoveListEntry]) {
+    /// Get the history scores for a batch of moves, scaled by `weight / 1024`.
+    pub(super) fn get_history_scores(&self, pos: &Board, ms: &mut [MoveListEntry], weight: i32) {
         for m in ms {
             let piece_moved = pos.moved_piece(m.mov);
             let from = m.mov.from();
             let to = m.mov.history_to_square();
-            m.score += i32::from(self.main_history.get(
+            let score = i32::from(self.main_history.get(
                 piece_moved,
                 to,
                 pos.threats.all.contains_square(from),
                 pos.threats.all.contains_square(to),
             ));
+            m.score += score * weight / 1024;
         }
     }
 
@@ -103,8 +105,14 @@ impl ThreadData<'_> {
         }
     }
 
-    /// Get the continuation history scores for a batch of moves.
-    pub(super) fn get_continuation_history_scores(&self, pos: &Board, ms: &mut [MoveListEntry], index: usize) {
+    /// Get the continuation history scores for a batch of moves, scaled by `weight / 1024`.
+    pub(super) fn get_continuation_history_scores(
+        &self,
+        pos: &Board,
+        ms: &mut [MoveListEntry],
+        index: usize,
+        weight: i32,
+    ) {
         // get the index'th from the back of the conthist history, and make sure the entry is valid.
         if let Some(Undo { cont_hist_index: ContHistIndex { square: Square::NO_SQUARE, .. }, .. }) = pos.history.last()
         {
@@ -119,7 +127,7 @@ impl ThreadData<'_> {
         for m in ms {
             let to = m.mov.history_to_square();
             let piece = pos.moved_piece(m.mov);
-            m.score += i32::from(cmh_block.get(piece, to));
+            m.score += i32::from(cmh_block.get(piece, to)) * weight / 1024;
         }
     }
 
@@ -141,6 +149,17 @@ impl ThreadData<'_> {
         i32::from(cmh_block.get(piece, to))
     }
 
+    /// Update the correction history with the difference between a search result and the static evaluation.
+    pub fn update_correction_history(&mut self, pos: &Board, depth: Depth, diff: i32) {
+        self.correction_history.update(pos.turn(), pos.pawn_key(), depth, diff);
+    }
+
+    /// Adjust a raw static evaluation by the correction history for this pawn structure.
+    pub fn correct_evaluation(&self, pos: &Board, raw_eval: i32) -> i32 {
+        let correction = self.correction_history.get(pos.turn(), pos.pawn_key()) / CORRECTION_HISTORY_GRAIN;
+        pos.damp_for_fifty_move_rule(raw_eval + correction).clamp(-MINIMUM_TB_WIN_SCORE + 1, MINIMUM_TB_WIN_SCORE - 1)
+    }
+
     /// Add a killer move.
     pub fn insert_killer(&mut self, pos: &Board, m: Move) {
         debug_assert!(pos.height < MAX_DEPTH.ply_to_horizon());
@@ -19,7 +19,7 @@ use crate::{
     piece::{Black, Col, Colour, PieceType, White},
     squareset::SquareSet,
     uci::CHESS960,
-    util::{Square, RAY_BETWEEN},
+    util::{Square, RAY_BETWEEN, RAY_FULL},
 };
 
 pub const MAX_POSITION_MOVES: usize = 218;
@@ -95,9 +95,9 @@ impl Board {
     fn generate_pawn_caps<C: Col, Mode: MovePickerMode>(
         &self,
         move_list: &mut MoveList,
+        our_pawns: SquareSet,
         valid_target_squares: SquareSet,
     ) {
-        let our_pawns = self.pieces.pawns::<C>();
         let their_pieces = self.pieces.their_pieces::<C>();
         // to determine which pawns can capture, we shift the opponent's pieces backwards and find the intersection
         let attacking_west = if C::WHITE {
@@ -160,17 +160,20 @@ impl Board {
         let attacks_east =
             if C::WHITE { ep_bb.south_west_one() & our_pawns } else { ep_bb.north_west_one() & our_pawns };
 
-        if attacks_west.non_empty() {
-            let from_sq = attacks_west.first();
-            move_list.push::<true>(Move::new_with_flags(from_sq, self.ep_sq, Move::EP_FLAG));
-        }
-        if attacks_east.non_empty() {
-            let from_sq = attacks_east.first();
-            move_list.push::<true>(Move::new_with_flags(from_sq, self.ep_sq, Move::EP_FLAG));
+        for from_sq in attacks_west | attacks_east {
+            let m = Move::new_with_flags(from_sq, self.ep_sq, Move::EP_FLAG);
+            if self.is_legal(m) {
+                move_list.push::<true>(m);
+            }
         }
     }
 
-    fn generate_pawn_forward<C: Col>(&self, move_list: &mut MoveList, valid_target_squares: SquareSet) {
+    fn generate_pawn_forward<C: Col>(
+        &self,
+        move_list: &mut MoveList,
+        our_pawns: SquareSet,
+        valid_target_squares: SquareSet,
+    ) {
         let start_rank = if C::WHITE { SquareSet::RANK_2 } else { SquareSet::RANK_7 };
         let promo_rank = if C::WHITE { SquareSet::RANK_7 } else { SquareSet::RANK_2 };
         let shifted_empty_squares = if C::WHITE { self.pieces.empty() >> 8 } else { self.pieces.empty() << 8 };
@@ -178,7 +181,6 @@ impl Board {
         let shifted_valid_squares = if C::WHITE { valid_target_squares >> 8 } else { valid_target_squares << 8 };
         let double_shifted_valid_squares =
             if C::WHITE { valid_target_squares >> 16 } else { valid_target_squares << 16 };
-        let our_pawns = self.pieces.pawns::<C>();
         let pushable_pawns = our_pawns & shifted_empty_squares;
         let double_pushable_pawns = pushable_pawns & double_shifted_empty_squares & start_rank;
         let promoting_pawns = pushable_pawns & promo_rank;
@@ -201,12 +203,12 @@ impl Board {
     fn generate_forward_promos<C: Col, Mode: MovePickerMode>(
         &self,
         move_list: &mut MoveList,
+        our_pawns: SquareSet,
         valid_target_squares: SquareSet,
     ) {
         let promo_rank = if C::WHITE { SquareSet::RANK_7 } else { SquareSet::RANK_2 };
         let shifted_empty_squares = if C::WHITE { self.pieces.empty() >> 8 } else { self.pieces.empty() << 8 };
         let shifted_valid_squares = if C::WHITE { valid_target_squares >> 8 } else { valid_target_squares << 8 };
-        let our_pawns = self.pieces.pawns::<C>();
         let pushable_pawns = our_pawns & shifted_empty_squares;
         let promoting_pawns = pushable_pawns & promo_rank;
         for sq in promoting_pawns & shifted_valid_squares {
@@ -222,6 +224,43 @@ impl Board {
         }
     }
 
+    /// The squares that a piece on `sq` can move to without exposing the king.
+    fn pin_mask(&self, sq: Square, king_sq: Square) -> SquareSet {
+        if self.threats.pinned.contains_square(sq) {
+            RAY_FULL[king_sq.index()][sq.index()]
+        } else {
+            SquareSet::FULL
+        }
+    }
+
+    /// Splits our pawns into the ones that can move freely, which are generated together,
+    /// and the pinned ones, which are generated one at a time along the line of their pin.
+    fn pawn_groups<C: Col>(
+        &self,
+        king_sq: Square,
+        valid_target_squares: SquareSet,
+    ) -> impl Iterator<Item = (SquareSet, SquareSet)> {
+        let our_pawns = self.pieces.pawns::<C>();
+        let pinned = our_pawns & self.threats.pinned;
+        std::iter::once((our_pawns & !pinned, valid_target_squares)).chain(
+            pinned
+                .into_iter()
+                .map(move |sq| (sq.as_set(), valid_target_squares & RAY_FULL[king_sq.index()][sq.index()])),
+        )
+    }
+
+    /// The squares that our king can move to. The king can't step back along
+    /// the line of a checking slider, as the slider sees through the king.
+    fn king_targets(&self, king_sq: Square) -> SquareSet {
+        let their_sliders = self.pieces.occupied_co(self.side.flip())
+            & (self.pieces.all_bishops() | self.pieces.all_rooks() | self.pieces.all_queens());
+        let mut xrayed = SquareSet::EMPTY;
+        for checker in self.threats.checkers & their_sliders {
+            xrayed |= RAY_FULL[king_sq.index()][checker.index()] & !checker.as_set();
+        }
+        bitboards::king_attacks(king_sq) & !self.threats.all & !xrayed
+    }
+
     pub fn generate_moves(&self, move_list: &mut MoveList) {
         move_list.clear();
         if self.side == Colour::WHITE {
@@ -242,7 +281,7 @@ impl Board {
 
         if self.threats.checkers.count() > 1 {
             // we're in double-check, so we can only move the king.
-            let moves = bitboards::king_attacks(our_king_sq) & !self.threats.all;
+            let moves = self.king_targets(our_king_sq);
             for to in moves & their_pieces {
                 move_list.push::<true>(Move::new(our_king_sq, to));
             }
@@ -258,14 +297,16 @@ impl Board {
             SquareSet::FULL
         };
 
-        self.generate_pawn_forward::<C>(move_list, valid_target_squares);
-        self.generate_pawn_caps::<C, MainSearch>(move_list, valid_target_squares);
+        for (pawns, valid_target_squares) in self.pawn_groups::<C>(our_king_sq, valid_target_squares) {
+            self.generate_pawn_forward::<C>(move_list, pawns, valid_target_squares);
+            self.generate_pawn_caps::<C, MainSearch>(move_list, pawns, valid_target_squares);
+        }
         self.generate_ep::<C>(move_list);
 
         // knights
         let our_knights = self.pieces.knights::<C>();
         for sq in our_knights {
-            let moves = bitboards::knight_attacks(sq) & valid_target_squares;
+            let moves = bitboards::knight_attacks(sq) & valid_target_squares & self.pin_mask(sq, our_king_sq);
             for to in moves & their_pieces {
                 move_list.push::<true>(Move::new(sq, to));
             }
@@ -275,7 +316,7 @@ impl Board {
         }
 
         // kings
-        let moves = bitboards::king_attacks(our_king_sq) & !self.threats.all;
+        let moves = self.king_targets(our_king_sq);
         for to in moves & their_pieces {
             move_list.push::<true>(Move::new(our_king_sq, to));
         }
@@ -287,7 +328,7 @@ impl Board {
         let our_diagonal_sliders = self.pieces.diags::<C>();
         let blockers = self.pieces.occupied();
         for sq in our_diagonal_sliders {
-            let moves = bitboards::bishop_attacks(sq, blockers) & valid_target_squares;
+            let moves = bitboards::bishop_attacks(sq, blockers) & valid_target_squares & self.pin_mask(sq, our_king_sq);
             for to in moves & their_pieces {
                 move_list.push::<true>(Move::new(sq, to));
             }
@@ -299,7 +340,7 @@ impl Board {
         // rooks and queens
         let our_orthogonal_sliders = self.pieces.orthos::<C>();
         for sq in our_orthogonal_sliders {
-            let moves = bitboards::rook_attacks(sq, blockers) & valid_target_squares;
+            let moves = bitboards::rook_attacks(sq, blockers) & valid_target_squares & self.pin_mask(sq, our_king_sq);
             for to in moves & their_pieces {
                 move_list.push::<true>(Move::new(sq, to));
             }
@@ -332,7 +373,7 @@ impl Board {
 
         if self.threats.checkers.count() > 1 {
             // we're in double-check, so we can only move the king.
-            let moves = bitboards::king_attacks(our_king_sq) & !self.threats.all;
+            let moves = self.king_targets(our_king_sq);
             for to in moves & their_pieces {
                 move_list.push::<true>(Move::new(our_king_sq, to));
             }
@@ -345,25 +386,25 @@ impl Board {
             SquareSet::FULL
         };
 
-        // promotions
-        self.generate_forward_promos::<C, Mode>(move_list, valid_target_squares);
-
-        // pawn captures and capture promos
-        self.generate_pawn_caps::<C, Mode>(move_list, valid_target_squares);
+        // promotions, pawn captures, and capture promos
+        for (pawns, valid_target_squares) in self.pawn_groups::<C>(our_king_sq, valid_target_squares) {
+            self.generate_forward_promos::<C, Mode>(move_list, pawns, valid_target_squares);
+            self.generate_pawn_caps::<C, Mode>(move_list, pawns, valid_target_squares);
+        }
         self.generate_ep::<C>(move_list);
 
         // knights
         let our_knights = self.pieces.knights::<C>();
         let their_pieces = self.pieces.their_pieces::<C>();
         for sq in our_knights {
-            let moves = bitboards::knight_attacks(sq) & valid_target_squares;
+            let moves = bitboards::knight_attacks(sq) & valid_target_squares & self.pin_mask(sq, our_king_sq);
             for to in moves & their_pieces {
                 move_list.push::<true>(Move::new(sq, to));
             }
         }
 
         // kings
-        let moves = bitboards::king_attacks(our_king_sq) & !self.threats.all;
+        let moves = self.king_targets(our_king_sq);
         for to in moves & their_pieces {
             move_list.push::<true>(Move::new(our_king_sq, to));
         }
@@ -372,7 +413,7 @@ impl Board {
         let our_diagonal_sliders = self.pieces.diags::<C>();
         let blockers = self.pieces.occupied();
         for sq in our_diagonal_sliders {
-            let moves = bitboards::bishop_attacks(sq, blockers) & valid_target_squares;
+            let moves = bitboards::bishop_attacks(sq, blockers) & valid_target_squares & self.pin_mask(sq, our_king_sq);
             for to in moves & their_pieces {
                 move_list.push::<true>(Move::new(sq, to));
             }
@@ -381,7 +422,7 @@ impl Board {
         // rooks and queens
         let our_orthogonal_sliders = self.pieces.orthos::<C>();
         for sq in our_orthogonal_sliders {
-            let moves = bitboards::rook_attacks(sq, blockers) & valid_target_squares;
+            let moves = bitboards::rook_attacks(sq, blockers) & valid_target_squares & self.pin_mask(sq, our_king_sq);
             for to in moves & their_pieces {
                 move_list.push::<true>(Move::new(sq, to));
             }
@@ -437,6 +478,8 @@ impl Board {
             let q_to = Square::A1.relative_to(C::COLOUR);
             let k_thru = Square::F1.relative_to(C::COLOUR);
             let q_thru = Square::D1.relative_to(C::COLOUR);
+            let k_dst = Square::G1.relative_to(C::COLOUR);
+            let q_dst = Square::C1.relative_to(C::COLOUR);
             let k_perm = self.castle_perm.kingside(C::COLOUR);
             let q_perm = self.castle_perm.queenside(C::COLOUR);
 
@@ -451,6 +494,7 @@ impl Board {
                     !got_attacked_king
                 }
                 && !self.sq_attacked_by::<C::Opposite>(k_thru)
+                && !self.sq_attacked_by::<C::Opposite>(k_dst)
             {
                 move_list.push::<false>(Move::new_with_flags(from, k_to, Move::CASTLE_FLAG));
             }
@@ -459,6 +503,7 @@ impl Board {
                 && (occupied & q_freespace).is_empty()
                 && !cache.unwrap_or_else(|| self.sq_attacked_by::<C::Opposite>(from))
                 && !self.sq_attacked_by::<C::Opposite>(q_thru)
+                && !self.sq_attacked_by::<C::Opposite>(q_dst)
             {
                 move_list.push::<false>(Move::new_with_flags(from, q_to, Move::CASTLE_FLAG));
             }
@@ -480,7 +525,10 @@ impl Board {
         if (relevant_occupied & (king_path | rook_path | king_dst.as_set() | rook_dst.as_set())).is_empty()
             && !self.any_attacked(king_path, C::Opposite::COLOUR)
         {
-            move_list.push::<false>(Move::new_with_flags(king_sq, castling_sq, Move::CASTLE_FLAG));
+            let m = Move::new_with_flags(king_sq, castling_sq, Move::CASTLE_FLAG);
+            if self.is_legal(m) {
+                move_list.push::<false>(m);
+            }
         }
     }
 
@@ -494,7 +542,12 @@ impl Board {
         debug_assert!(move_list.iter_moves().all(|m| m.is_valid()));
     }
 
-    fn generate_pawn_quiet<C: Col>(&self, move_list: &mut MoveList, valid_target_squares: SquareSet) {
+    fn generate_pawn_quiet<C: Col>(
+        &self,
+        move_list: &mut MoveList,
+        our_pawns: SquareSet,
+        valid_target_squares: SquareSet,
+    ) {
         let start_rank = if C::WHITE { SquareSet::RANK_2 } else { SquareSet::RANK_7 };
         let promo_rank = if C::WHITE { SquareSet::RANK_7 } else { SquareSet::RANK_2 };
         let shifted_empty_squares = if C::WHITE { self.pieces.empty() >> 8 } else { self.pieces.empty() << 8 };
@@ -502,7 +555,6 @@ impl Board {
         let shifted_valid_squares = if C::WHITE { valid_target_squares >> 8 } else { valid_target_squares << 8 };
         let double_shifted_valid_squares =
             if C::WHITE { valid_target_squares >> 16 } else { valid_target_squares << 16 };
-        let our_pawns = self.pieces.pawns::<C>();
         let pushable_pawns = our_pawns & shifted_empty_squares;
         let double_pushable_pawns = pushable_pawns & double_shifted_empty_squares & start_rank;
         let promoting_pawns = pushable_pawns & promo_rank;
@@ -523,7 +575,7 @@ impl Board {
 
         if self.threats.checkers.count() > 1 {
             // we're in double-check, so we can only move the king.
-            let moves = bitboards::king_attacks(our_king_sq) & !self.threats.all;
+            let moves = self.king_targets(our_king_sq);
             for to in moves & freespace {
                 move_list.push::<false>(Move::new(our_king_sq, to));
             }
@@ -537,19 +589,21 @@ impl Board {
         };
 
         // pawns
-        self.generate_pawn_quiet::<C>(move_list, valid_target_squares);
+        for (pawns, valid_target_squares) in self.pawn_groups::<C>(our_king_sq, valid_target_squares) {
+            self.generate_pawn_quiet::<C>(move_list, pawns, valid_target_squares);
+        }
 
         // knights
         let our_knights = self.pieces.knights::<C>();
         for sq in our_knights {
-            let moves = bitboards::knight_attacks(sq) & valid_target_squares;
+            let moves = bitboards::knight_attacks(sq) & valid_target_squares & self.pin_mask(sq, our_king_sq);
             for to in moves & !blockers {
                 move_list.push::<false>(Move::new(sq, to));
             }
         }
 
         // kings
-        let moves = bitboards::king_attacks(our_king_sq) & !self.threats.all;
+        let moves = self.king_targets(our_king_sq);
         for to in moves & !blockers {
             move_list.push::<false>(Move::new(our_king_sq, to));
         }
@@ -557,7 +611,7 @@ impl Board {
         // bishops and queens
         let our_diagonal_sliders = self.pieces.diags::<C>();
         for sq in our_diagonal_sliders {
-            let moves = bitboards::bishop_attacks(sq, blockers) & valid_target_squares;
+            let moves = bitboards::bishop_attacks(sq, blockers) & valid_target_squares & self.pin_mask(sq, our_king_sq);
             for to in moves & !blockers {
                 move_list.push::<false>(Move::new(sq, to));
             }
@@ -566,7 +620,7 @@ impl Board {
         // rooks and queens
         let our_orthogonal_sliders = self.pieces.orthos::<C>();
         for sq in our_orthogonal_sliders {
-            let moves = bitboards::rook_attacks(sq, blockers) & valid_target_squares;
+            let moves = bitboards::rook_attacks(sq, blockers) & valid_target_squares & self.pin_mask(sq, our_king_sq);
             for to in moves & !blockers {
                 move_list.push::<false>(Move::new(sq, to));
             }
@@ -652,4 +706,32 @@ mod tests {
             synced_perft(&mut pos, 2);
         }
     }
+
+    #[test]
+    fn generated_moves_are_legal() {
+        use super::*;
+        use crate::bench;
+
+        let mut pos = Board::default();
+
+        for fen in bench::BENCH_POSITIONS {
+            pos.set_from_fen(fen).unwrap();
+            let mut ml = MoveList::new();
+            pos.generate_moves(&mut ml);
+            for &m in ml.iter_moves() {
+                assert!(pos.make_move_simple(m), "{m} rejected in {fen}");
+                assert!(!pos.sq_attacked(pos.king_sq(pos.turn().flip()), pos.turn()), "{m} is illegal in {fen}");
+                pos.unmake_move_base();
+            }
+        }
+
+        // capturing en passant would expose the king to the rook.
+        pos.set_from_fen("8/8/8/r2PpK2/8/8/8/7k w - e6 0 2").unwrap();
+        assert!(pos.legal_moves().iter().all(|m| !m.is_ep()));
+        // the knight is pinned, and the king can't step back along the line of the check.
+        pos.set_from_fen("4r2k/8/8/8/4N3/8/8/r3K3 w - - 0 1").unwrap();
+        let moves = pos.legal_moves();
+        assert!(moves.iter().all(|m| m.from() == Square::E1 && m.to() != Square::F1));
+        assert_eq!(moves.len(), 3);
+    }
 }
@@ -340,6 +340,8 @@ pub struct Threats {
     // pub minor: SquareSet,
     // pub rook: SquareSet,
     pub checkers: SquareSet,
+    /// Pieces of the side to move that are pinned to their king.
+    pub pinned: SquareSet,
 }
 
 impl Display for BitBoard {
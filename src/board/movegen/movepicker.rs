@@ -46,7 +46,12 @@ pub struct MovePicker<MovePickerMode> {
     killers: [Move; 2],
     counter_move: Move,
     pub skip_quiets: bool,
+    /// Whether pushes of passed pawns to the sixth or seventh rank are still
+    /// yielded once `skip_quiets` is set.
+    pub keep_passed_pushes: bool,
     see_threshold: i32,
+    /// weights (out of 1024) for the main, 1-ply, and 2-ply history tables.
+    pub history_weights: [i32; 3],
     _mode: std::marker::PhantomData<MovePickerMode>,
 }
 
@@ -64,7 +69,9 @@ impl<Mode: MovePickerMode> MovePicker<Mode> {
             killers,
             counter_move,
             skip_quiets: false,
+            keep_passed_pushes: false,
             see_threshold,
+            history_weights: [1024; 3],
             _mode: std::marker::PhantomData,
         }
     }
@@ -128,17 +135,22 @@ impl<Mode: MovePickerMode> MovePicker<Mode> {
         }
         if self.stage == Stage::GenerateQuiets {
             self.stage = Stage::YieldRemaining;
-            if !self.skip_quiets {
+            if !self.skip_quiets || self.keep_passed_pushes {
                 let start = self.movelist.len();
                 position.generate_quiets(&mut self.movelist);
                 let quiets = &mut self.movelist[start..];
-                Self::score_quiets(t, position, quiets);
+                Self::score_quiets(t, position, quiets, self.history_weights);
             }
         }
         if self.stage == Stage::YieldRemaining {
             if let Some(m) = self.yield_once() {
                 return Some(m);
             }
+            if self.skip_quiets && self.keep_passed_pushes {
+                if let Some(m) = self.yield_passed_push(position) {
+                    return Some(m);
+                }
+            }
             self.stage = Stage::Done;
         }
         None
@@ -185,6 +197,8 @@ impl<Mode: MovePickerMode> MovePicker<Mode> {
             if self.skip_quiets && not_winning {
                 // the best we could find wasn't winning,
                 // and we're skipping quiet moves, so we're done.
+                // the move is put back, in case it's a passed pawn push.
+                self.index -= 1;
                 return None;
             }
             if !self.was_tried_lazily(m.mov) {
@@ -193,15 +207,27 @@ impl<Mode: MovePickerMode> MovePicker<Mode> {
         }
     }
 
-    pub fn score_quiets(t: &ThreadData, pos: &Board, ms: &mut [MoveListEntry]) {
+    /// Extracts the next remaining push of a passed pawn to the sixth or seventh rank,
+    /// in movelist order, for when all other quiet moves are being skipped.
+    fn yield_passed_push(&mut self, position: &Board) -> Option<MoveListEntry> {
+        let found = (self.index..self.movelist.len()).find(|&i| {
+            let m = self.movelist[i].mov;
+            !self.was_tried_lazily(m) && position.is_passed_pawn_push(m)
+        })?;
+        self.movelist.swap(found, self.index);
+        self.index += 1;
+        Some(self.movelist[self.index - 1])
+    }
+
+    pub fn score_quiets(t: &ThreadData, pos: &Board, ms: &mut [MoveListEntry], weights: [i32; 3]) {
         // zero-out the ordering scores
         for m in &mut *ms {
             m.score = 0;
         }
 
-        t.get_history_scores(pos, ms);
-        t.get_continuation_history_scores(pos, ms, 0);
-        t.get_continuation_history_scores(pos, ms, 1);
+        t.get_history_scores(pos, ms, weights[0]);
+        t.get_continuation_history_scores(pos, ms, 0, weights[1]);
+        t.get_continuation_history_scores(pos, ms, 1, weights[2]);
         // t.get_continuation_history_scores(pos, ms, 3);
     }
 
@@ -1,19 +1,114 @@
 #![allow(unused_imports)]
 
 use crate::{
+    errors::IllegalPositionError,
     nnue::network::NNUEState,
-    piece::{Colour, Piece},
+    piece::{Colour, Piece, PieceType},
     searchinfo::SearchInfo,
+    squareset::SquareSet,
     util::{Rank, Square},
 };
 
-#[cfg(debug_assertions)]
+#[cfg(any(debug_assertions, feature = "verify"))]
 use crate::errors::PositionValidityError;
 
 use super::{movegen::bitboards::BitLoop, Board};
 
 impl Board {
-    #[cfg(debug_assertions)]
+    /// Checks that a position set up from outside the engine could have arisen in a game,
+    /// as far as can be told from the position alone. Unlike `check_validity`, this
+    /// assumes the board's internal state is coherent, and asks whether the chess is.
+    pub fn check_legality(&self) -> Result<(), IllegalPositionError> {
+        if let Some(sq) = (self.pieces.all_pawns() & (SquareSet::RANK_1 | SquareSet::RANK_8)).iter().next() {
+            return Err(IllegalPositionError::PawnOnBackRank(sq));
+        }
+
+        for colour in [Colour::WHITE, Colour::BLACK] {
+            let count = |piece_type| self.pieces.piece_bb(Piece::new(colour, piece_type)).count();
+            let pawns = count(PieceType::PAWN);
+            if pawns > 8 {
+                return Err(IllegalPositionError::TooManyPawns(colour));
+            }
+            if self.pieces.occupied_co(colour).count() > 16 {
+                return Err(IllegalPositionError::TooManyPieces(colour));
+            }
+            let promoted = count(PieceType::KNIGHT).saturating_sub(2)
+                + count(PieceType::BISHOP).saturating_sub(2)
+                + count(PieceType::ROOK).saturating_sub(2)
+                + count(PieceType::QUEEN).saturating_sub(1);
+            if promoted > 8 - pawns {
+                return Err(IllegalPositionError::TooManyPromotedPieces(colour));
+            }
+        }
+
+        if self.sq_attacked(self.king_sq(self.side.flip()), self.side) {
+            return Err(IllegalPositionError::OpponentInCheck);
+        }
+
+        let ep_rank = if self.side == Colour::WHITE { Rank::RANK_6 } else { Rank::RANK_3 };
+        if self.ep_sq != Square::NO_SQUARE && self.ep_sq.rank() != ep_rank {
+            return Err(IllegalPositionError::EnPassantRank(self.ep_sq));
+        }
+
+        Ok(())
+    }
+
+    /// Drops castling rights that don't have a king and rook in place, and an en passant
+    /// square that no pawn has just skipped over. Returns a description of each one dropped.
+    ///
+    /// An en passant square is also dropped, without comment, if there is no pawn to capture
+    /// on it, as `make_move` never sets one in that case either.
+    pub(super) fn sanitise_rights(&mut self) -> Vec<String> {
+        let mut dropped = Vec::new();
+
+        for colour in [Colour::WHITE, Colour::BLACK] {
+            let king = self.king_sq(colour);
+            let back_rank = if colour == Colour::WHITE { Rank::RANK_1 } else { Rank::RANK_8 };
+            let rook = Piece::new(colour, PieceType::ROOK);
+            for kingside in [true, false] {
+                let right = if kingside {
+                    self.castle_perm.kingside_mut(colour)
+                } else {
+                    self.castle_perm.queenside_mut(colour)
+                };
+                let sq = *right;
+                if sq == Square::NO_SQUARE {
+                    continue;
+                }
+                if king.rank() != back_rank || self.piece_array[sq.index()] != rook || (sq > king) != kingside {
+                    *right = Square::NO_SQUARE;
+                    let side = if kingside { "kingside" } else { "queenside" };
+                    dropped.push(format!(
+                        "{colour} {side} castling right, which needs a rook on {sq} beside a king on its back rank"
+                    ));
+                }
+            }
+        }
+
+        let ep_rank = if self.side == Colour::WHITE { Rank::RANK_6 } else { Rank::RANK_3 };
+        if self.ep_sq != Square::NO_SQUARE && self.ep_sq.rank() == ep_rank {
+            let (pushed, origin) = if self.side == Colour::WHITE {
+                (self.ep_sq.sub(8), self.ep_sq.add(8))
+            } else {
+                (self.ep_sq.add(8), self.ep_sq.sub(8))
+            };
+            let capturers = (pushed.as_set().west_one() | pushed.as_set().east_one())
+                & self.pieces.piece_bb(Piece::new(self.side, PieceType::PAWN));
+            if self.piece_at(pushed) != Piece::new(self.side.flip(), PieceType::PAWN)
+                || self.piece_at(self.ep_sq) != Piece::EMPTY
+                || self.piece_at(origin) != Piece::EMPTY
+            {
+                dropped.push(format!("en passant square {}, as no pawn can have just moved past it", self.ep_sq));
+                self.ep_sq = Square::NO_SQUARE;
+            } else if capturers.is_empty() {
+                self.ep_sq = Square::NO_SQUARE;
+            }
+        }
+
+        dropped
+    }
+
+    #[cfg(any(debug_assertions, feature = "verify"))]
     #[allow(clippy::cognitive_complexity, clippy::too_many_lines)]
     pub fn check_validity(&self) -> Result<(), PositionValidityError> {
         #![allow(clippy::similar_names, clippy::cast_possible_truncation)]
@@ -42,6 +137,21 @@ impl Board {
         if self.generate_pos_key() != self.key {
             return Err(format!("key is corrupt: expected {:?}, got {:?}", self.generate_pos_key(), self.key));
         }
+        if self.generate_pawn_key() != self.pawn_key {
+            return Err(format!(
+                "pawn key is corrupt: expected {:?}, got {:?}",
+                self.generate_pawn_key(),
+                self.pawn_key
+            ));
+        }
+
+        if self.generate_threats(self.side.flip()) != self.threats {
+            return Err(format!(
+                "threats are corrupt: expected {:?}, got {:?}",
+                self.generate_threats(self.side.flip()),
+                self.threats
+            ));
+        }
 
         if !(self.ep_sq == Square::NO_SQUARE
             || (self.ep_sq.rank() == Rank::RANK_6 && self.side == Colour::WHITE)
@@ -90,4 +200,38 @@ impl Board {
 
         Ok(())
     }
+
+    /// Recomputes everything that `make_move` maintains incrementally, and panics
+    /// with a reproducible position command if any of it has drifted.
+    #[cfg(any(debug_assertions, feature = "verify"))]
+    pub fn verify_incremental_state(&self) {
+        if let Err(e) = self.check_validity() {
+            panic!("{e}\nreproduce with: {}", self.position_command());
+        }
+    }
+
+    /// Rebuilds the game so far as a UCI position command, by winding
+    /// the board back to the first state in its history.
+    #[cfg(any(debug_assertions, feature = "verify"))]
+    pub fn position_command(&self) -> String {
+        // this doesn't use unmake_move, as the board is probably corrupt.
+        let mut root = self.clone();
+        let mut moves = Vec::new();
+        while let Some(undo) = root.history.pop() {
+            root.side = root.side.flip();
+            root.ply -= 1;
+            root.ep_sq = undo.ep_square;
+            root.key = undo.key;
+            root.threats = undo.threats;
+            root.castle_perm = undo.castle_perm;
+            root.fifty_move_counter = undo.fifty_move_counter;
+            root.pawn_key = undo.pawn_key;
+            if !undo.mov.is_null() {
+                root.unmake_pieces(undo.mov, undo.captured);
+            }
+            moves.push(if undo.mov.is_null() { "0000".to_string() } else { undo.mov.to_string() });
+        }
+        moves.reverse();
+        format!("position fen {} moves {}", root.fen(), moves.join(" "))
+    }
 }
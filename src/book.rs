@@ -0,0 +1,326 @@
+// Building Polyglot opening books from PGN game collections.
+//
+// Every position from the first few plies of each game is hashed with the Polyglot keys, and
+// the move played from it gets points for the side that played it: two for a win and one for
+// a draw. A move's points become its weight in the book, so a GUI or engine using the book
+// picks moves in proportion to how well they did. Moves that never scored are left out.
+//
+// A book file is a sequence of 16-byte big-endian entries, sorted by key:
+//   key (u64), move (u16), weight (u16), learn (u32)
+// where the move packs the to-file, to-rank, from-file, from-rank, and promotion piece
+// into three bits each, from the least significant end, and castling is written as the king
+// capturing its own rook.
+
+mod keys;
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    str::FromStr,
+};
+
+use crate::{
+    board::Board,
+    chessmove::Move,
+    piece::{Colour, Piece, PieceType},
+    util::Square,
+};
+
+use self::keys::{CASTLING, EN_PASSANT, PIECE, RANDOM64, TURN};
+
+/// A game result, as given in a PGN "Result" tag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    WhiteWin,
+    BlackWin,
+    Draw,
+}
+
+impl FromStr for Outcome {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "1-0" => Ok(Self::WhiteWin),
+            "0-1" => Ok(Self::BlackWin),
+            "1/2-1/2" => Ok(Self::Draw),
+            _ => Err(format!("unknown result \"{s}\", expected one of 1-0, 0-1, 1/2-1/2")),
+        }
+    }
+}
+
+impl Outcome {
+    /// The points that a move by `side` earns in a game with this result.
+    const fn points_for(self, side: Colour) -> u32 {
+        match (self, side) {
+            (Self::Draw, _) => 1,
+            (Self::WhiteWin, Colour::WHITE) | (Self::BlackWin, Colour::BLACK) => 2,
+            _ => 0,
+        }
+    }
+}
+
+/// Configuration options for building a book.
+pub struct MakebookOptions<'a> {
+    pub pgn: &'a Path,
+    pub output: &'a Path,
+    /// Only moves from this many plies into each game are added.
+    pub max_ply: usize,
+    /// The results of the games to take moves from.
+    pub results: &'a [Outcome],
+    /// Moves played in fewer games than this are left out.
+    pub min_games: u32,
+}
+
+/// How often a move was played from a position, and the points it earned.
+#[derive(Default, Clone, Copy)]
+struct Tally {
+    games: u32,
+    points: u32,
+}
+
+/// The Polyglot hash of a position.
+pub fn polyglot_key(board: &Board) -> u64 {
+    let mut key = 0;
+    for sq in (0..64).map(Square::new) {
+        let piece = board.piece_at(sq);
+        if !piece.is_empty() {
+            let kind = 2 * piece.piece_type().index() + usize::from(piece.colour() == Colour::WHITE);
+            key ^= RANDOM64[PIECE + 64 * kind + sq.index()];
+        }
+    }
+    let rights = board.castling_rights();
+    for (i, rook) in [rights.wk, rights.wq, rights.bk, rights.bq].into_iter().enumerate() {
+        if rook != Square::NO_SQUARE {
+            key ^= RANDOM64[CASTLING + i];
+        }
+    }
+    // the en passant file only counts if a pawn of the side to move stands ready to capture.
+    let ep = board.ep_sq();
+    if ep != Square::NO_SQUARE {
+        let us = board.turn();
+        let rank = if us == Colour::WHITE { 4 } else { 3 };
+        let pawn = Piece::new(us, PieceType::PAWN);
+        let file = ep.file();
+        let capturers = [file.checked_sub(1), (file < 7).then_some(file + 1)];
+        if capturers.into_iter().flatten().any(|f| board.piece_at(Square::from_rank_file(rank, f)) == pawn) {
+            key ^= RANDOM64[EN_PASSANT + usize::from(file)];
+        }
+    }
+    if board.turn() == Colour::WHITE {
+        key ^= RANDOM64[TURN];
+    }
+    key
+}
+
+/// Encodes a move the way Polyglot books store it.
+fn polyglot_move(m: Move) -> u16 {
+    let (from, to) = (m.from(), m.to());
+    let promotion = if m.is_promo() { m.promotion_type().inner() } else { 0 };
+    u16::from(to.file())
+        | u16::from(to.rank()) << 3
+        | u16::from(from.file()) << 6
+        | u16::from(from.rank()) << 9
+        | u16::from(promotion) << 12
+}
+
+/// A game from a PGN file: the tags that matter here, and the unparsed movetext.
+#[derive(Default)]
+struct PgnGame {
+    result: Option<Outcome>,
+    fen: Option<String>,
+    /// Whether the game is standard chess, as far as the "Variant" tag says.
+    standard: bool,
+    movetext: String,
+}
+
+/// Reads the games from a PGN file, one at a time.
+fn read_games(reader: impl BufRead, mut callback: impl FnMut(&PgnGame)) -> io::Result<()> {
+    let new_game = || PgnGame { standard: true, ..PgnGame::default() };
+    let mut game = new_game();
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim();
+        if let Some(tag) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            // a tag after movetext starts the next game.
+            if !game.movetext.trim().is_empty() {
+                callback(&game);
+                game = new_game();
+            }
+            let Some((name, value)) = tag.split_once(' ') else {
+                continue;
+            };
+            let value = value.trim().trim_matches('"');
+            match name {
+                "Result" => game.result = value.parse().ok(),
+                "FEN" => game.fen = Some(value.to_string()),
+                "Variant" => game.standard = value.eq_ignore_ascii_case("standard"),
+                _ => {}
+            }
+        } else {
+            game.movetext.push_str(line);
+            game.movetext.push('\n');
+        }
+    }
+    if !game.movetext.trim().is_empty() {
+        callback(&game);
+    }
+    Ok(())
+}
+
+/// The moves of the main line of some PGN movetext, in SAN, skipping comments,
+/// variations, move numbers, and annotation glyphs.
+fn mainline_moves(movetext: &str) -> Vec<&str> {
+    let mut moves = Vec::new();
+    let mut variation_depth = 0usize;
+    let mut rest = movetext.trim_start();
+    while let Some(c) = rest.chars().next() {
+        match c {
+            '{' => rest = rest.find('}').map_or("", |i| &rest[i + 1..]),
+            ';' => rest = rest.find('\n').map_or("", |i| &rest[i + 1..]),
+            '(' => {
+                variation_depth += 1;
+                rest = &rest[1..];
+            }
+            ')' => {
+                variation_depth = variation_depth.saturating_sub(1);
+                rest = &rest[1..];
+            }
+            _ => {
+                let end = rest.find(|c: char| c.is_whitespace() || "{;()".contains(c)).unwrap_or(rest.len());
+                let mut token = &rest[..end];
+                rest = &rest[end..];
+                if variation_depth == 0 && matches!(token, "1-0" | "0-1" | "1/2-1/2" | "*") {
+                    break;
+                }
+                // strip a move number, which may run straight into the move, as in "12.Nf3" or "12...Nf6".
+                if let Some(i) = token.find('.') {
+                    if token[..i].bytes().all(|b| b.is_ascii_digit()) {
+                        token = token[i..].trim_start_matches('.');
+                    }
+                }
+                if variation_depth == 0 && !token.is_empty() && !token.starts_with('$') {
+                    moves.push(token);
+                }
+            }
+        }
+        rest = rest.trim_start();
+    }
+    moves
+}
+
+/// Reads a PGN file and writes a Polyglot book of the moves played.
+pub fn run_makebook(options: &MakebookOptions) -> Result<(), String> {
+    let input = File::open(options.pgn).map_err(|e| format!("failed to open {}: {e}", options.pgn.display()))?;
+    let mut tallies = HashMap::<(u64, u16), Tally>::new();
+    let (mut read, mut used) = (0usize, 0usize);
+    read_games(BufReader::new(input), |game| {
+        read += 1;
+        let Some(outcome) = game.result.filter(|r| options.results.contains(r)) else {
+            return;
+        };
+        if !game.standard {
+            return;
+        }
+        let mut board = Board::default();
+        if let Some(fen) = &game.fen {
+            if board.set_from_fen(fen).is_err() {
+                return;
+            }
+        }
+        used += 1;
+        for san in mainline_moves(&game.movetext).into_iter().take(options.max_ply) {
+            let Ok(m) = board.parse_san(san) else {
+                eprintln!("game {read}: can't play \"{san}\" in {}, skipping the rest of the game", board.fen());
+                break;
+            };
+            let tally = tallies.entry((polyglot_key(&board), polyglot_move(m))).or_default();
+            tally.games += 1;
+            tally.points += outcome.points_for(board.turn());
+            board.zero_height();
+            board.make_move_simple(m);
+        }
+    })
+    .map_err(|e| format!("failed to read {}: {e}", options.pgn.display()))?;
+
+    let mut entries = tallies
+        .into_iter()
+        .filter(|(_, tally)| tally.games >= options.min_games && tally.points > 0)
+        .map(|((key, m), tally)| (key, m, tally.points))
+        .collect::<Vec<_>>();
+    // books are searched by key, and readers expect the best moves for a position first.
+    entries.sort_unstable_by(|a, b| a.0.cmp(&b.0).then(b.2.cmp(&a.2)).then(a.1.cmp(&b.1)));
+
+    let output =
+        File::create(options.output).map_err(|e| format!("failed to create {}: {e}", options.output.display()))?;
+    let mut output = BufWriter::new(output);
+    write_entries(&mut output, &entries).map_err(|e| format!("failed to write {}: {e}", options.output.display()))?;
+    println!("Read {read} games, used {used}, and wrote {} entries to {}", entries.len(), options.output.display());
+    Ok(())
+}
+
+/// Writes book entries, which must be sorted by key with the heaviest moves first.
+/// Weights that don't fit in sixteen bits are scaled down along with the rest of their position's.
+fn write_entries(output: &mut impl Write, entries: &[(u64, u16, u32)]) -> io::Result<()> {
+    for position in entries.chunk_by(|a, b| a.0 == b.0) {
+        let heaviest = u64::from(position[0].2);
+        for &(key, m, points) in position {
+            let weight = if heaviest > u64::from(u16::MAX) {
+                (u64::from(points) * u64::from(u16::MAX) / heaviest).max(1)
+            } else {
+                u64::from(points)
+            };
+            output.write_all(&key.to_be_bytes())?;
+            output.write_all(&m.to_be_bytes())?;
+            output.write_all(&u16::try_from(weight).unwrap_or(u16::MAX).to_be_bytes())?;
+            output.write_all(&0u32.to_be_bytes())?;
+        }
+    }
+    output.flush()
+}
+
+mod tests {
+    #[test]
+    fn polyglot_keys_match_the_reference() {
+        use super::polyglot_key;
+        use crate::board::Board;
+
+        // the test vectors from the Polyglot book format documentation.
+        let mut board = Board::default();
+        assert_eq!(polyglot_key(&board), 0x463B_9618_1691_FC9C);
+        for (san, key) in [
+            ("e4", 0x823C_9B50_FD11_4196),
+            ("d5", 0x0756_B944_61C5_0FB0),
+            ("e5", 0x662F_AFB9_65DB_29D4),
+            ("f5", 0x22A4_8B5A_8E47_FF78),
+            ("Ke2", 0x652A_607C_A3F2_42C1),
+            ("Kf7", 0x00FD_D303_C946_BDD9),
+        ] {
+            let m = board.parse_san(san).unwrap();
+            board.make_move_simple(m);
+            assert_eq!(polyglot_key(&board), key, "after {san}");
+        }
+    }
+
+    #[test]
+    fn reads_pgn_mainlines() {
+        use super::{mainline_moves, polyglot_move, read_games, Outcome};
+        use crate::board::Board;
+
+        let pgn =
+            "[Event \"?\"]\n[Result \"1-0\"]\n\n1. e4 {best by test} e5 (1... c5 2. Nf3) 2.Nf3 $1 Nc6\n3. Bb5 1-0\n\n\
+                   [Result \"*\"]\n[Variant \"Chess960\"]\n\n1. d4 *\n";
+        let mut games = Vec::new();
+        read_games(pgn.as_bytes(), |game| games.push((game.result, game.standard, game.movetext.clone()))).unwrap();
+        assert_eq!(games.len(), 2);
+        assert_eq!((games[0].0, games[0].1), (Some(Outcome::WhiteWin), true));
+        assert_eq!(mainline_moves(&games[0].2), ["e4", "e5", "Nf3", "Nc6", "Bb5"]);
+        assert_eq!((games[1].0, games[1].1), (None, false));
+
+        let board = Board::from_fen("r3k2r/1P6/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        assert_eq!(polyglot_move(board.parse_san("O-O").unwrap()), 0x0107);
+        assert_eq!(polyglot_move(board.parse_san("bxa8=Q").unwrap()), 0x4C78);
+    }
+}
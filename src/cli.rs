@@ -4,9 +4,25 @@ use clap::Parser;
 #[clap(author, version, about)]
 #[allow(clippy::struct_excessive_bools, clippy::option_option)]
 pub struct Cli {
-    /// Run the perft test suite
+    /// Run the perft test suite, or the positions in an EPD file of `<FEN> ;D1 <NODES> ;D2 <NODES> ...` lines.
+    #[clap(long, value_name = "PATH")]
+    pub perfttest: Option<Option<std::path::PathBuf>>,
+    /// Speak CECP, the protocol used by xboard and winboard, instead of UCI.
     #[clap(long)]
-    pub perfttest: bool,
+    pub xboard: bool,
+    /// Use the human-readable output format even when not attached to a terminal.
+    #[clap(long)]
+    pub pretty: bool,
+    /// Record all protocol input and output, with timestamps, to a log file.
+    #[clap(long, value_name = "PATH")]
+    pub log: Option<std::path::PathBuf>,
+    /// A file of default UCI option values to apply at startup, as `Name = value` lines.
+    /// Defaults to viridithas.toml in the working directory, if it exists.
+    #[clap(long, value_name = "PATH")]
+    pub config: Option<std::path::PathBuf>,
+    /// Seed the random number generator, so that datagen, SPRT openings, and move variety are reproducible.
+    #[clap(long, value_name = "N")]
+    pub seed: Option<u64>,
     /// Scan a packed data record and report statistics
     #[clap(short, long)]
     pub dataset_stats: Option<std::path::PathBuf>,
@@ -25,6 +41,10 @@ pub struct Cli {
     /// Generate training data for the NNUE.
     #[clap(long)]
     pub datagen: Option<Option<String>>,
+    /// A file of opening positions (FEN or EPD, one per line) for --datagen.
+    /// Games start from a random one of these instead of the standard or DFRC starting positions.
+    #[clap(long, value_name = "PATH", requires = "datagen")]
+    pub datagen_book: Option<std::path::PathBuf>,
     /// Splat a binary game record into binary records.
     #[clap(long)]
     pub splat: Option<std::path::PathBuf>,
@@ -38,14 +58,175 @@ pub struct Cli {
     /// Limit the number of games to convert.
     #[clap(long, value_name = "N")]
     pub limit: Option<usize>,
-    /// Output node benchmark for OpenBench.
-    /// Implemented as a subcommand because that's what OpenBench expects.
+    /// Subcommands, such as the node benchmark for OpenBench.
+    /// The benchmark is a subcommand because that's what OpenBench expects.
     #[clap(subcommand)]
-    pub bench: Option<Bench>,
+    pub subcommand: Option<Subcommands>,
 }
 
 #[derive(Parser)]
-pub enum Bench {
-    /// Output node benchmark for OpenBench.
-    Bench,
+pub enum Subcommands {
+    /// Output node benchmark for OpenBench, or time one part of the engine.
+    Bench {
+        /// The part of the engine to benchmark on its own: movegen, eval, makemove, or see.
+        target: Option<crate::bench::Subsystem>,
+    },
+    /// Check that the evaluation is the same for positions and their colour-flipped mirrors.
+    Selftest,
+    /// Run the UCI commands in a file, or on stdin if the path is "-", and exit.
+    Script {
+        /// The file of commands, one per line.
+        path: std::path::PathBuf,
+    },
+    /// Search the positions of an EPD test suite, and report how many were solved.
+    Testsuite {
+        /// The EPD file, with "bm" or "am" opcodes on every record.
+        path: std::path::PathBuf,
+        /// The time to search each position for, in milliseconds.
+        #[clap(long, value_name = "MS", default_value_t = 1000)]
+        movetime: u64,
+        /// The number of search threads.
+        #[clap(long, value_name = "N", default_value_t = 1)]
+        threads: usize,
+        /// The hash table size, in megabytes.
+        #[clap(long, value_name = "MB", default_value_t = 16)]
+        hash: usize,
+    },
+    /// Play a match against another UCI engine, and report the results.
+    Match {
+        /// The opponent's executable.
+        opponent: std::path::PathBuf,
+        /// A file of opening positions (FEN or EPD, one per line).
+        /// Without one, games start from random openings.
+        #[clap(long, value_name = "PATH")]
+        book: Option<std::path::PathBuf>,
+        /// The time control, as seconds+increment.
+        #[clap(long, value_name = "TC", default_value = "10+0.1")]
+        tc: String,
+        /// The number of games to play. Openings are played twice with colours reversed,
+        /// so this is rounded up to an even number.
+        #[clap(long, value_name = "N", default_value_t = 20)]
+        games: usize,
+        /// Write the games to this file as PGN.
+        #[clap(long, value_name = "PATH")]
+        pgn: Option<std::path::PathBuf>,
+        /// The hash table size for both engines, in megabytes.
+        #[clap(long, value_name = "MB", default_value_t = 16)]
+        hash: usize,
+    },
+    /// Run an SPRT of modified search parameters against the defaults.
+    Sprt {
+        /// The parameters under test, as comma-separated NAME=VALUE pairs, e.g. `RFP_MARGIN=80,LMP_DEPTH=7`.
+        params: String,
+        /// Parameters for the baseline side, in the same format.
+        #[clap(long, value_name = "PARAMS")]
+        base: Option<String>,
+        /// A file of opening positions (FEN or EPD, one per line).
+        /// Without one, games start from random openings.
+        #[clap(long, value_name = "PATH")]
+        book: Option<std::path::PathBuf>,
+        /// The node limit per move.
+        #[clap(long, value_name = "N", default_value_t = 25_000)]
+        nodes: u64,
+        /// The lower Elo bound.
+        #[clap(long, default_value_t = 0.0, allow_negative_numbers = true)]
+        elo0: f64,
+        /// The upper Elo bound.
+        #[clap(long, default_value_t = 5.0, allow_negative_numbers = true)]
+        elo1: f64,
+        /// The maximum number of games to play.
+        #[clap(long, value_name = "N", default_value_t = 100_000)]
+        max_games: usize,
+        /// The number of threads to play games on.
+        #[clap(long, value_name = "N", default_value_t = 1)]
+        threads: usize,
+    },
+    /// Play the engine against itself, and write the games as PGN with the score,
+    /// depth and time of every move in comments.
+    Selfplay {
+        /// The time control, as seconds+increment. Defaults to 10+0.1.
+        #[clap(long, value_name = "TC", conflicts_with = "nodes")]
+        tc: Option<String>,
+        /// Search a fixed number of nodes per move instead of playing on a clock.
+        #[clap(long, value_name = "N")]
+        nodes: Option<u64>,
+        /// The number of games to play.
+        #[clap(long, value_name = "N", default_value_t = 10)]
+        games: usize,
+        /// A file of opening positions (FEN or EPD, one per line).
+        /// Without one, games start from random openings.
+        #[clap(long, value_name = "PATH")]
+        book: Option<std::path::PathBuf>,
+        /// The file to write the games to.
+        #[clap(long, value_name = "PATH", default_value = "selfplay.pgn")]
+        pgn: std::path::PathBuf,
+        /// The hash table size, in megabytes.
+        #[clap(long, value_name = "MB", default_value_t = 16)]
+        hash: usize,
+    },
+    /// Convert training data between formats, filtering positions on the way.
+    Convert {
+        /// The file to read positions from.
+        input: std::path::PathBuf,
+        /// The file to write positions to.
+        output: std::path::PathBuf,
+        /// The format of the input: games, text, or marlinformat.
+        #[clap(long, value_name = "FORMAT", default_value = "games")]
+        from: crate::datagen::convert::Format,
+        /// The format of the output: text, marlinformat, or bulletformat.
+        #[clap(long, value_name = "FORMAT", default_value = "bulletformat")]
+        to: crate::datagen::convert::Format,
+        /// Drop positions where the side to move is in check.
+        #[clap(long)]
+        no_checks: bool,
+        /// Drop positions where the move played was a capture or promotion (game records only).
+        #[clap(long)]
+        no_captures: bool,
+        /// Drop positions with an absolute score above this, in centipawns.
+        #[clap(long, value_name = "CP")]
+        max_score: Option<i32>,
+        /// Stop after writing this many positions.
+        #[clap(long, value_name = "N")]
+        limit: Option<usize>,
+    },
+    /// Build a Polyglot opening book from the games in a PGN file. Each move is weighted
+    /// by the results it got for the side that played it.
+    Makebook {
+        /// The PGN file to read games from.
+        pgn: std::path::PathBuf,
+        /// The file to write the book to.
+        #[clap(long, value_name = "PATH", default_value = "book.bin")]
+        output: std::path::PathBuf,
+        /// Only take moves from this many plies into each game.
+        #[clap(long, value_name = "N", default_value_t = 24)]
+        max_ply: usize,
+        /// Only take moves from games with these results, comma-separated.
+        #[clap(long, value_name = "RESULTS", value_delimiter = ',', default_value = "1-0,0-1,1/2-1/2")]
+        results: Vec<crate::book::Outcome>,
+        /// Leave out moves played in fewer than this many games.
+        #[clap(long, value_name = "N", default_value_t = 1)]
+        min_games: u32,
+    },
+    /// Count the leaf nodes of the move tree from a position.
+    #[clap(allow_missing_positional = true)]
+    Perft {
+        /// The position to search from, defaulting to the starting position.
+        fen: Option<String>,
+        /// The depth to search to.
+        depth: usize,
+        /// Report the node count under each root move.
+        #[clap(long)]
+        divide: bool,
+        /// The number of threads to split the root moves between.
+        #[clap(long, value_name = "N", default_value_t = 1)]
+        threads: usize,
+    },
+}
+
+/// Parses a time control in the form "seconds+increment", such as "10+0.1" or "60".
+pub fn parse_time_control(tc: &str) -> Option<(std::time::Duration, std::time::Duration)> {
+    let (time, increment) = tc.split_once('+').unwrap_or((tc, "0"));
+    let time = std::time::Duration::try_from_secs_f64(time.parse().ok()?).ok()?;
+    let increment = std::time::Duration::try_from_secs_f64(increment.parse().ok()?).ok()?;
+    Some((time, increment))
 }
@@ -0,0 +1,121 @@
+// Cuckoo tables for detecting upcoming repetitions, after
+// Marcel van Kervinck's "Detecting upcoming repetitions" (2013),
+// by way of the implementation in Stockfish.
+//
+// Every reversible move of a non-pawn piece is stored in the table,
+// keyed by the change it makes to the zobrist hash. If the difference
+// between the current key and the key of a prior position is present
+// in the table, then a single move connects the two positions.
+
+#![allow(clippy::cast_possible_truncation)]
+
+use crate::{
+    cfor,
+    lookups::init_hash_keys,
+    piece::{Colour, Piece, PieceType},
+    util::Square,
+};
+
+const TABLE_SIZE: usize = 0x2000;
+
+const fn h1(key: u64) -> usize {
+    (key & 0x1FFF) as usize
+}
+
+const fn h2(key: u64) -> usize {
+    ((key >> 16) & 0x1FFF) as usize
+}
+
+/// Empty-board reachability, in the manner of the attack tables.
+/// We can't use the magic tables here, as they aren't available at compile-time.
+const fn reachable(piece_type: PieceType, from: Square, to: Square) -> bool {
+    let (df, dr) = (to.file().abs_diff(from.file()), to.rank().abs_diff(from.rank()));
+    match piece_type {
+        PieceType::KNIGHT => (df == 1 && dr == 2) || (df == 2 && dr == 1),
+        PieceType::BISHOP => df == dr && df != 0,
+        PieceType::ROOK => (df == 0) != (dr == 0),
+        PieceType::QUEEN => (df == dr && df != 0) || ((df == 0) != (dr == 0)),
+        PieceType::KING => max!(df, dr) == 1,
+        _ => false,
+    }
+}
+
+#[allow(clippy::large_stack_arrays)]
+const fn init_cuckoo_tables() -> ([u64; TABLE_SIZE], [(Square, Square); TABLE_SIZE], usize) {
+    let mut keys = [0; TABLE_SIZE];
+    let mut squares = [(Square::NO_SQUARE, Square::NO_SQUARE); TABLE_SIZE];
+    let mut count = 0;
+    let (piece_keys, _, side_key) = init_hash_keys();
+    let pieces = [PieceType::KNIGHT, PieceType::BISHOP, PieceType::ROOK, PieceType::QUEEN, PieceType::KING];
+    cfor!(let mut colour = 0; colour < 2; colour += 1; {
+        cfor!(let mut pt = 0; pt < pieces.len(); pt += 1; {
+            let piece = Piece::new(Colour::new(colour == 1), pieces[pt]);
+            cfor!(let mut s1 = 0; s1 < 64; s1 += 1; {
+                cfor!(let mut s2 = s1 + 1; s2 < 64; s2 += 1; {
+                    let from = Square::new(s1);
+                    let to = Square::new(s2);
+                    if reachable(pieces[pt], from, to) {
+                        let mut key =
+                            piece_keys[piece.index()][from.index()] ^ piece_keys[piece.index()][to.index()] ^ side_key;
+                        let mut mv = (from, to);
+                        let mut i = h1(key);
+                        // insert, kicking out any existing entry to its alternative slot.
+                        loop {
+                            let displaced_key = keys[i];
+                            let displaced_mv = squares[i];
+                            keys[i] = key;
+                            squares[i] = mv;
+                            key = displaced_key;
+                            mv = displaced_mv;
+                            if key == 0 {
+                                break;
+                            }
+                            i = if i == h1(key) { h2(key) } else { h1(key) };
+                        }
+                        count += 1;
+                    }
+                });
+            });
+        });
+    });
+    (keys, squares, count)
+}
+
+static KEYS: [u64; TABLE_SIZE] = init_cuckoo_tables().0;
+static SQUARES: [(Square, Square); TABLE_SIZE] = init_cuckoo_tables().1;
+
+/// Looks up the reversible move that changes a position's key by `key_diff`,
+/// returning its two squares if such a move exists.
+pub fn lookup(key_diff: u64) -> Option<(Square, Square)> {
+    let i = h1(key_diff);
+    if KEYS[i] == key_diff {
+        return Some(SQUARES[i]);
+    }
+    let i = h2(key_diff);
+    if KEYS[i] == key_diff {
+        return Some(SQUARES[i]);
+    }
+    None
+}
+
+mod tests {
+    #[test]
+    fn cuckoo_table_has_all_reversible_moves() {
+        // the number of reversible non-pawn moves on an empty board,
+        // for both colours, counting each pair of squares only once.
+        assert_eq!(super::init_cuckoo_tables().2, 3668);
+    }
+
+    #[test]
+    fn cuckoo_lookup_finds_knight_move() {
+        use crate::{
+            lookups::{PIECE_KEYS, SIDE_KEY},
+            piece::Piece,
+            util::Square,
+        };
+        let diff = PIECE_KEYS[Piece::WN.index()][Square::G1.index()]
+            ^ PIECE_KEYS[Piece::WN.index()][Square::F3.index()]
+            ^ SIDE_KEY;
+        assert_eq!(super::lookup(diff), Some((Square::G1, Square::F3)));
+    }
+}
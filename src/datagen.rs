@@ -1,5 +1,6 @@
 #![allow(dead_code)]
 
+pub mod convert;
 mod dataformat;
 
 use std::{
@@ -16,7 +17,7 @@ use std::{
 };
 
 use bulletformat::ChessBoard;
-use rand::Rng;
+use rand::{Rng, SeedableRng};
 
 use crate::{
     board::{
@@ -26,7 +27,9 @@ use crate::{
     chessmove::Move,
     datagen::dataformat::Game,
     piece::{Colour, PieceType},
-    searchinfo::SearchInfo,
+    rng::{self, Xoshiro},
+    searchinfo::{SearchInfo, Silent},
+    sprt,
     tablebases::{self, probe::WDL},
     threadlocal::ThreadData,
     timemgmt::{SearchLimit, TimeManager},
@@ -48,6 +51,27 @@ enum DataGenLimit {
     Nodes(u64),
 }
 
+/// Where self-play games start from.
+#[derive(Clone, Debug, Hash)]
+enum Openings {
+    /// The standard starting position.
+    Classical,
+    /// A random double Fischer random chess starting position.
+    Dfrc,
+    /// A random position from a file of FENs or EPD records.
+    Book(PathBuf),
+}
+
+impl Display for Openings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Classical => write!(f, "classical"),
+            Self::Dfrc => write!(f, "dfrc"),
+            Self::Book(path) => write!(f, "book {}", path.display()),
+        }
+    }
+}
+
 /// Configuration options for Viri's self-play data generation.
 #[derive(Clone, Debug, Hash)]
 struct DataGenOptions {
@@ -61,12 +85,14 @@ struct DataGenOptions {
     use_nnue: bool,
     // The depth or node limit for searches.
     limit: DataGenLimit,
-    // Whether to generate DFRC data.
-    generate_dfrc: bool,
+    // Where games start from, before the random moves are made.
+    openings: Openings,
     // log level
     log_level: u8,
     // position count limit
     position_count_limit: Option<u64>,
+    // The seed for the random openings, drawn at startup if not set.
+    seed: Option<u64>,
 }
 
 impl DataGenOptions {
@@ -78,9 +104,10 @@ impl DataGenOptions {
             tablebases_path: None,
             use_nnue: true,
             limit: DataGenLimit::Depth(8),
-            generate_dfrc: true,
+            openings: Openings::Dfrc,
             log_level: 1,
             position_count_limit: None,
+            seed: None,
         }
     }
 
@@ -94,7 +121,11 @@ impl DataGenOptions {
                 .as_ref()
                 .map_or_else(|| "no_tb".into(), |tablebases_path| tablebases_path.to_string_lossy()),
             if self.use_nnue { "nnue" } else { "hce" },
-            if self.generate_dfrc { "dfrc" } else { "classical" },
+            match self.openings {
+                Openings::Classical => "classical",
+                Openings::Dfrc => "dfrc",
+                Openings::Book(_) => "book",
+            },
             match self.limit {
                 DataGenLimit::Depth(depth) => format!("d{depth}"),
                 DataGenLimit::Nodes(nodes) => format!("n{nodes}"),
@@ -103,7 +134,7 @@ impl DataGenOptions {
     }
 }
 
-pub fn gen_data_main(cli_config: Option<&str>) {
+pub fn gen_data_main(cli_config: Option<&str>, book: Option<&Path>) {
     assert!(
         !cfg!(not(feature = "datagen")),
         "Data generation is not enabled, please enable the 'datagen' feature to use this functionality."
@@ -115,7 +146,7 @@ pub fn gen_data_main(cli_config: Option<&str>) {
     })
     .expect("Failed to set Ctrl-C handler");
 
-    let options: DataGenOptions = cli_config.map_or_else(
+    let mut options: DataGenOptions = cli_config.map_or_else(
         || {
             let options = DataGenOptions::new();
             show_boot_info(&options);
@@ -124,7 +155,26 @@ pub fn gen_data_main(cli_config: Option<&str>) {
         |s| s.parse().expect("Failed to parse CLI config, expected short def string (e.g. '100g-2t-<TBPATH>-nnue-d8')"),
     );
 
-    CHESS960.store(options.generate_dfrc, Ordering::SeqCst);
+    options.seed = Some(options.seed.unwrap_or_else(rng::user_seed));
+    if let Some(book) = book {
+        options.openings = Openings::Book(book.to_path_buf());
+    }
+    let book = match &options.openings {
+        Openings::Book(path) => match sprt::load_book(path) {
+            Ok(book) if book.is_empty() => {
+                eprintln!("Opening book {} contains no positions", path.display());
+                return;
+            }
+            Ok(book) => book,
+            Err(e) => {
+                eprintln!("{e}");
+                return;
+            }
+        },
+        _ => Vec::new(),
+    };
+
+    CHESS960.store(matches!(options.openings, Openings::Dfrc), Ordering::SeqCst);
     FENS_GENERATED.store(0, Ordering::SeqCst);
 
     if options.log_level > 0 {
@@ -165,8 +215,9 @@ pub fn gen_data_main(cli_config: Option<&str>) {
         let thread_handles = (0..options.num_threads)
             .map(|id| {
                 let opt_ref = &options;
+                let book_ref = &book;
                 let path_ref = &data_dir;
-                s.spawn(move || generate_on_thread(id, opt_ref, path_ref))
+                s.spawn(move || generate_on_thread(id, opt_ref, book_ref, path_ref))
             })
             .collect::<Vec<_>>();
         for handle in thread_handles {
@@ -202,12 +253,15 @@ fn print_game_stats(counters: &HashMap<GameOutcome, u64>) {
 }
 
 #[allow(clippy::cognitive_complexity)]
-fn generate_on_thread(id: usize, options: &DataGenOptions, data_dir: &Path) -> HashMap<GameOutcome, u64> {
+fn generate_on_thread(
+    id: usize,
+    options: &DataGenOptions,
+    book: &[Board],
+    data_dir: &Path,
+) -> HashMap<GameOutcome, u64> {
     #![allow(clippy::cast_precision_loss, clippy::too_many_lines, clippy::cast_possible_truncation)]
-    // this rng is different between each thread
-    // (https://rust-random.github.io/book/guide-parallel.html)
-    // so no worries :3
-    let mut rng = rand::thread_rng();
+    // each thread gets its own stream from the run's seed.
+    let mut rng = Xoshiro::seed_from_u64(options.seed.unwrap_or_default().wrapping_add(id as u64));
     let mut board = Board::default();
     let mut tt = TT::new();
     tt.resize(16 * MEGABYTE);
@@ -218,7 +272,7 @@ fn generate_on_thread(id: usize, options: &DataGenOptions, data_dir: &Path) -> H
         DataGenLimit::Nodes(nodes) => SearchLimit::SoftNodes { soft_limit: nodes, hard_limit: nodes * 8 },
     });
     let nodes = AtomicU64::new(0);
-    let mut info = SearchInfo { time_manager, print_to_stdout: false, ..SearchInfo::new(&stopped, &nodes) };
+    let mut info = SearchInfo { time_manager, sink: &Silent, ..SearchInfo::new(&stopped, &nodes) };
 
     let n_games_to_run = std::cmp::max(options.num_games / options.num_threads, 1);
 
@@ -273,10 +327,10 @@ fn generate_on_thread(id: usize, options: &DataGenOptions, data_dir: &Path) -> H
             }
         }
         // reset everything: board, thread data, tt, search info
-        if options.generate_dfrc {
-            board.set_dfrc_idx(rand::Rng::gen_range(&mut rng, 0..960 * 960));
-        } else {
-            board.set_startpos();
+        match options.openings {
+            Openings::Classical => board.set_startpos(),
+            Openings::Dfrc => board.set_dfrc_idx(rand::Rng::gen_range(&mut rng, 0..960 * 960)),
+            Openings::Book(_) => board = book[rng.gen_range(0..book.len())].clone(),
         }
         thread_data.nnue.reinit_from(&board);
         tt.clear(1);
@@ -289,8 +343,10 @@ fn generate_on_thread(id: usize, options: &DataGenOptions, data_dir: &Path) -> H
         if options.log_level > 2 {
             eprintln!("Making random moves...");
         }
-        // pick either 8 or 9 random moves (to balance out the win/loss/draw ratio)
-        let max = if rng.gen_bool(0.5) { 8 } else { 9 };
+        // pick either 8 or 9 random moves (to balance out the win/loss/draw ratio),
+        // or just 2 or 3 from a book position, which is already out of the opening.
+        let random_plies = if matches!(options.openings, Openings::Book(_)) { 2 } else { 8 };
+        let max = random_plies + usize::from(rng.gen_bool(0.5));
         for _ in 0..max {
             let res = board.make_random_move(&mut rng, &mut thread_data);
             if res.is_none() {
@@ -520,11 +576,19 @@ fn config_loop(mut options: DataGenOptions) -> DataGenOptions {
             }
             "dfrc" => {
                 if let Ok(dfrc) = value.parse::<bool>() {
-                    options.generate_dfrc = dfrc;
+                    options.openings = if dfrc { Openings::Dfrc } else { Openings::Classical };
                 } else {
                     eprintln!("Invalid value for dfrc, must be a boolean");
                 }
             }
+            "book" => {
+                let book = PathBuf::from(value);
+                if book.is_file() {
+                    options.openings = Openings::Book(book);
+                } else {
+                    eprintln!("Invalid value for book, must be a path to a file of FENs or EPD records");
+                }
+            }
             "log_level" => {
                 let log_level = match value.parse::<u8>() {
                     Ok(log_level) => log_level,
@@ -535,6 +599,16 @@ fn config_loop(mut options: DataGenOptions) -> DataGenOptions {
                 };
                 options.log_level = log_level;
             }
+            "seed" => {
+                let seed = match value.parse::<u64>() {
+                    Ok(seed) => seed,
+                    Err(e) => {
+                        eprintln!("{e}");
+                        continue;
+                    }
+                };
+                options.seed = Some(seed);
+            }
             "positions_limit" => {
                 let positions_limit = match value.parse::<u64>() {
                     Ok(positions_limit) => positions_limit,
@@ -546,7 +620,7 @@ fn config_loop(mut options: DataGenOptions) -> DataGenOptions {
                 options.position_count_limit = Some(positions_limit);
             }
             other => {
-                eprintln!("Invalid parameter (\"{other}\"), supported parameters are \"num_games\", \"num_threads\", \"tablebases_path\", \"use_nnue\", \"limit\", and \"log_level\"");
+                eprintln!("Invalid parameter (\"{other}\"), supported parameters are \"num_games\", \"num_threads\", \"tablebases_path\", \"use_nnue\", \"limit\", \"dfrc\", \"book\", \"seed\", \"positions_limit\", and \"log_level\"");
             }
         }
     }
@@ -576,9 +650,9 @@ impl FromStr for DataGenOptions {
             options.tablebases_path = Some(PathBuf::from(parts[2]));
         }
         options.use_nnue = parts[3] == "nnue";
-        options.generate_dfrc = match parts.get(4).copied() {
-            Some("dfrc") => true,
-            Some("classical") => false,
+        options.openings = match parts.get(4).copied() {
+            Some("dfrc") => Openings::Dfrc,
+            Some("classical") => Openings::Classical,
             _ => return Err(format!("Invalid game type specifier: {}, must be \"dfrc\" or \"classical\"", parts[4])),
         };
         let limit = match parts[5].chars().next() {
@@ -623,8 +697,11 @@ impl Display for DataGenOptions {
                 DataGenLimit::Nodes(nodes) => format!("nodes {nodes}"),
             }
         )?;
-        writeln!(f, " |> dfrc: {}", self.generate_dfrc)?;
+        writeln!(f, " |> openings: {}", self.openings)?;
         writeln!(f, " |> log_level: {}", self.log_level)?;
+        if let Some(seed) = self.seed {
+            writeln!(f, " |> seed: {seed}")?;
+        }
         if self.tablebases_path.is_none() {
             writeln!(
                 f,
@@ -0,0 +1,257 @@
+// Conversion between training data formats, so that the whole data pipeline can run from this one binary.
+// Positions can be read from self-play game records, marlinformat, or plain text ("FEN | score | result"),
+// and written as text, marlinformat, or bulletformat, dropping any that don't pass the filters on the way.
+// Scores and results are from white's point of view throughout, as they are in the game records.
+// Bulletformat can't be read back, as it doesn't store castling rights or the en passant square.
+
+use std::{
+    fmt::{self, Display},
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    str::FromStr,
+};
+
+use bulletformat::ChessBoard;
+
+use crate::{
+    board::Board,
+    chessmove::Move,
+    datagen::dataformat::{self, marlinformat::PackedBoard, Game},
+};
+
+/// A training data format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Self-play game records, as written by datagen.
+    Games,
+    /// One "FEN | score | result" line per position.
+    Text,
+    Marlinformat,
+    Bulletformat,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "games" => Ok(Self::Games),
+            "text" => Ok(Self::Text),
+            "marlinformat" => Ok(Self::Marlinformat),
+            "bulletformat" => Ok(Self::Bulletformat),
+            _ => Err(format!("unknown format \"{s}\", expected one of games, text, marlinformat, bulletformat")),
+        }
+    }
+}
+
+impl Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Games => "games",
+            Self::Text => "text",
+            Self::Marlinformat => "marlinformat",
+            Self::Bulletformat => "bulletformat",
+        };
+        write!(f, "{name}")
+    }
+}
+
+pub struct ConvertOptions<'a> {
+    pub input: &'a Path,
+    pub output: &'a Path,
+    pub from: Format,
+    pub to: Format,
+    /// Drop positions where the side to move is in check.
+    pub skip_checks: bool,
+    /// Drop positions where the move played was a capture or promotion.
+    /// Only game records know which move was played, so this has no effect on other formats.
+    pub skip_captures: bool,
+    /// Drop positions with an absolute score above this.
+    pub max_score: Option<i32>,
+    /// Stop after writing this many positions.
+    pub limit: Option<usize>,
+}
+
+/// A position, with its evaluation and the outcome of its game.
+struct Sample {
+    board: Board,
+    eval: i16,
+    /// 2 for a white win, 1 for a draw, and 0 for a black win, as in marlinformat.
+    wdl: u8,
+    /// The move played in the position, if known.
+    best_move: Option<Move>,
+}
+
+impl ConvertOptions<'_> {
+    fn keep(&self, sample: &Sample) -> bool {
+        let in_check = self.skip_checks && sample.board.in_check();
+        let capture = self.skip_captures && sample.best_move.is_some_and(|m| sample.board.is_tactical(m));
+        let extreme = self.max_score.is_some_and(|max| i32::from(sample.eval).abs() > max);
+        !(in_check || capture || extreme)
+    }
+}
+
+fn parse_text(line: &str) -> Result<Sample, String> {
+    let mut fields = line.split('|').map(str::trim);
+    let (Some(fen), Some(score), Some(result), None) = (fields.next(), fields.next(), fields.next(), fields.next())
+    else {
+        return Err(format!("expected \"FEN | score | result\", got \"{line}\""));
+    };
+    let board = Board::from_fen(fen).map_err(|e| e.to_string())?;
+    let eval = score.parse().map_err(|e| format!("bad score \"{score}\": {e}"))?;
+    let wdl = match result {
+        "1.0" | "1" | "1-0" => 2,
+        "0.5" | "1/2-1/2" => 1,
+        "0.0" | "0" | "0-1" => 0,
+        _ => return Err(format!("bad result \"{result}\"")),
+    };
+    Ok(Sample { board, eval, wdl, best_move: None })
+}
+
+fn format_text(sample: &Sample) -> String {
+    let result = ["0.0", "0.5", "1.0"][usize::from(sample.wdl)];
+    format!("{} | {} | {result}", sample.board.fen(), sample.eval)
+}
+
+/// Reads every position in `input`, passing each to `callback` until it returns false.
+fn read_samples(
+    input: impl BufRead,
+    format: Format,
+    mut callback: impl FnMut(Sample) -> io::Result<bool>,
+) -> Result<(), String> {
+    let mut input = input;
+    match format {
+        Format::Games => {
+            let mut move_buffer = Vec::new();
+            loop {
+                let game = match Game::deserialise_from(&mut input, std::mem::take(&mut move_buffer)) {
+                    Ok(game) => game,
+                    Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+                    Err(e) => return Err(format!("bad game record: {e}")),
+                };
+                let mut samples = Vec::with_capacity(game.len());
+                game.visit_moves(|board, mv, eval, wdl| {
+                    samples.push(Sample { board: board.clone(), eval, wdl, best_move: Some(mv) });
+                });
+                move_buffer = game.into_move_buffer();
+                for sample in samples {
+                    if !callback(sample).map_err(|e| e.to_string())? {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+        Format::Text => {
+            for (number, line) in input.lines().enumerate() {
+                let line = line.map_err(|e| e.to_string())?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let sample = parse_text(&line).map_err(|e| format!("line {}: {e}", number + 1))?;
+                if !callback(sample).map_err(|e| e.to_string())? {
+                    return Ok(());
+                }
+            }
+            Ok(())
+        }
+        Format::Marlinformat => loop {
+            let mut bytes = [0; std::mem::size_of::<PackedBoard>()];
+            match input.read_exact(&mut bytes) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+                Err(e) => return Err(e.to_string()),
+            }
+            let (board, eval, wdl, _) = PackedBoard::from_bytes(bytes).unpack();
+            if wdl > PackedBoard::WDL_WIN {
+                return Err(format!("bad marlinformat record with WDL {wdl}"));
+            }
+            if !callback(Sample { board, eval, wdl, best_move: None }).map_err(|e| e.to_string())? {
+                return Ok(());
+            }
+        },
+        Format::Bulletformat => Err("bulletformat can only be written, not read".to_string()),
+    }
+}
+
+fn write_sample(output: &mut impl Write, format: Format, sample: &Sample) -> io::Result<()> {
+    match format {
+        Format::Text => writeln!(output, "{}", format_text(sample)),
+        Format::Marlinformat => output.write_all(&sample.board.pack(sample.eval, sample.wdl, 0).as_bytes()),
+        Format::Bulletformat => {
+            let chess_board = dataformat::to_bulletformat(&sample.board, sample.eval, sample.wdl);
+            let bytes =
+                unsafe { std::mem::transmute::<ChessBoard, [u8; std::mem::size_of::<ChessBoard>()]>(chess_board) };
+            output.write_all(&bytes)
+        }
+        Format::Games => unreachable!("positions can't be written as game records"),
+    }
+}
+
+/// Converts the positions in one data file into another format.
+pub fn run_convert(options: &ConvertOptions) -> Result<(), String> {
+    if options.to == Format::Games {
+        return Err("positions can't be converted back into game records".to_string());
+    }
+    if options.from == Format::Bulletformat {
+        return Err("bulletformat can only be written, not read".to_string());
+    }
+    if options.output.exists() {
+        return Err(format!("output file {} already exists", options.output.display()));
+    }
+    let input = File::open(options.input).map_err(|e| format!("failed to open {}: {e}", options.input.display()))?;
+    let output =
+        File::create(options.output).map_err(|e| format!("failed to create {}: {e}", options.output.display()))?;
+    let mut output = BufWriter::new(output);
+
+    let mut read = 0;
+    let mut written = 0;
+    read_samples(BufReader::new(input), options.from, |sample| {
+        read += 1;
+        if options.keep(&sample) {
+            write_sample(&mut output, options.to, &sample)?;
+            written += 1;
+        }
+        if read % (1 << 20) == 0 {
+            print!("\r{read} positions read, {written} written");
+            io::stdout().flush()?;
+        }
+        Ok(options.limit.is_none_or(|limit| written < limit))
+    })?;
+    output.flush().map_err(|e| e.to_string())?;
+    println!("\r{read} positions read, {written} written ({} filtered out)", read - written);
+    Ok(())
+}
+
+mod tests {
+    #[test]
+    fn text_roundtrip() {
+        use super::{format_text, parse_text, ConvertOptions, Format};
+        use std::path::Path;
+
+        let line = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3 | 35 | 0.5";
+        let sample = parse_text(line).unwrap();
+        assert_eq!(sample.eval, 35);
+        assert_eq!(sample.wdl, 1);
+        assert_eq!(format_text(&sample), line);
+        assert_eq!(parse_text("8/8/8/8/8/8/8/K6k w - - 0 1 | -12 | 0-1").unwrap().wdl, 0);
+        assert!(parse_text("8/8/8/8/8/8/8/K6k w - - 0 1 | 7").is_err());
+        assert!(parse_text("8/8/8/8/8/8/8/K6k w - - 0 1 | 7 | 2.0").is_err());
+
+        let options = ConvertOptions {
+            input: Path::new("in"),
+            output: Path::new("out"),
+            from: Format::Text,
+            to: Format::Marlinformat,
+            skip_checks: true,
+            skip_captures: true,
+            max_score: Some(30),
+            limit: None,
+        };
+        assert!(!options.keep(&sample));
+        let check = parse_text("4k3/8/8/8/8/8/8/4K2r w - - 0 1 | 0 | 0.5").unwrap();
+        assert!(!options.keep(&check));
+        let quiet = parse_text("4k3/8/8/8/8/8/8/4K3 w - - 0 1 | 0 | 0.5").unwrap();
+        assert!(options.keep(&quiet));
+    }
+}
@@ -7,7 +7,7 @@ use crate::{
 
 use self::marlinformat::{util::I16Le, PackedBoard};
 
-mod marlinformat;
+pub mod marlinformat;
 
 pub struct Game {
     /// The initial position of the self-play game.
@@ -132,36 +132,43 @@ impl Game {
         for (mv, eval) in &self.moves {
             let eval = eval.get();
             if filter(*mv, i32::from(eval), &board) {
-                let mut bbs = [0; 8];
-                let bitboard = &board.pieces;
-                bbs[0] = bitboard.occupied_co(Colour::WHITE).inner();
-                bbs[1] = bitboard.occupied_co(Colour::BLACK).inner();
-                bbs[2] = bitboard.of_type(PieceType::PAWN).inner();
-                bbs[3] = bitboard.of_type(PieceType::KNIGHT).inner();
-                bbs[4] = bitboard.of_type(PieceType::BISHOP).inner();
-                bbs[5] = bitboard.of_type(PieceType::ROOK).inner();
-                bbs[6] = bitboard.of_type(PieceType::QUEEN).inner();
-                bbs[7] = bitboard.of_type(PieceType::KING).inner();
-                callback(
-                    bulletformat::ChessBoard::from_raw(
-                        bbs,
-                        (board.turn() != Colour::WHITE).into(),
-                        eval,
-                        f32::from(wdl) / 2.0,
-                    )
-                    .unwrap(),
-                );
+                callback(to_bulletformat(&board, eval, wdl));
             }
             board.make_move_simple(*mv);
         }
     }
 
+    /// Exposes each position in the game along with the move played in it, its evaluation,
+    /// and the outcome of the game, via a callback.
+    pub fn visit_moves(&self, mut callback: impl FnMut(&Board, Move, i16, u8)) {
+        let (mut board, _, wdl, _) = self.initial_position.unpack();
+        for (mv, eval) in &self.moves {
+            callback(&board, *mv, eval.get(), wdl);
+            board.make_move_simple(*mv);
+        }
+    }
+
     /// Efficiency method that allows us to recover the move vector without allocating a new vector.
     pub fn into_move_buffer(self) -> Vec<(Move, marlinformat::util::I16Le)> {
         self.moves
     }
 }
 
+/// Converts a position, with its white-relative evaluation and outcome, into a bulletformat `ChessBoard`.
+pub fn to_bulletformat(board: &Board, eval: i16, wdl: u8) -> bulletformat::ChessBoard {
+    let mut bbs = [0; 8];
+    let bitboard = &board.pieces;
+    bbs[0] = bitboard.occupied_co(Colour::WHITE).inner();
+    bbs[1] = bitboard.occupied_co(Colour::BLACK).inner();
+    bbs[2] = bitboard.of_type(PieceType::PAWN).inner();
+    bbs[3] = bitboard.of_type(PieceType::KNIGHT).inner();
+    bbs[4] = bitboard.of_type(PieceType::BISHOP).inner();
+    bbs[5] = bitboard.of_type(PieceType::ROOK).inner();
+    bbs[6] = bitboard.of_type(PieceType::QUEEN).inner();
+    bbs[7] = bitboard.of_type(PieceType::KING).inner();
+    bulletformat::ChessBoard::from_raw(bbs, (board.turn() != Colour::WHITE).into(), eval, f32::from(wdl) / 2.0).unwrap()
+}
+
 #[cfg(test)]
 mod tests {
     use crate::uci::CHESS960;
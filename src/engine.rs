@@ -0,0 +1,364 @@
+// A programmatic interface to the engine, for embedding it in GUIs, bots and test harnesses
+// without going through the UCI protocol. Searches run synchronously on the calling thread
+// (plus any helper threads), and report their progress through a callback.
+
+use std::{
+    fmt::{self, Display},
+    sync::{
+        atomic::{AtomicBool, AtomicU64},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use crate::{
+    board::{
+        evaluation::{is_mate_score, MATE_SCORE},
+        Board,
+    },
+    kpk, perft,
+    piece::Colour,
+    search::{parameters::Config, pv::PVariation, strength},
+    searchinfo::{InfoCallback, SearchInfo, SearchOptions},
+    threadlocal::ThreadData,
+    timemgmt::SearchLimit,
+    transpositiontable::{Bound, TT},
+    uci,
+    util::MEGABYTE,
+};
+
+const DEFAULT_HASH_MEGABYTES: usize = 16;
+
+/// An instance of the engine, with its own position and hash table.
+pub struct Engine {
+    pos: Board,
+    tt: TT,
+    threads: usize,
+    conf: Config,
+    options: SearchOptions,
+    stopped: AtomicBool,
+    nodes: AtomicU64,
+}
+
+/// A limit on how long a search runs for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    /// Search to a fixed depth.
+    Depth(i32),
+    /// Search a fixed number of nodes.
+    Nodes(u64),
+    /// Search for a fixed amount of time.
+    Time(Duration),
+    /// Play a move in a game, with this much time left on the clock and this increment per move.
+    Clock {
+        /// The time remaining on the clock.
+        time: Duration,
+        /// The time added to the clock after each move.
+        increment: Duration,
+    },
+}
+
+/// A score, from the point of view of the side to move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Score {
+    /// An advantage in centipawns.
+    Centipawns(i32),
+    /// A forced mate in this many moves, negative if the side to move is getting mated.
+    Mate(i32),
+}
+
+/// Whether a reported score is exact, or a bound from a failed aspiration search.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScoreBound {
+    /// The score is exact.
+    Exact,
+    /// The true score is at least this good.
+    Lower,
+    /// The true score is at most this good.
+    Upper,
+}
+
+/// A progress report from a running search, equivalent to a UCI "info" line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchUpdate {
+    /// The depth of the iteration that produced this report.
+    pub depth: usize,
+    /// The greatest height reached in the search so far.
+    pub seldepth: usize,
+    /// The number of nodes searched so far.
+    pub nodes: u64,
+    /// The time since the search started.
+    pub elapsed: Duration,
+    /// The score of the principal variation.
+    pub score: Score,
+    /// Whether the score is exact.
+    pub bound: ScoreBound,
+    /// The principal variation, as moves in UCI notation.
+    pub pv: Vec<String>,
+}
+
+/// The outcome of a search.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchResult {
+    /// The move to play, in UCI notation, or `None` if there are no legal moves.
+    pub best_move: Option<String>,
+    /// The score of the position after searching.
+    pub score: Score,
+    /// The depth of the last completed iteration.
+    pub depth: usize,
+    /// The number of nodes searched.
+    pub nodes: u64,
+    /// The principal variation of the last completed iteration.
+    pub pv: Vec<String>,
+}
+
+/// An error from setting up a position.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PositionError {
+    /// The FEN could not be parsed.
+    InvalidFen(String),
+    /// A move could not be parsed, or was illegal.
+    InvalidMove(String),
+}
+
+impl Display for PositionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidFen(e) => write!(f, "invalid FEN: {e}"),
+            Self::InvalidMove(e) => write!(f, "invalid move: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for PositionError {}
+
+impl Score {
+    /// Converts an internal score, relative to the side to move.
+    const fn from_internal(score: i32) -> Self {
+        if is_mate_score(score) {
+            let moves_to_mate = (MATE_SCORE - score.abs() + 1) / 2;
+            Self::Mate(if score > 0 { moves_to_mate } else { -moves_to_mate })
+        } else {
+            Self::Centipawns(uci::normalise_score(score))
+        }
+    }
+}
+
+impl SearchUpdate {
+    pub(crate) fn new(
+        board: &Board,
+        bound: Bound,
+        pv: &PVariation,
+        depth: usize,
+        info: &SearchInfo,
+        nodes: u64,
+    ) -> Self {
+        let bound = match bound {
+            Bound::Lower => ScoreBound::Lower,
+            Bound::Upper => ScoreBound::Upper,
+            _ => ScoreBound::Exact,
+        };
+        // the search reports fail-highs and fail-lows in terms of the window, which is
+        // flipped when black is to move.
+        let bound = match (board.turn() == Colour::BLACK, bound) {
+            (true, ScoreBound::Lower) => ScoreBound::Upper,
+            (true, ScoreBound::Upper) => ScoreBound::Lower,
+            (_, bound) => bound,
+        };
+        Self {
+            depth,
+            seldepth: info.seldepth.ply_to_horizon(),
+            nodes,
+            elapsed: info.time_manager.elapsed(),
+            score: Score::from_internal(pv.score),
+            bound,
+            pv: pv.moves().iter().map(ToString::to_string).collect(),
+        }
+    }
+}
+
+impl Engine {
+    /// Creates an engine set up on the starting position, with a 16 MB hash table and one search thread.
+    #[must_use]
+    pub fn new() -> Self {
+        kpk::init();
+        let mut tt = TT::new();
+        tt.resize(DEFAULT_HASH_MEGABYTES * MEGABYTE);
+        Self {
+            pos: Board::default(),
+            tt,
+            threads: 1,
+            conf: Config::default(),
+            options: SearchOptions::default(),
+            stopped: AtomicBool::new(false),
+            nodes: AtomicU64::new(0),
+        }
+    }
+
+    /// Resizes the hash table, which also clears it.
+    pub fn set_hash_size(&mut self, megabytes: usize) {
+        self.tt.resize(megabytes.max(1) * MEGABYTE);
+    }
+
+    /// Sets the number of threads used for searching.
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+    }
+
+    /// Changes how later searches are run and which moves they play, such as limiting the
+    /// engine's strength. These settings belong to this engine alone.
+    pub const fn set_options(&mut self, options: SearchOptions) {
+        self.options = options;
+    }
+
+    /// The settings that searches are run with.
+    #[must_use]
+    pub const fn options(&self) -> SearchOptions {
+        self.options
+    }
+
+    /// Sets the position to search, either from a FEN or the starting position
+    /// if `fen` is `None`, followed by a sequence of moves in UCI or SAN notation.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the FEN or any of the moves are invalid, in which
+    /// case the engine's position is left unchanged.
+    pub fn set_position(&mut self, fen: Option<&str>, moves: &[&str]) -> Result<(), PositionError> {
+        let mut pos = Board::default();
+        if let Some(fen) = fen {
+            pos.set_from_fen(fen).map_err(|e| PositionError::InvalidFen(e.to_string()))?;
+        }
+        for text in moves {
+            pos.zero_height();
+            let m = pos.parse_move(text).map_err(|e| PositionError::InvalidMove(e.to_string()))?;
+            pos.make_move_simple(m);
+        }
+        pos.zero_height();
+        self.pos = pos;
+        Ok(())
+    }
+
+    /// The current position, as a FEN.
+    #[must_use]
+    pub fn fen(&self) -> String {
+        self.pos.fen()
+    }
+
+    /// Forgets everything learned in previous searches, as between games.
+    pub fn new_game(&mut self) {
+        self.tt.clear(self.threads);
+    }
+
+    /// Searches the current position, calling `on_info` with each progress report.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `on_info` panics.
+    pub fn search(&mut self, limit: Limit, on_info: impl Fn(&SearchUpdate) + Sync) -> SearchResult {
+        if self.pos.legal_moves().is_empty() {
+            let score = if self.pos.in_check() { Score::Mate(0) } else { Score::Centipawns(0) };
+            return SearchResult { best_move: None, score, depth: 0, nodes: 0, pv: Vec::new() };
+        }
+
+        let last_exact = Mutex::new(None::<SearchUpdate>);
+        let callback = |update: &SearchUpdate| {
+            if update.bound == ScoreBound::Exact {
+                *last_exact.lock().unwrap() = Some(update.clone());
+            }
+            on_info(update);
+        };
+
+        // the previous search leaves the stop flag set.
+        *self.stopped.get_mut() = false;
+        let mut info = SearchInfo::with_search_params(&self.stopped, &self.nodes, &self.conf);
+        let sink = InfoCallback(&callback);
+        info.sink = &sink;
+        info.options = self.options;
+        info.time_manager.set_limit(match limit {
+            Limit::Depth(depth) => SearchLimit::Depth(depth.into()),
+            Limit::Nodes(nodes) => SearchLimit::Nodes(nodes),
+            Limit::Time(time) => SearchLimit::Time(millis(time)),
+            Limit::Clock { time, increment } => SearchLimit::Dynamic {
+                our_clock: millis(time),
+                their_clock: millis(time),
+                our_inc: millis(increment),
+                their_inc: millis(increment),
+                moves_to_go: None,
+            },
+        });
+        info.time_manager.set_node_cap(self.options.limit_strength.then(|| strength::node_limit(self.options.elo)));
+        info.time_manager.start();
+
+        // helper threads make the search depend on OS scheduling.
+        let threads = if self.options.deterministic { 1 } else { self.threads };
+        let mut thread_data = (0..threads).map(|i| ThreadData::new(i, &self.pos, self.tt.view())).collect::<Vec<_>>();
+        self.tt.increase_age();
+        let (white_score, best_move) = self.pos.search_position(&mut info, &mut thread_data, self.tt.view());
+        let nodes = info.nodes.get_global();
+        drop(thread_data);
+
+        let stm_score = if self.pos.turn() == Colour::WHITE { white_score } else { -white_score };
+        let last = last_exact.into_inner().unwrap();
+        SearchResult {
+            best_move: Some(best_move.to_string()),
+            score: last.as_ref().map_or_else(|| Score::from_internal(stm_score), |update| update.score),
+            depth: last.as_ref().map_or(0, |update| update.depth),
+            nodes,
+            pv: last.map_or_else(|| vec![best_move.to_string()], |update| update.pv),
+        }
+    }
+
+    /// Counts the leaf nodes of the move tree of the current position, to the given depth.
+    pub fn perft(&mut self, depth: usize) -> u64 {
+        perft::perft(&mut self.pos, depth)
+    }
+}
+
+fn millis(duration: Duration) -> u64 {
+    u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+mod tests {
+    #[test]
+    fn engine_finds_mate_in_one() {
+        use super::{Engine, Limit, Score};
+
+        let mut engine = Engine::new();
+        engine.set_position(Some("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1"), &[]).unwrap();
+        let updates = std::sync::atomic::AtomicUsize::new(0);
+        let result = engine.search(Limit::Depth(4), |_| {
+            updates.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        });
+        assert_eq!(result.best_move.as_deref(), Some("a1a8"));
+        assert_eq!(result.score, Score::Mate(1));
+        assert!(updates.into_inner() > 0);
+        // a second search on the same engine.
+        let again = engine.search(Limit::Nodes(1000), |_| {});
+        assert_eq!(again.best_move.as_deref(), Some("a1a8"));
+        assert!(engine.set_position(None, &["e2e5"]).is_err());
+        engine.set_position(None, &["e2e4"]).unwrap();
+        assert_eq!(engine.perft(2), 600);
+    }
+
+    #[test]
+    fn options_belong_to_one_engine() {
+        use super::{Engine, Limit};
+        use crate::{search::strength, searchinfo::SearchOptions};
+
+        let mut weak = Engine::new();
+        weak.set_options(SearchOptions { limit_strength: true, elo: strength::MIN_ELO, ..SearchOptions::default() });
+        let strong = Engine::new();
+        assert_eq!(strong.options(), SearchOptions::default());
+        // the strength limit cuts a search that would otherwise take millions of nodes down to a
+        // few hundred, as the node count is only checked every so often.
+        let result = weak.search(Limit::Depth(20), |_| {});
+        assert!(result.nodes < 1000, "searched {} nodes", result.nodes);
+    }
+}
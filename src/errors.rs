@@ -1,5 +1,7 @@
 use std::fmt::Display;
 
+use crate::{piece::Colour, util::Square};
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum MoveParseError {
     InvalidLength(usize),
@@ -9,6 +11,7 @@ pub enum MoveParseError {
     InvalidToSquareRank(char),
     InvalidPromotionPiece(char),
     IllegalMove(String),
+    AmbiguousMove(String),
 }
 impl Display for MoveParseError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
@@ -20,11 +23,98 @@ impl Display for MoveParseError {
             Self::InvalidToSquareRank(rank) => write!(f, "Invalid to-square rank {rank}"),
             Self::InvalidPromotionPiece(piece) => write!(f, "Invalid promotion piece {piece}"),
             Self::IllegalMove(m) => write!(f, "Illegal move {m}"),
+            Self::AmbiguousMove(m) => write!(f, "Ambiguous move {m}"),
         }
     }
 }
 
-#[cfg(debug_assertions)]
+#[cfg(any(debug_assertions, feature = "verify"))]
 pub type PositionValidityError = String;
 
-pub type FenParseError = String;
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IllegalPositionError {
+    PawnOnBackRank(Square),
+    TooManyPawns(Colour),
+    TooManyPieces(Colour),
+    /// More pieces beyond the starting set than there are missing pawns to have promoted.
+    TooManyPromotedPieces(Colour),
+    /// The side that just moved has left its king in check.
+    OpponentInCheck,
+    /// An en passant square on the wrong rank for the side to move.
+    EnPassantRank(Square),
+}
+impl Display for IllegalPositionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::PawnOnBackRank(sq) => write!(f, "there is a pawn on {sq}, on a back rank"),
+            Self::TooManyPawns(colour) => write!(f, "{colour} has more than eight pawns"),
+            Self::TooManyPieces(colour) => write!(f, "{colour} has more than sixteen pieces"),
+            Self::TooManyPromotedPieces(colour) => {
+                write!(f, "{colour} has more promoted pieces than it has missing pawns")
+            }
+            Self::OpponentInCheck => write!(f, "the side not to move is in check"),
+            Self::EnPassantRank(sq) => {
+                write!(f, "the en passant square {sq} is on the wrong rank for the side to move")
+            }
+        }
+    }
+}
+
+/// The six space-separated fields of a FEN string, in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FenField {
+    Placement,
+    SideToMove,
+    Castling,
+    EnPassant,
+    HalfmoveClock,
+    FullmoveNumber,
+}
+
+impl Display for FenField {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            Self::Placement => "piece placement",
+            Self::SideToMove => "side to move",
+            Self::Castling => "castling rights",
+            Self::EnPassant => "en passant square",
+            Self::HalfmoveClock => "halfmove clock",
+            Self::FullmoveNumber => "fullmove number",
+        };
+        // fields are numbered from one, as they would be counted by someone reading the FEN.
+        write!(f, "{name} (field {})", *self as usize + 1)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FenParseError {
+    NotAscii,
+    MissingField(FenField),
+    /// A field is present, but `token` (all or part of it) is malformed.
+    InvalidField {
+        field: FenField,
+        token: String,
+        expected: &'static str,
+    },
+    /// The FEN is well-formed, but the position it describes can't arise in a game.
+    IllegalPosition(IllegalPositionError),
+}
+
+impl FenParseError {
+    pub fn invalid(field: FenField, token: impl Into<String>, expected: &'static str) -> Self {
+        Self::InvalidField { field, token: token.into(), expected }
+    }
+}
+
+impl Display for FenParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::NotAscii => write!(f, "FEN contains non-ASCII characters"),
+            Self::MissingField(field) => write!(f, "FEN is missing the {field}"),
+            Self::InvalidField { field, token, expected } => {
+                write!(f, "invalid {field} in FEN at \"{token}\", expected {expected}")
+            }
+            Self::IllegalPosition(e) => write!(f, "FEN describes an impossible position: {e}"),
+        }
+    }
+}
@@ -0,0 +1,63 @@
+// A small per-thread cache of raw network outputs, keyed by the full
+// position key. Only the raw output of the network is stored, as it
+// depends on nothing but the pieces and the side to move - all of the
+// adjustments made on top of it (material scaling, the fifty-move rule,
+// endgame knowledge) are cheap, and are reapplied on every lookup.
+
+use crate::util::VALUE_NONE;
+
+const EVAL_CACHE_SIZE: usize = 1 << 14;
+
+#[derive(Clone, Copy)]
+struct Entry {
+    key: u64,
+    eval: i32,
+}
+
+#[derive(Clone)]
+pub struct EvalCache {
+    table: Box<[Entry]>,
+}
+
+impl EvalCache {
+    pub fn new() -> Self {
+        Self { table: vec![Entry { key: 0, eval: VALUE_NONE }; EVAL_CACHE_SIZE].into_boxed_slice() }
+    }
+
+    pub fn clear(&mut self) {
+        self.table.fill(Entry { key: 0, eval: VALUE_NONE });
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    const fn index(key: u64) -> usize {
+        key as usize & (EVAL_CACHE_SIZE - 1)
+    }
+
+    pub fn probe(&self, key: u64) -> Option<i32> {
+        let entry = self.table[Self::index(key)];
+        if entry.key == key && entry.eval != VALUE_NONE {
+            Some(entry.eval)
+        } else {
+            None
+        }
+    }
+
+    pub fn store(&mut self, key: u64, eval: i32) {
+        self.table[Self::index(key)] = Entry { key, eval };
+    }
+}
+
+mod tests {
+    #[test]
+    fn eval_cache_round_trip() {
+        let mut cache = super::EvalCache::new();
+        let key = 0xDEAD_BEEF_CAFE_F00D;
+        assert_eq!(cache.probe(key), None);
+        cache.store(key, 37);
+        assert_eq!(cache.probe(key), Some(37));
+        // a different key that maps to the same slot must not hit.
+        assert_eq!(cache.probe(key ^ (1 << 40)), None);
+        cache.clear();
+        assert_eq!(cache.probe(key), None);
+    }
+}
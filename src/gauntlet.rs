@@ -0,0 +1,464 @@
+// A match runner, for quick strength checks against another UCI engine without external tooling.
+// The opponent runs as a subprocess, and we play it in-process through `Engine`. Each opening is
+// played twice with colours reversed, on a clock with increment. Games are adjudicated when
+// both engines agree that the game is decided, and are written out as PGN as they finish.
+
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    board::{Board, GameOutcome},
+    engine::{Engine, Limit, Score},
+    piece::Colour,
+    rng::Xoshiro,
+    sprt::{load_book, random_opening, Tally},
+    NAME, VERSION,
+};
+
+/// How long the opponent gets to answer "uci" and "isready".
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Grace time for moves on top of the clock, to allow for process and pipe latency.
+const MOVE_OVERHEAD: Duration = Duration::from_millis(100);
+/// Scores at least this large (in centipawns), agreed by both engines, are a win.
+const WIN_ADJUDICATION_SCORE: i32 = 1000;
+const WIN_ADJUDICATION_PLIES: usize = 6;
+/// Scores at most this large (in centipawns), agreed by both engines, are a draw.
+const DRAW_ADJUDICATION_SCORE: i32 = 10;
+const DRAW_ADJUDICATION_PLIES: usize = 16;
+/// Draw adjudication only starts after this many plies.
+const DRAW_ADJUDICATION_MIN_PLY: usize = 60;
+
+/// Configuration options for a match.
+pub struct MatchOptions {
+    /// The opponent's executable.
+    pub opponent: PathBuf,
+    /// A file of opening positions, one FEN or EPD per line.
+    pub book: Option<PathBuf>,
+    /// The base time for each side.
+    pub time: Duration,
+    /// The increment for each side.
+    pub increment: Duration,
+    /// The number of games to play, rounded up to an even number.
+    pub games: usize,
+    /// Where to write the games.
+    pub pgn: Option<PathBuf>,
+    /// The hash table size for both engines, in megabytes.
+    pub hash_mb: usize,
+}
+
+/// A UCI engine running as a subprocess.
+struct Opponent {
+    child: Child,
+    stdin: ChildStdin,
+    lines: mpsc::Receiver<String>,
+    name: String,
+}
+
+impl Opponent {
+    fn start(path: &Path, hash_mb: usize) -> Result<Self, String> {
+        let mut child = Command::new(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("failed to start {}: {e}", path.display()))?;
+        let stdin = child.stdin.take().ok_or("no stdin for opponent")?;
+        let stdout = child.stdout.take().ok_or("no stdout for opponent")?;
+        let (sender, lines) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        let mut opponent = Self {
+            child,
+            stdin,
+            lines,
+            name: path.file_name().map_or_else(String::new, |n| n.to_string_lossy().into()),
+        };
+        opponent.send("uci")?;
+        while let Some(line) = opponent.receive(HANDSHAKE_TIMEOUT) {
+            if let Some(name) = line.strip_prefix("id name ") {
+                opponent.name = name.trim().to_string();
+            }
+            if line.trim() == "uciok" {
+                opponent.send(&format!("setoption name Hash value {hash_mb}"))?;
+                opponent.sync()?;
+                return Ok(opponent);
+            }
+        }
+        Err(format!("{} did not respond to \"uci\"", path.display()))
+    }
+
+    fn send(&mut self, command: &str) -> Result<(), String> {
+        writeln!(self.stdin, "{command}")
+            .and_then(|()| self.stdin.flush())
+            .map_err(|e| format!("failed to write to {}: {e}", self.name))
+    }
+
+    fn receive(&self, timeout: Duration) -> Option<String> {
+        self.lines.recv_timeout(timeout).ok()
+    }
+
+    /// Waits for the opponent to be ready for the next command.
+    fn sync(&mut self) -> Result<(), String> {
+        self.send("isready")?;
+        while let Some(line) = self.receive(HANDSHAKE_TIMEOUT) {
+            if line.trim() == "readyok" {
+                return Ok(());
+            }
+        }
+        Err(format!("{} did not respond to \"isready\"", self.name))
+    }
+
+    /// Asks for a move, given the clocks of white and black. The move is `None` if the opponent runs out of time.
+    fn go(
+        &mut self,
+        position: &str,
+        mover: Colour,
+        clocks: [Duration; 2],
+        increment: Duration,
+    ) -> Result<Reply, String> {
+        self.send(&format!("position {position}"))?;
+        let [wtime, btime] = clocks.map(|c| c.as_millis());
+        let inc = increment.as_millis();
+        self.send(&format!("go wtime {wtime} btime {btime} winc {inc} binc {inc}"))?;
+        let mut score = None;
+        let deadline = Instant::now() + clocks[mover.index()] + MOVE_OVERHEAD;
+        loop {
+            let Some(line) = self.receive(deadline.saturating_duration_since(Instant::now())) else {
+                // out of time: stop the search, and throw away the move when it arrives.
+                self.send("stop")?;
+                while let Some(line) = self.receive(HANDSHAKE_TIMEOUT) {
+                    if line.starts_with("bestmove") {
+                        break;
+                    }
+                }
+                return Ok(Reply { mov: None, score });
+            };
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("info") => {
+                    while let Some(word) = words.next() {
+                        if word == "score" {
+                            score = match (words.next(), words.next().and_then(|v| v.parse().ok())) {
+                                (Some("cp"), Some(cp)) => Some(Score::Centipawns(cp)),
+                                (Some("mate"), Some(n)) => Some(Score::Mate(n)),
+                                _ => score,
+                            };
+                        }
+                    }
+                }
+                Some("bestmove") => return Ok(Reply { mov: words.next().map(ToString::to_string), score }),
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Drop for Opponent {
+    fn drop(&mut self) {
+        // if the opponent has already died, there's nothing to tell it.
+        let _ = self.send("quit");
+        let start = Instant::now();
+        while start.elapsed() < HANDSHAKE_TIMEOUT {
+            if !matches!(self.child.try_wait(), Ok(None)) {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        if let Err(e) = self.child.kill() {
+            eprintln!("failed to shut down {}: {e}", self.name);
+        }
+    }
+}
+
+/// A move chosen by either side, and the score it reported, from its own point of view.
+struct Reply {
+    mov: Option<String>,
+    score: Option<Score>,
+}
+
+/// The result of a game: the points for white (0, 1 or 2 half-points) and why the game ended.
+pub struct GameResult {
+    pub white_half_points: u8,
+    pub termination: &'static str,
+}
+
+impl GameResult {
+    pub const fn from_outcome(outcome: GameOutcome) -> Self {
+        let termination = match outcome {
+            GameOutcome::WhiteWinMate | GameOutcome::BlackWinMate => "checkmate",
+            GameOutcome::DrawStalemate => "stalemate",
+            GameOutcome::DrawFiftyMoves => "fifty-move rule",
+            GameOutcome::DrawRepetition => "threefold repetition",
+            GameOutcome::DrawInsufficientMaterial => "insufficient material",
+            _ => "adjudication",
+        };
+        Self { white_half_points: outcome.as_packed_u8(), termination }
+    }
+
+    /// A loss for `loser` for a reason other than the position on the board.
+    fn forfeit(loser: Colour, termination: &'static str) -> Self {
+        Self { white_half_points: if loser == Colour::WHITE { 0 } else { 2 }, termination }
+    }
+
+    pub const fn pgn_result(&self) -> &'static str {
+        match self.white_half_points {
+            2 => "1-0",
+            1 => "1/2-1/2",
+            _ => "0-1",
+        }
+    }
+}
+
+/// Converts a score from the mover's point of view to centipawns from white's point of view.
+pub fn white_centipawns(score: Score, mover: Colour) -> i32 {
+    let cp = match score {
+        Score::Centipawns(cp) => cp,
+        Score::Mate(n) if n > 0 => 100_000,
+        Score::Mate(_) => -100_000,
+    };
+    if mover == Colour::WHITE {
+        cp
+    } else {
+        -cp
+    }
+}
+
+/// Tracks the scores of the last few moves to decide when to adjudicate.
+#[derive(Default)]
+pub struct Adjudicator {
+    scores: Vec<Option<i32>>,
+}
+
+impl Adjudicator {
+    pub fn update(&mut self, white_cp: Option<i32>, ply: usize) -> Option<GameResult> {
+        self.scores.push(white_cp);
+        let recent = |n: usize| self.scores.iter().rev().take(n).copied().collect::<Option<Vec<_>>>();
+        if let Some(recent) = recent(WIN_ADJUDICATION_PLIES).filter(|r| r.len() == WIN_ADJUDICATION_PLIES) {
+            if recent.iter().all(|&cp| cp >= WIN_ADJUDICATION_SCORE) {
+                return Some(GameResult { white_half_points: 2, termination: "adjudication" });
+            }
+            if recent.iter().all(|&cp| cp <= -WIN_ADJUDICATION_SCORE) {
+                return Some(GameResult { white_half_points: 0, termination: "adjudication" });
+            }
+        }
+        if ply >= DRAW_ADJUDICATION_MIN_PLY {
+            if let Some(recent) = recent(DRAW_ADJUDICATION_PLIES).filter(|r| r.len() == DRAW_ADJUDICATION_PLIES) {
+                if recent.iter().all(|&cp| cp.abs() <= DRAW_ADJUDICATION_SCORE) {
+                    return Some(GameResult { white_half_points: 1, termination: "adjudication" });
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Plays one game, with viridithas as `our_colour`, returning the result and the moves in SAN.
+fn play_game(
+    opening: &Board,
+    our_colour: Colour,
+    engine: &mut Engine,
+    opponent: &mut Opponent,
+    options: &MatchOptions,
+) -> Result<(GameResult, Vec<String>), String> {
+    let start_fen = opening.fen();
+    let mut board = opening.clone();
+    let mut uci_moves = Vec::new();
+    let mut san_moves = Vec::new();
+    let mut clocks = [options.time; 2];
+    let mut adjudicator = Adjudicator::default();
+    engine.new_game();
+    opponent.send("ucinewgame")?;
+    opponent.sync()?;
+    loop {
+        let outcome = board.outcome();
+        if outcome != GameOutcome::Ongoing {
+            return Ok((GameResult::from_outcome(outcome), san_moves));
+        }
+        let mover = board.turn();
+        let start = Instant::now();
+        let reply = if mover == our_colour {
+            let played = uci_moves.iter().map(String::as_str).collect::<Vec<_>>();
+            engine.set_position(Some(&start_fen), &played).map_err(|e| e.to_string())?;
+            let limit = Limit::Clock { time: clocks[mover.index()], increment: options.increment };
+            let result = engine.search(limit, |_| {});
+            Reply { mov: result.best_move, score: Some(result.score) }
+        } else {
+            let position = if uci_moves.is_empty() {
+                format!("fen {start_fen}")
+            } else {
+                format!("fen {start_fen} moves {}", uci_moves.join(" "))
+            };
+            opponent.go(&position, mover, clocks, options.increment)?
+        };
+        let clock = &mut clocks[mover.index()];
+        let elapsed = start.elapsed();
+        if elapsed > *clock + MOVE_OVERHEAD || reply.mov.is_none() {
+            return Ok((GameResult::forfeit(mover, "time forfeit"), san_moves));
+        }
+        *clock = clock.saturating_sub(elapsed) + options.increment;
+
+        let text = reply.mov.unwrap_or_default();
+        let Ok(m) = board.parse_uci(&text) else {
+            return Ok((GameResult::forfeit(mover, "illegal move"), san_moves));
+        };
+        san_moves.push(board.san(m).unwrap_or_else(|| text.clone()));
+        uci_moves.push(text);
+        board.make_move_simple(m);
+
+        let white_cp = reply.score.map(|score| white_centipawns(score, mover));
+        if let Some(result) = adjudicator.update(white_cp, board.ply()) {
+            return Ok((result, san_moves));
+        }
+    }
+}
+
+/// Writes one game as PGN. Each move is written as given, so it may carry a comment after the SAN.
+pub fn write_pgn(
+    out: &mut impl Write,
+    event: &str,
+    round: usize,
+    players: [&str; 2],
+    opening: &Board,
+    result: &GameResult,
+    moves: &[String],
+) -> io::Result<()> {
+    let fen = opening.fen();
+    writeln!(out, "[Event \"{event}\"]")?;
+    writeln!(out, "[Site \"?\"]")?;
+    writeln!(out, "[Date \"{}\"]", chrono::Local::now().format("%Y.%m.%d"))?;
+    writeln!(out, "[Round \"{round}\"]")?;
+    writeln!(out, "[White \"{}\"]", players[0])?;
+    writeln!(out, "[Black \"{}\"]", players[1])?;
+    writeln!(out, "[Result \"{}\"]", result.pgn_result())?;
+    writeln!(out, "[FEN \"{fen}\"]")?;
+    writeln!(out, "[SetUp \"1\"]")?;
+    writeln!(out, "[Termination \"{}\"]", result.termination)?;
+    writeln!(out)?;
+    let mut line = String::new();
+    let first_ply = opening.ply();
+    for (i, san) in moves.iter().enumerate() {
+        let ply = first_ply + i;
+        let token = if ply & 1 == 0 {
+            format!("{}. {san}", ply / 2 + 1)
+        } else if i == 0 {
+            format!("{}... {san}", ply / 2 + 1)
+        } else {
+            san.clone()
+        };
+        if line.len() + token.len() >= 80 {
+            writeln!(out, "{}", line.trim_end())?;
+            line.clear();
+        }
+        line.push_str(&token);
+        line.push(' ');
+    }
+    writeln!(out, "{line}{}", result.pgn_result())?;
+    writeln!(out)?;
+    out.flush()
+}
+
+pub fn run_match(options: &MatchOptions) -> Result<(), String> {
+    let book = options.book.as_ref().map_or_else(|| Ok(Vec::new()), load_book)?;
+    let mut pgn = match &options.pgn {
+        Some(path) => {
+            Some(BufWriter::new(File::create(path).map_err(|e| format!("failed to create {}: {e}", path.display()))?))
+        }
+        None => None,
+    };
+    let mut engine = Engine::new();
+    engine.set_hash_size(options.hash_mb);
+    let mut opponent = Opponent::start(&options.opponent, options.hash_mb)?;
+    let our_name = format!("{NAME} {VERSION}");
+    println!(
+        "{our_name} vs {} at {:.1}+{:.2}s, {} games",
+        opponent.name,
+        options.time.as_secs_f64(),
+        options.increment.as_secs_f64(),
+        options.games.div_ceil(2) * 2
+    );
+
+    let mut tally = Tally::default();
+    let mut rng = Xoshiro::from_user_seed(0);
+    for pair in 0..options.games.div_ceil(2) {
+        let opening = if book.is_empty() { random_opening(&mut rng) } else { book[pair % book.len()].clone() };
+        for our_colour in [Colour::WHITE, Colour::BLACK] {
+            let (result, moves) = play_game(&opening, our_colour, &mut engine, &mut opponent, options)?;
+            let our_half_points =
+                if our_colour == Colour::WHITE { result.white_half_points } else { 2 - result.white_half_points };
+            match our_half_points {
+                2 => tally.wins += 1,
+                1 => tally.draws += 1,
+                _ => tally.losses += 1,
+            }
+            let players = if our_colour == Colour::WHITE {
+                [our_name.as_str(), opponent.name.as_str()]
+            } else {
+                [opponent.name.as_str(), our_name.as_str()]
+            };
+            if let Some(pgn) = &mut pgn {
+                let round = tally.games();
+                let round = usize::try_from(round).unwrap_or(usize::MAX);
+                write_pgn(pgn, &format!("{NAME} match"), round, players, &opening, &result, &moves)
+                    .map_err(|e| format!("failed to write PGN: {e}"))?;
+            }
+            println!(
+                "Game {}: {} - {} {} ({}) | +{} ={} -{} | Elo: {:.1}",
+                tally.games(),
+                players[0],
+                players[1],
+                result.pgn_result(),
+                result.termination,
+                tally.wins,
+                tally.draws,
+                tally.losses,
+                tally.elo()
+            );
+        }
+    }
+    println!(
+        "Final: {our_name} vs {}: +{} ={} -{}, score {:.1}%, Elo {:.1}",
+        opponent.name,
+        tally.wins,
+        tally.draws,
+        tally.losses,
+        tally.score() * 100.0,
+        tally.elo()
+    );
+    Ok(())
+}
+
+mod tests {
+    #[test]
+    fn adjudication_needs_agreement_over_several_plies() {
+        use super::{Adjudicator, DRAW_ADJUDICATION_MIN_PLY, WIN_ADJUDICATION_PLIES};
+
+        let mut adjudicator = Adjudicator::default();
+        for ply in 1..WIN_ADJUDICATION_PLIES {
+            assert!(adjudicator.update(Some(1500), ply).is_none());
+        }
+        let result = adjudicator.update(Some(1200), WIN_ADJUDICATION_PLIES).unwrap();
+        assert_eq!(result.white_half_points, 2);
+
+        // a missing score resets the count.
+        let mut adjudicator = Adjudicator::default();
+        for ply in 0..100 {
+            let score = if ply % 5 == 0 { None } else { Some(0) };
+            assert!(adjudicator.update(score, ply).is_none());
+        }
+        let mut adjudicator = Adjudicator::default();
+        let drawn = (0..100).find_map(|ply| adjudicator.update(Some(3), ply)).unwrap();
+        assert_eq!(drawn.white_half_points, 1);
+        assert!(adjudicator.scores.len() > DRAW_ADJUDICATION_MIN_PLY);
+    }
+}
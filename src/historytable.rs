@@ -1,13 +1,13 @@
 use crate::{
     chessmove::Move,
-    piece::{Piece, PieceType},
+    piece::{Colour, Piece, PieceType},
     util::{depth::Depth, Square, BOARD_N_SQUARES},
 };
 
 const AGEING_DIVISOR: i16 = 2;
 
 const fn history_bonus(depth: Depth) -> i32 {
-    let depth = depth.round();
+    let depth = depth.trunc();
     if depth > 13 {
         32
     } else {
@@ -182,6 +182,56 @@ impl DoubleHistoryTable {
     }
 }
 
+pub const CORRECTION_HISTORY_SIZE: usize = 16_384;
+pub const CORRECTION_HISTORY_GRAIN: i32 = 256;
+const CORRECTION_HISTORY_WEIGHT_SCALE: i32 = 256;
+const CORRECTION_HISTORY_MAX: i32 = CORRECTION_HISTORY_GRAIN * 32;
+
+/// Tracks how far off the static evaluation tends to be for a given pawn structure,
+/// stored in units of `1 / CORRECTION_HISTORY_GRAIN` of an evaluation point.
+#[derive(Clone)]
+pub struct CorrectionHistoryTable {
+    table: [[i32; CORRECTION_HISTORY_SIZE]; 2],
+}
+
+impl CorrectionHistoryTable {
+    pub fn boxed() -> Box<Self> {
+        #![allow(clippy::cast_ptr_alignment)]
+        // SAFETY: we're allocating a zeroed block of memory, and then casting it to a Box<Self>
+        // this is fine! because [[i32; CORRECTION_HISTORY_SIZE]; 2] is just a bunch of i32s,
+        // which are fine to zero-out.
+        unsafe {
+            let layout = std::alloc::Layout::new::<Self>();
+            let ptr = std::alloc::alloc_zeroed(layout);
+            if ptr.is_null() {
+                std::alloc::handle_alloc_error(layout);
+            }
+            Box::from_raw(ptr.cast())
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.table.iter_mut().flatten().for_each(|x| *x = 0);
+    }
+
+    pub const fn get(&self, colour: Colour, key: u64) -> i32 {
+        #![allow(clippy::cast_possible_truncation)]
+        self.table[colour.index()][key as usize % CORRECTION_HISTORY_SIZE]
+    }
+
+    /// Moves the entry towards `diff`, the amount by which the search result exceeded
+    /// the static evaluation, with deeper searches given more weight.
+    pub fn update(&mut self, colour: Colour, key: u64, depth: Depth, diff: i32) {
+        #![allow(clippy::cast_possible_truncation)]
+        let entry = &mut self.table[colour.index()][key as usize % CORRECTION_HISTORY_SIZE];
+        let depth = depth.trunc().max(0);
+        let new_weight = (depth * depth + 2 * depth + 1).min(128);
+        let scaled_diff = diff * CORRECTION_HISTORY_GRAIN;
+        let update = *entry * (CORRECTION_HISTORY_WEIGHT_SCALE - new_weight) + scaled_diff * new_weight;
+        *entry = (update / CORRECTION_HISTORY_WEIGHT_SCALE).clamp(-CORRECTION_HISTORY_MAX, CORRECTION_HISTORY_MAX);
+    }
+}
+
 #[derive(Clone)]
 pub struct MoveTable {
     table: Vec<Move>,
@@ -0,0 +1,175 @@
+// A bitbase for king-and-pawn-versus-king endings, generated by retrograde analysis.
+// This is a port of the classic scheme used by Stockfish: the side with the pawn is
+// normalised to be white, with the pawn on files A-D, so every position fits into
+// 2 * 24 * 64 * 64 bits (24 KiB).
+
+#![allow(clippy::cast_possible_truncation)]
+
+use std::sync::OnceLock;
+
+use crate::{
+    board::movegen::bitboards::{king_attacks, pawn_attacks},
+    piece::{Colour, White},
+    util::{Rank, Square},
+};
+
+const MAX_INDEX: usize = 2 * 24 * 64 * 64;
+
+const INVALID: u8 = 0b000;
+const UNKNOWN: u8 = 0b001;
+const DRAW: u8 = 0b010;
+const WIN: u8 = 0b100;
+
+static BITBASE: OnceLock<Vec<u64>> = OnceLock::new();
+
+const fn index(stm: Colour, bksq: Square, wksq: Square, psq: Square) -> usize {
+    wksq.index()
+        | (bksq.index() << 6)
+        | (stm.index() << 12)
+        | ((psq.file() as usize) << 13)
+        | (((Rank::RANK_7 - psq.rank()) as usize) << 15)
+}
+
+/// Decodes an index into (side to move, white king, black king, pawn).
+const fn decode(idx: usize) -> (Colour, Square, Square, Square) {
+    let wksq = Square::new((idx & 0x3F) as u8);
+    let bksq = Square::new(((idx >> 6) & 0x3F) as u8);
+    let stm = Colour::new((idx >> 12) & 1 == 1);
+    let psq = Square::from_rank_file(Rank::RANK_7 - ((idx >> 15) & 0x7) as u8, ((idx >> 13) & 0x3) as u8);
+    (stm, wksq, bksq, psq)
+}
+
+/// Classifies the positions that can be resolved without looking at their children.
+fn initial_result(idx: usize) -> u8 {
+    let (stm, wksq, bksq, psq) = decode(idx);
+    let push_sq = psq.add(8);
+
+    if Square::distance(wksq, bksq) <= 1
+        || wksq == psq
+        || bksq == psq
+        || stm == Colour::WHITE && pawn_attacks::<White>(psq.as_set()).contains_square(bksq)
+    {
+        // two pieces on one square, or a king can be captured.
+        INVALID
+    } else if stm == Colour::WHITE
+        && psq.rank() == Rank::RANK_7
+        && wksq != push_sq
+        && (Square::distance(bksq, push_sq) > 1 || Square::distance(wksq, push_sq) == 1)
+    {
+        // the pawn promotes without being captured.
+        WIN
+    } else if stm == Colour::BLACK
+        && ((king_attacks(bksq) & !(king_attacks(wksq) | pawn_attacks::<White>(psq.as_set()))).is_empty()
+            || (king_attacks(bksq) & !king_attacks(wksq)).contains_square(psq))
+    {
+        // stalemate, or the black king takes the pawn.
+        DRAW
+    } else {
+        UNKNOWN
+    }
+}
+
+/// Classifies a position by looking at the results of its children.
+fn classify(db: &[u8], idx: usize) -> u8 {
+    let (stm, wksq, bksq, psq) = decode(idx);
+    let (good, bad) = if stm == Colour::WHITE { (WIN, DRAW) } else { (DRAW, WIN) };
+
+    let mut r = INVALID;
+    if stm == Colour::WHITE {
+        for to in king_attacks(wksq) {
+            r |= db[index(Colour::BLACK, bksq, to, psq)];
+        }
+        if psq.rank() < Rank::RANK_7 {
+            // single push
+            r |= db[index(Colour::BLACK, bksq, wksq, psq.add(8))];
+        }
+        if psq.rank() == Rank::RANK_2 && psq.add(8) != wksq && psq.add(8) != bksq {
+            // double push
+            r |= db[index(Colour::BLACK, bksq, wksq, psq.add(16))];
+        }
+    } else {
+        for to in king_attacks(bksq) {
+            r |= db[index(Colour::WHITE, to, wksq, psq)];
+        }
+    }
+
+    if r & good != 0 {
+        good
+    } else if r & UNKNOWN != 0 {
+        UNKNOWN
+    } else {
+        bad
+    }
+}
+
+fn generate() -> Vec<u64> {
+    let mut db = (0..MAX_INDEX).map(initial_result).collect::<Vec<_>>();
+
+    // iterate until none of the unknown positions can be resolved.
+    let mut repeat = true;
+    while repeat {
+        repeat = false;
+        for idx in 0..MAX_INDEX {
+            if db[idx] == UNKNOWN {
+                db[idx] = classify(&db, idx);
+                repeat |= db[idx] != UNKNOWN;
+            }
+        }
+    }
+
+    let mut bitbase = vec![0; MAX_INDEX / 64];
+    for (idx, &result) in db.iter().enumerate() {
+        if result == WIN {
+            bitbase[idx / 64] |= 1 << (idx % 64);
+        }
+    }
+    bitbase
+}
+
+/// Generates the bitbase, if it hasn't been generated already.
+pub fn init() {
+    BITBASE.get_or_init(generate);
+}
+
+/// Returns true if the side with the pawn (`strong`) wins with best play.
+pub fn probe(strong: Colour, strong_king: Square, pawn: Square, weak_king: Square, stm: Colour) -> bool {
+    let (mut wksq, mut psq, mut bksq) = (strong_king, pawn, weak_king);
+    let mut stm = stm;
+    if strong == Colour::BLACK {
+        wksq = wksq.flip_rank();
+        psq = psq.flip_rank();
+        bksq = bksq.flip_rank();
+        stm = stm.flip();
+    }
+    if psq.file() > 3 {
+        wksq = wksq.flip_file();
+        psq = psq.flip_file();
+        bksq = bksq.flip_file();
+    }
+    let idx = index(stm, bksq, wksq, psq);
+    BITBASE.get_or_init(generate)[idx / 64] & (1 << (idx % 64)) != 0
+}
+
+mod tests {
+    #[test]
+    fn kpk_opposition() {
+        use crate::{piece::Colour, util::Square};
+        // white king e5, pawn e4, black king e7: whoever moves loses the opposition.
+        assert!(!super::probe(Colour::WHITE, Square::E5, Square::E4, Square::E7, Colour::WHITE));
+        assert!(super::probe(Colour::WHITE, Square::E5, Square::E4, Square::E7, Colour::BLACK));
+        // the same position, with the colours reversed.
+        assert!(!super::probe(Colour::BLACK, Square::E4, Square::E5, Square::E2, Colour::BLACK));
+        assert!(super::probe(Colour::BLACK, Square::E4, Square::E5, Square::E2, Colour::WHITE));
+        // with the king on the sixth rank, the pawn always wins.
+        assert!(super::probe(Colour::WHITE, Square::E6, Square::E5, Square::E8, Colour::WHITE));
+    }
+
+    #[test]
+    fn kpk_rook_pawn() {
+        use crate::{piece::Colour, util::Square};
+        // the defending king reaches the corner in time.
+        assert!(!super::probe(Colour::WHITE, Square::H1, Square::A4, Square::C8, Colour::WHITE));
+        // the pawn outruns the defending king.
+        assert!(super::probe(Colour::WHITE, Square::A1, Square::A2, Square::H8, Colour::WHITE));
+    }
+}
@@ -0,0 +1,272 @@
+#![warn(clippy::all, clippy::pedantic, clippy::nursery, clippy::cargo)]
+#![allow(clippy::multiple_crate_versions)]
+#![deny(missing_docs)]
+
+//! Viridithas, a UCI chess engine written in Rust.
+//!
+//! As well as the `viridithas` binary, the engine can be embedded as a library through [`Engine`].
+
+use std::io::IsTerminal;
+
+use crate::{nnue::network, search::parameters::Config};
+
+#[macro_use]
+mod macros;
+
+mod bench;
+mod board;
+mod book;
+mod chessmove;
+mod cli;
+mod cuckoo;
+mod errors;
+mod evalcache;
+mod gauntlet;
+mod historytable;
+mod image;
+mod kpk;
+mod logging;
+mod lookups;
+mod magic;
+mod makemove;
+mod nnue;
+mod perft;
+mod piece;
+mod rng;
+mod search;
+mod searchinfo;
+mod selfplay;
+mod selftest;
+mod sprt;
+mod squareset;
+mod tablebases;
+mod testsuite;
+mod threadlocal;
+mod timemgmt;
+mod transpositiontable;
+mod uci;
+mod util;
+mod xboard;
+
+mod datagen;
+mod engine;
+
+pub use bench::{Microbench, Subsystem};
+pub use engine::{Engine, Limit, PositionError, Score, ScoreBound, SearchResult, SearchUpdate};
+pub use searchinfo::SearchOptions;
+
+/// The name of the engine.
+pub static NAME: &str = "Viridithas";
+/// The version of the engine.
+pub static VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Runs the command-line interface of the `viridithas` binary.
+///
+/// # Panics
+///
+/// Panics if the engine's standard input or output is closed unexpectedly.
+#[allow(clippy::too_many_lines)]
+pub fn run() {
+    #[cfg(debug_assertions)]
+    std::env::set_var("RUST_BACKTRACE", "1");
+
+    if std::env::args_os().len() == 1 {
+        // fast path to UCI:
+        return uci::main_loop(false, std::io::stdout().is_terminal(), None);
+    }
+
+    let cli = <cli::Cli as clap::Parser>::parse();
+
+    if let Some(seed) = cli.seed {
+        uci::SEED.store(seed, std::sync::atomic::Ordering::SeqCst);
+    }
+
+    if let Some(config) = cli.datagen {
+        #[cfg(feature = "datagen")]
+        return datagen::gen_data_main(config.as_deref(), cli.datagen_book.as_deref());
+        #[cfg(not(feature = "datagen"))]
+        {
+            std::mem::drop(config);
+            println!("datagen feature not enabled (compile with --features datagen)");
+            return;
+        }
+    }
+
+    if let Some(input) = cli.splat {
+        let Some(output) = cli.output else {
+            println!("Output path required for splatting (use --output)");
+            return;
+        };
+        return datagen::run_splat(&input, &output, true, cli.marlinformat, cli.limit);
+    }
+
+    if let Some(input) = cli.topgn {
+        let Some(output) = cli.output else {
+            println!("Output path required for PGN conversion (use --output)");
+            return;
+        };
+        return datagen::run_topgn(&input, &output, cli.limit);
+    }
+
+    if let Some(data_path) = cli.dataset_stats {
+        return datagen::dataset_stats(&data_path);
+    }
+
+    if let Some(path) = &cli.log {
+        if let Err(e) = logging::open(path) {
+            return eprintln!("failed to open log file {}: {e}", path.display());
+        }
+        *uci::LOG_FILE.lock().expect("failed to lock log file path") = path.display().to_string();
+    }
+
+    if cli.xboard {
+        xboard::main_loop(&std::sync::Mutex::new(uci::stdin_reader()));
+        return logging::close();
+    }
+
+    if let Some(path) = &cli.perfttest {
+        if let Err(e) = perft::gamut(path.as_deref()) {
+            eprintln!("{e}");
+        }
+        return;
+    }
+
+    if cli.spsajson {
+        return println!("{}", Config::default().emit_json_for_spsa());
+    }
+
+    if cli.spsaob {
+        return println!("{}", Config::default().emit_csv_for_spsa());
+    }
+
+    if cli.visnnue {
+        return network::visualise_nnue();
+    }
+
+    if matches!(cli.subcommand, Some(cli::Subcommands::Selftest)) {
+        return selftest::run();
+    }
+
+    if let Some(cli::Subcommands::Script { path }) = &cli.subcommand {
+        if let Err(e) = uci::run_script(path, cli.pretty) {
+            eprintln!("failed to read script {}: {e}", path.display());
+        }
+        return logging::close();
+    }
+
+    if let Some(cli::Subcommands::Testsuite { path, movetime, threads, hash }) = &cli.subcommand {
+        let options = testsuite::TestsuiteOptions {
+            path,
+            movetime: std::time::Duration::from_millis(*movetime),
+            threads: *threads,
+            hash_mb: *hash,
+        };
+        if let Err(e) = testsuite::run(&options) {
+            eprintln!("{e}");
+        }
+        return;
+    }
+
+    if let Some(cli::Subcommands::Match { opponent, book, tc, games, pgn, hash }) = &cli.subcommand {
+        let Some((time, increment)) = cli::parse_time_control(tc) else {
+            return eprintln!("Invalid time control \"{tc}\", expected seconds+increment, e.g. \"10+0.1\"");
+        };
+        let options = gauntlet::MatchOptions {
+            opponent: opponent.clone(),
+            book: book.clone(),
+            time,
+            increment,
+            games: *games,
+            pgn: pgn.clone(),
+            hash_mb: *hash,
+        };
+        if let Err(e) = gauntlet::run_match(&options) {
+            eprintln!("{e}");
+        }
+        return;
+    }
+
+    if let Some(cli::Subcommands::Sprt { params, base, book, nodes, elo0, elo1, max_games, threads }) = &cli.subcommand
+    {
+        return sprt::run_sprt(&sprt::SprtOptions {
+            test: params.clone(),
+            base: base.clone(),
+            book: book.clone(),
+            nodes: *nodes,
+            elo0: *elo0,
+            elo1: *elo1,
+            threads: *threads,
+            max_games: *max_games,
+        });
+    }
+
+    if let Some(cli::Subcommands::Selfplay { tc, nodes, games, book, pgn, hash }) = &cli.subcommand {
+        let limit = if let Some(nodes) = nodes {
+            selfplay::MoveLimit::Nodes(*nodes)
+        } else {
+            let tc = tc.as_deref().unwrap_or("10+0.1");
+            let Some((time, increment)) = cli::parse_time_control(tc) else {
+                return eprintln!("Invalid time control \"{tc}\", expected seconds+increment, e.g. \"10+0.1\"");
+            };
+            selfplay::MoveLimit::Clock { time, increment }
+        };
+        let options =
+            selfplay::SelfplayOptions { book: book.clone(), limit, games: *games, pgn: pgn.clone(), hash_mb: *hash };
+        if let Err(e) = selfplay::run_selfplay(&options) {
+            eprintln!("{e}");
+        }
+        return;
+    }
+
+    if let Some(cli::Subcommands::Convert { input, output, from, to, no_checks, no_captures, max_score, limit }) =
+        &cli.subcommand
+    {
+        let options = datagen::convert::ConvertOptions {
+            input,
+            output,
+            from: *from,
+            to: *to,
+            skip_checks: *no_checks,
+            skip_captures: *no_captures,
+            max_score: *max_score,
+            limit: *limit,
+        };
+        if let Err(e) = datagen::convert::run_convert(&options) {
+            eprintln!("{e}");
+        }
+        return;
+    }
+
+    if let Some(cli::Subcommands::Makebook { pgn, output, max_ply, results, min_games }) = &cli.subcommand {
+        let options = book::MakebookOptions { pgn, output, max_ply: *max_ply, results, min_games: *min_games };
+        if let Err(e) = book::run_makebook(&options) {
+            eprintln!("{e}");
+        }
+        return;
+    }
+
+    if let Some(cli::Subcommands::Bench { target: Some(target) }) = cli.subcommand {
+        return bench::run_microbench(target);
+    }
+
+    if let Some(cli::Subcommands::Perft { fen, depth, divide, threads }) = cli.subcommand {
+        let mut pos = board::Board::default();
+        if let Some(fen) = fen {
+            if let Err(e) = pos.set_from_fen(&fen) {
+                return eprintln!("Invalid FEN: {e}");
+            }
+        }
+        if divide {
+            perft::run_divide(&mut pos, depth, threads);
+        } else {
+            perft::run_perft(&mut pos, depth, threads);
+        }
+        return;
+    }
+
+    uci::main_loop(
+        matches!(cli.subcommand, Some(cli::Subcommands::Bench { target: None })),
+        cli.pretty || std::io::stdout().is_terminal(),
+        cli.config.as_deref(),
+    );
+}
@@ -0,0 +1,83 @@
+// Optional logging of protocol traffic, for debugging problems with GUIs.
+// Every line the engine reads or writes is timestamped and sent over a channel to a
+// writer thread, so the search never waits on file I/O. Lines read from stdin are
+// marked with "<<", lines written to stdout with ">>", and warnings with "!!".
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Mutex,
+    },
+    thread::JoinHandle,
+};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static WRITER: Mutex<Option<(mpsc::Sender<String>, JoinHandle<()>)>> = Mutex::new(None);
+
+/// Starts logging to the file at `path`, replacing any log that is already open.
+pub fn open(path: &Path) -> io::Result<()> {
+    let file = File::create(path)?;
+    let (sender, receiver) = mpsc::channel::<String>();
+    let handle = std::thread::Builder::new().name("log-writer".into()).spawn(move || {
+        let mut out = BufWriter::new(file);
+        // the channel closes when the log is closed or replaced.
+        while let Ok(line) = receiver.recv() {
+            // write out anything else that's queued before flushing, so bursts of output are written together.
+            let written = std::iter::once(line)
+                .chain(receiver.try_iter())
+                .try_for_each(|line| writeln!(out, "{line}"))
+                .and_then(|()| out.flush());
+            if written.is_err() {
+                break;
+            }
+        }
+    })?;
+    close();
+    *WRITER.lock().unwrap() = Some((sender, handle));
+    ENABLED.store(true, Ordering::SeqCst);
+    Ok(())
+}
+
+/// Stops logging, waiting for anything that is still queued to be written.
+pub fn close() {
+    ENABLED.store(false, Ordering::SeqCst);
+    let writer = WRITER.lock().unwrap().take();
+    if let Some((sender, handle)) = writer {
+        drop(sender);
+        // a panic in the writer thread has already been reported, and there's nothing more to do about it.
+        let _ = handle.join();
+    }
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+fn record(marker: &str, text: &str) {
+    let Ok(writer) = WRITER.lock() else {
+        return;
+    };
+    let Some((sender, _)) = writer.as_ref() else {
+        return;
+    };
+    let timestamp = chrono::Local::now().format("%Y-%m-%d %H:%M:%S%.3f");
+    for line in text.lines() {
+        // if the writer thread has died there's nowhere to report it, so the error is dropped.
+        let _ = sender.send(format!("{timestamp} {marker} {line}"));
+    }
+}
+
+pub fn record_input(text: &str) {
+    record("<<", text);
+}
+
+pub fn record_output(text: &str) {
+    record(">>", text);
+}
+
+pub fn record_warning(text: &str) {
+    record("!!", text);
+}
@@ -18,7 +18,7 @@ macro_rules! cfor {
     }
 }
 
-const fn init_hash_keys() -> ([[u64; 64]; 13], [u64; 16], u64) {
+pub const fn init_hash_keys() -> ([[u64; 64]; 13], [u64; 16], u64) {
     let mut state = XorShiftState::new();
     let mut piece_keys = [[0; 64]; 13];
     cfor!(let mut index = 0; index < 13; index += 1; {
@@ -33,4 +33,52 @@ macro_rules! min {
     };
 }
 
+/// Like `println!`, but also records the line in the log file, if one is open.
+macro_rules! println_logged {
+    ($($arg:tt)*) => {{
+        if $crate::logging::enabled() {
+            let line = format!($($arg)*);
+            println!("{line}");
+            $crate::logging::record_output(&line);
+        } else {
+            println!($($arg)*);
+        }
+    }};
+}
+
+/// Like `print!`, but also records the text in the log file, if one is open.
+macro_rules! print_logged {
+    ($($arg:tt)*) => {{
+        if $crate::logging::enabled() {
+            let text = format!($($arg)*);
+            print!("{text}");
+            $crate::logging::record_output(&text);
+        } else {
+            print!($($arg)*);
+        }
+    }};
+}
+
+/// Like `eprintln!`, but also records the line in the log file as a warning, if one is open.
+macro_rules! eprintln_logged {
+    ($($arg:tt)*) => {{
+        if $crate::logging::enabled() {
+            let line = format!($($arg)*);
+            eprintln!("{line}");
+            $crate::logging::record_warning(&line);
+        } else {
+            eprintln!($($arg)*);
+        }
+    }};
+}
+
+/// Prints an `info string` diagnostic, if the GUI has switched on debug mode.
+macro_rules! debug_info {
+    ($($arg:tt)*) => {
+        if $crate::uci::DEBUG.load(std::sync::atomic::Ordering::Relaxed) {
+            println_logged!("info string {}", format_args!($($arg)*));
+        }
+    };
+}
+
 pub(crate) use inconceivable;
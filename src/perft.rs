@@ -3,16 +3,58 @@
 use std::{
     fs::File,
     io::{BufRead, BufReader},
-    sync::atomic::Ordering,
+    path::Path,
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+    time::Instant,
 };
 
 #[cfg(test)]
 use crate::threadlocal::ThreadData;
 use crate::{
     board::{movegen::MoveList, Board},
+    chessmove::Move,
     uci::CHESS960,
+    util::MEGABYTE,
 };
 
+/// The size of the table used to cache subtree counts in hashed perft.
+const PERFT_TABLE_BYTES: usize = 16 * MEGABYTE;
+
+#[derive(Clone, Copy, Default)]
+struct PerftEntry {
+    key: u64,
+    depth: u64,
+    nodes: u64,
+}
+
+/// A hash table of subtree node counts, keyed by position and depth.
+pub struct PerftTable {
+    entries: Vec<PerftEntry>,
+}
+
+impl PerftTable {
+    pub fn new() -> Self {
+        Self { entries: vec![PerftEntry::default(); PERFT_TABLE_BYTES / std::mem::size_of::<PerftEntry>()] }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    const fn index(&self, key: u64, depth: usize) -> usize {
+        // mix the depth into the index, so that the same position
+        // at different depths doesn't always compete for one slot.
+        (key ^ (depth as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)) as usize % self.entries.len()
+    }
+
+    fn probe(&self, key: u64, depth: usize) -> Option<u64> {
+        let entry = self.entries[self.index(key, depth)];
+        (entry.key == key && entry.depth == depth as u64).then_some(entry.nodes)
+    }
+
+    fn store(&mut self, key: u64, depth: usize, nodes: u64) {
+        let index = self.index(key, depth);
+        self.entries[index] = PerftEntry { key, depth: depth as u64, nodes };
+    }
+}
+
 pub fn perft(pos: &mut Board, depth: usize) -> u64 {
     #[cfg(debug_assertions)]
     pos.check_validity().unwrap();
@@ -24,6 +66,11 @@ pub fn perft(pos: &mut Board, depth: usize) -> u64 {
     let mut ml = MoveList::new();
     pos.generate_moves(&mut ml);
 
+    // the move generator is fully legal, so the leaves can be counted without making them.
+    if depth == 1 {
+        return ml.len() as u64;
+    }
+
     let mut count = 0;
     for &m in ml.iter_moves() {
         if !pos.make_move_simple(m) {
@@ -36,6 +83,101 @@ pub fn perft(pos: &mut Board, depth: usize) -> u64 {
     count
 }
 
+/// Perft, reusing the counts of subtrees that have been seen before.
+pub fn perft_hashed(pos: &mut Board, depth: usize, table: &mut PerftTable) -> u64 {
+    #[cfg(debug_assertions)]
+    pos.check_validity().unwrap();
+
+    // the bottom two plies are too cheap to be worth caching.
+    if depth <= 1 {
+        return perft(pos, depth);
+    }
+
+    if let Some(nodes) = table.probe(pos.hashkey(), depth) {
+        return nodes;
+    }
+
+    let mut ml = MoveList::new();
+    pos.generate_moves(&mut ml);
+
+    let mut count = 0;
+    for &m in ml.iter_moves() {
+        if !pos.make_move_simple(m) {
+            continue;
+        }
+        count += perft_hashed(pos, depth - 1, table);
+        pos.unmake_move_base();
+    }
+
+    table.store(pos.hashkey(), depth, count);
+
+    count
+}
+
+/// Counts the leaf nodes under each legal move in the position.
+/// The root moves are shared out between `threads` threads, each
+/// of which works on its own copy of the board.
+pub fn divide(pos: &Board, depth: usize, threads: usize) -> Vec<(Move, u64)> {
+    debug_assert!(depth > 0, "divide requires a depth of at least one");
+    let root_moves = pos.legal_moves();
+    let next_move = AtomicUsize::new(0);
+    let counts = root_moves.iter().map(|_| AtomicU64::new(0)).collect::<Vec<_>>();
+
+    std::thread::scope(|s| {
+        for _ in 0..threads.max(1) {
+            s.spawn(|| {
+                let mut pos = pos.clone();
+                let mut table = PerftTable::new();
+                loop {
+                    let i = next_move.fetch_add(1, Ordering::Relaxed);
+                    let Some(&m) = root_moves.get(i) else {
+                        break;
+                    };
+                    pos.make_move_simple(m);
+                    counts[i].store(perft_hashed(&mut pos, depth - 1, &mut table), Ordering::Relaxed);
+                    pos.unmake_move_base();
+                }
+            });
+        }
+    });
+
+    root_moves.into_iter().zip(counts.into_iter().map(AtomicU64::into_inner)).collect()
+}
+
+/// Runs perft on the position, reporting the node count and speed.
+pub fn run_perft(pos: &mut Board, depth: usize, threads: usize) {
+    #![allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+    let start_time = Instant::now();
+    let nodes = if threads > 1 && depth > 1 {
+        divide(pos, depth, threads).into_iter().map(|(_, nodes)| nodes).sum()
+    } else {
+        perft_hashed(pos, depth, &mut PerftTable::new())
+    };
+    let elapsed = start_time.elapsed();
+    let nps = nodes as f64 / elapsed.as_secs_f64();
+    println_logged!("info depth {depth} nodes {nodes} time {elapsed} nps {nps:.0}", elapsed = elapsed.as_millis());
+}
+
+/// Runs perft on the position, reporting the node count under each root move.
+pub fn run_divide(pos: &mut Board, depth: usize, threads: usize) {
+    #![allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+    if depth == 0 {
+        return run_perft(pos, depth, threads);
+    }
+    let start_time = Instant::now();
+    let mut nodes = 0;
+    for (m, arm_nodes) in divide(pos, depth, threads) {
+        nodes += arm_nodes;
+        println_logged!("{m}: {arm_nodes}");
+    }
+    let elapsed = start_time.elapsed();
+    println_logged!(
+        "info depth {depth} nodes {nodes} time {elapsed} nps {nps:.0}",
+        elapsed = elapsed.as_millis(),
+        nps = nodes as f64 / elapsed.as_secs_f64()
+    );
+}
+
 #[cfg(test)]
 pub fn nnue_perft(pos: &mut Board, t: &mut ThreadData, depth: usize) -> u64 {
     #[cfg(debug_assertions)]
@@ -87,67 +229,92 @@ pub fn movepicker_perft(pos: &mut Board, t: &mut ThreadData, depth: usize) -> u6
     count
 }
 
-pub fn gamut() {
-    #[cfg(debug_assertions)]
-    const NODES_LIMIT: u64 = 60_000;
-    #[cfg(not(debug_assertions))]
-    const NODES_LIMIT: u64 = 60_000_000;
-    // open perftsuite.epd
-    println!("running perft on perftsuite.epd");
-    let f = File::open("epds/perftsuite.epd").unwrap();
-    let mut pos = Board::new();
-    for line in BufReader::new(f).lines() {
-        let line = line.unwrap();
-        let mut parts = line.split(';');
-        let fen = parts.next().unwrap().trim();
-        pos.set_from_fen(fen).unwrap();
-        for depth_part in parts {
-            let depth_part = depth_part.trim();
-            let (d, nodes) = depth_part.split_once(' ').unwrap();
-            let d = d.chars().nth(1).unwrap().to_digit(10).unwrap();
-            let nodes = nodes.parse::<u64>().unwrap();
-            if nodes > NODES_LIMIT {
-                println!("Skipping...");
-                break;
-            }
-            let perft_nodes = perft(&mut pos, d as usize);
-            if perft_nodes == nodes {
-                println!("PASS: fen {fen}, depth {d}");
-            } else {
-                println!("FAIL: fen {fen}, depth {d}: expected {nodes}, got {perft_nodes}");
-                panic!("perft failed");
-            }
-        }
+/// One line of a perft EPD file: a position and the expected node counts at some depths.
+#[derive(Debug, PartialEq, Eq)]
+struct PerftRecord {
+    fen: String,
+    /// (depth, nodes) pairs, in the order given.
+    counts: Vec<(usize, u64)>,
+}
+
+/// Parses a line in the standard `<fen> ;D1 20 ;D2 400` format.
+/// The move counters may be left off the FEN, as they are in most EPD files.
+fn parse_perft_record(line: &str) -> Result<PerftRecord, String> {
+    let mut parts = line.split(';');
+    let fen = parts.next().unwrap_or_default().trim();
+    let fen = if fen.split_whitespace().count() == 4 { format!("{fen} 0 1") } else { fen.to_string() };
+    let counts = parts
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let parsed = part
+                .strip_prefix('D')
+                .and_then(|part| part.split_once(char::is_whitespace))
+                .and_then(|(depth, nodes)| Some((depth.parse().ok()?, nodes.trim().parse().ok()?)));
+            parsed.ok_or_else(|| format!("expected a depth and node count like \"D1 20\", got \"{part}\""))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if counts.is_empty() {
+        return Err(format!("no node counts in \"{line}\""));
     }
-    // open frcperftsuite.epd
-    println!("running perft on frcperftsuite.epd");
-    CHESS960.store(true, Ordering::SeqCst);
-    let f = File::open("epds/frcperftsuite.epd").unwrap();
+    Ok(PerftRecord { fen, counts })
+}
+
+/// Checks the move generator against every position in a perft EPD file, skipping
+/// depths with more than `nodes_limit` nodes. Positions with Shredder-FEN castling
+/// rights are set up as Chess960. Returns the number of counts that didn't match.
+fn run_epd(path: &Path, nodes_limit: u64, table: &mut PerftTable) -> Result<usize, String> {
+    let f = File::open(path).map_err(|e| format!("failed to open {}: {e}", path.display()))?;
+    let mut failures = 0;
     let mut pos = Board::new();
-    for line in BufReader::new(f).lines() {
-        let line = line.unwrap();
-        let mut parts = line.split(';');
-        let fen = parts.next().unwrap().trim();
-        pos.set_from_fen(fen).unwrap();
-        for depth_part in parts {
-            let depth_part = depth_part.trim();
-            let (d, nodes) = depth_part.split_once(' ').unwrap();
-            let d = d.chars().nth(1).unwrap().to_digit(10).unwrap();
-            let nodes = nodes.parse::<u64>().unwrap();
-            if nodes > NODES_LIMIT {
+    for (number, line) in BufReader::new(f).lines().enumerate() {
+        let line = line.map_err(|e| e.to_string())?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let record = parse_perft_record(line).map_err(|e| format!("line {}: {e}", number + 1))?;
+        let castling = record.fen.split_whitespace().nth(2).unwrap_or("-");
+        CHESS960.store(!castling.bytes().all(|c| b"KQkq-".contains(&c)), Ordering::SeqCst);
+        pos.set_from_fen(&record.fen).map_err(|e| format!("line {}: {e}", number + 1))?;
+        for &(depth, nodes) in &record.counts {
+            if nodes > nodes_limit {
                 println!("Skipping...");
                 break;
             }
-            let perft_nodes = perft(&mut pos, d as usize);
+            let perft_nodes = perft_hashed(&mut pos, depth, table);
             if perft_nodes == nodes {
-                println!("PASS: fen {fen}, depth {d}");
+                println!("PASS: fen {}, depth {depth}", record.fen);
             } else {
-                println!("FAIL: fen {fen}, depth {d}: expected {nodes}, got {perft_nodes}");
-                panic!("perft failed");
+                println!("FAIL: fen {}, depth {depth}: expected {nodes}, got {perft_nodes}", record.fen);
+                failures += 1;
             }
         }
     }
-    CHESS960.store(false, Ordering::SeqCst);
+    Ok(failures)
+}
+
+/// Runs the perft suite in `path`, or the standard and FRC suites in `epds/` if no path is given.
+pub fn gamut(path: Option<&Path>) -> Result<(), String> {
+    #[cfg(debug_assertions)]
+    const NODES_LIMIT: u64 = 60_000;
+    #[cfg(not(debug_assertions))]
+    const NODES_LIMIT: u64 = 60_000_000;
+    let paths = path
+        .map_or_else(|| vec![Path::new("epds/perftsuite.epd"), Path::new("epds/frcperftsuite.epd")], |path| vec![path]);
+    let chess960 = CHESS960.load(Ordering::SeqCst);
+    let mut table = PerftTable::new();
+    let mut failures = 0;
+    for path in paths {
+        println!("running perft on {}", path.display());
+        let result = run_epd(path, NODES_LIMIT, &mut table);
+        CHESS960.store(chess960, Ordering::SeqCst);
+        failures += result?;
+    }
+    if failures > 0 {
+        return Err(format!("perft failed: {failures} node counts did not match"));
+    }
+    Ok(())
 }
 
 mod tests {
@@ -177,6 +344,33 @@ mod tests {
         // assert_eq!(perft(&mut pos, 4), 4_085_603);
     }
 
+    #[test]
+    fn hashed_perft_matches_perft() {
+        use super::*;
+        const TEST_FEN: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+        let mut pos = Board::new();
+        pos.set_from_fen(TEST_FEN).unwrap();
+        let mut table = PerftTable::new();
+        assert_eq!(perft_hashed(&mut pos, 3, &mut table), 97_862);
+        // a second run is served from the table.
+        assert_eq!(perft_hashed(&mut pos, 3, &mut table), 97_862);
+    }
+
+    #[test]
+    fn divide_sums_to_perft() {
+        use super::*;
+
+        let pos = Board::default();
+        let arms = divide(&pos, 3, 1);
+        assert_eq!(arms.len(), 20);
+        assert_eq!(arms.iter().map(|&(_, nodes)| nodes).sum::<u64>(), 8_902);
+        let e2e4 = arms.iter().find(|(m, _)| m.to_string() == "e2e4").unwrap();
+        assert_eq!(e2e4.1, 600);
+        // splitting the root moves between threads doesn't change the counts.
+        assert_eq!(divide(&pos, 3, 4), arms);
+    }
+
     #[test]
     fn perft_start_position() {
         use super::*;
@@ -293,4 +487,18 @@ mod tests {
         println!("{bb_returned}", bb_returned = pos.pieces);
         assert_eq!(pos.hashkey(), hashkey_before);
     }
+
+    #[test]
+    fn perft_records_parse() {
+        use super::parse_perft_record;
+
+        let record = parse_perft_record("4k3/8/8/8/8/8/8/4K2R w K - ;D1 15 ;D2 66 ;D10 12").unwrap();
+        assert_eq!(record.fen, "4k3/8/8/8/8/8/8/4K2R w K - 0 1");
+        assert_eq!(record.counts, [(1, 15), (2, 66), (10, 12)]);
+        let full = parse_perft_record("4k3/8/8/8/8/8/8/4K2R w K - 3 20 ;D1 15").unwrap();
+        assert_eq!(full.fen, "4k3/8/8/8/8/8/8/4K2R w K - 3 20");
+        assert!(parse_perft_record("4k3/8/8/8/8/8/8/4K2R w K - 0 1").is_err());
+        assert!(parse_perft_record("4k3/8/8/8/8/8/8/4K2R w K - 0 1 ;D1 lots").is_err());
+        assert!(parse_perft_record("4k3/8/8/8/8/8/8/4K2R w K - 0 1 ;P1 15").is_err());
+    }
 }
@@ -1,3 +1,15 @@
+// Random number generation.
+// `XorShiftState` is a fixed-seed generator used at compile time to build the zobrist keys and magics,
+// so its output must never change. `Xoshiro` (xoshiro256++, seeded through splitmix64) drives everything
+// stochastic at runtime - move variety, strength limiting, and opening randomisation in datagen and
+// SPRT - and takes its seed from the `Seed` UCI option or the `--seed` flag, so that runs can be repeated.
+
+use std::sync::atomic::Ordering;
+
+use rand::{RngCore, SeedableRng};
+
+use crate::uci;
+
 const SEED: u128 = 0x246C_CB2D_3B40_2853_9918_0A6D_BC3A_F444;
 pub struct XorShiftState {
     pub state: u128,
@@ -44,3 +56,116 @@ impl XorShiftState {
         first & second & third
     }
 }
+
+/// The splitmix64 generator, used to expand a single 64-bit seed into a full generator state.
+pub struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    pub const fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub const fn next(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+/// The xoshiro256++ generator. It implements `rand`'s traits, so it works with
+/// `gen_range`, `choose`, and friends.
+#[derive(Debug, Clone)]
+pub struct Xoshiro {
+    s: [u64; 4],
+}
+
+impl Xoshiro {
+    /// Creates a generator from the user's seed, or from entropy if no seed was set.
+    /// `stream` separates the generators used for different purposes, threads, or games,
+    /// so that each gets its own sequence from the one seed.
+    pub fn from_user_seed(stream: u64) -> Self {
+        Self::seed_from_u64(user_seed().wrapping_add(stream))
+    }
+}
+
+impl RngCore for Xoshiro {
+    fn next_u32(&mut self) -> u32 {
+        #[allow(clippy::cast_possible_truncation)]
+        let r = (self.next_u64() >> 32) as u32; // the high bits are the better ones.
+        r
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let [s0, s1, s2, s3] = &mut self.s;
+        let result = s0.wrapping_add(*s3).rotate_left(23).wrapping_add(*s0);
+        let t = *s1 << 17;
+        *s2 ^= *s0;
+        *s3 ^= *s1;
+        *s1 ^= *s2;
+        *s0 ^= *s3;
+        *s2 ^= t;
+        *s3 = s3.rotate_left(45);
+        result
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl SeedableRng for Xoshiro {
+    type Seed = [u8; 32];
+
+    fn from_seed(seed: Self::Seed) -> Self {
+        let mut s = [0; 4];
+        for (word, bytes) in s.iter_mut().zip(seed.chunks_exact(8)) {
+            *word = u64::from_le_bytes(bytes.try_into().unwrap());
+        }
+        if s == [0; 4] {
+            // the all-zero state is a fixed point.
+            return Self::seed_from_u64(0);
+        }
+        Self { s }
+    }
+
+    fn seed_from_u64(seed: u64) -> Self {
+        let mut sm = SplitMix64::new(seed);
+        Self { s: [sm.next(), sm.next(), sm.next(), sm.next()] }
+    }
+}
+
+/// The seed set by the user, or a fresh random one if none was set.
+/// Zero means "unset", as it's the default of the `Seed` option.
+pub fn user_seed() -> u64 {
+    match uci::SEED.load(Ordering::SeqCst) {
+        0 => rand::random(),
+        seed => seed,
+    }
+}
+
+mod tests {
+    #[test]
+    fn reference_outputs() {
+        use super::{SplitMix64, Xoshiro};
+        use rand::RngCore;
+
+        assert_eq!(SplitMix64::new(0).next(), 0xE220_A839_7B1D_CDAF);
+        let mut rng = Xoshiro { s: [1, 2, 3, 4] };
+        let outputs = [41_943_041, 58_720_359, 3_588_806_011_781_223, 3_591_011_842_654_386, 9_228_616_714_210_784_205];
+        for expected in outputs {
+            assert_eq!(rng.next_u64(), expected);
+        }
+    }
+}
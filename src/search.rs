@@ -2,19 +2,24 @@
 
 pub mod parameters;
 pub mod pv;
+pub mod strength;
+#[cfg(feature = "treedump")]
+pub mod treedump;
 
 use std::{
     ops::ControlFlow,
     sync::atomic::{AtomicU64, Ordering},
     thread,
+    time::Duration,
 };
 
 use arrayvec::ArrayVec;
+use rand::{seq::SliceRandom, SeedableRng};
 
 use crate::{
     board::{
         evaluation::{
-            is_game_theoretic_score, mate_in, mated_in, tb_loss_in, tb_win_in, MATE_SCORE, MINIMUM_MATE_SCORE,
+            is_game_theoretic_score, mate_in, mated_in, tb_loss_in, tb_win_in, KpkOutcome, MATE_SCORE,
             MINIMUM_TB_WIN_SCORE,
         },
         movegen::{
@@ -27,15 +32,16 @@ use crate::{
     cfor,
     chessmove::Move,
     piece::{Colour, PieceType},
+    rng::Xoshiro,
     search::pv::PVariation,
-    searchinfo::SearchInfo,
+    searchinfo::{Report, SearchInfo, Silent},
     tablebases::{self, probe::WDL},
     threadlocal::ThreadData,
     transpositiontable::{Bound, TTHit, TTView},
     uci,
     util::{
         depth::{Depth, ONE_PLY, ZERO_PLY},
-        INFINITY, MAX_DEPTH, VALUE_NONE,
+        INFINITY, MAX_DEPTH, MAX_PLY, VALUE_NONE,
     },
 };
 
@@ -52,6 +58,26 @@ use self::parameters::Config;
 // in alpha-beta, a call to alpha_beta(ALLNODE, alpha, beta) returns a score <= alpha.
 // Every move at an All-node is searched, and the score returned is an upper bound, so the exact score might be lower.
 
+/// Attaches a note to the current node of the recorded search tree.
+macro_rules! tree_note {
+    ($info:expr, $t:expr, $note:expr) => {
+        #[cfg(feature = "treedump")]
+        if $t.thread_id == 0 {
+            $info.tree.note($note);
+        }
+    };
+}
+
+/// Records a move that was pruned without being searched in the recorded search tree.
+macro_rules! tree_prune {
+    ($info:expr, $t:expr, $m:expr, $reason:expr) => {
+        #[cfg(feature = "treedump")]
+        if $t.thread_id == 0 {
+            $info.tree.prune($m, $reason);
+        }
+    };
+}
+
 const ASPIRATION_WINDOW: i32 = 6;
 const ASPIRATION_WINDOW_MIN_DEPTH: Depth = Depth::new(5);
 const RFP_MARGIN: i32 = 73;
@@ -91,10 +117,31 @@ const DO_DEEPER_BASE_MARGIN: i32 = 64;
 const DO_DEEPER_DEPTH_MARGIN: i32 = 11;
 const HISTORY_PRUNING_DEPTH: Depth = Depth::new(7);
 const HISTORY_PRUNING_MARGIN: i32 = -2500;
+const MAIN_HISTORY_WEIGHT: i32 = 1024;
+const CONT1_HISTORY_WEIGHT: i32 = 1024;
+const CONT2_HISTORY_WEIGHT: i32 = 1024;
+const QS_FUTILITY_MARGIN: i32 = 150;
+const QS_DELTA_MARGIN: i32 = 250;
+const PASSED_PUSH_DEPTH: Depth = Depth::new(6);
 
 const TIME_MANAGER_UPDATE_MIN_DEPTH: Depth = Depth::new(4);
+/// The maximum number of plies that check, recapture, and pawn-push
+/// extensions may add to a single line. Singular extensions are
+/// counted towards this total, but are limited separately.
+const MAX_CUMULATIVE_EXTENSIONS: i32 = 16;
+
+/// How many root moves are considered when playing something other than the best move.
+const RANDOMISED_ROOT_CANDIDATES: usize = 5;
 
-static TB_HITS: AtomicU64 = AtomicU64::new(0);
+/// How often the node count and hash usage are reported while no iteration finishes.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+/// With `UCI_ShowCurrLine`, the line being searched is reported once the main thread has spent
+/// this long on one root move, then at most once per interval, cut to this many plies.
+const CURRLINE_DELAY: Duration = Duration::from_secs(3);
+const CURRLINE_INTERVAL: Duration = Duration::from_secs(1);
+const CURRLINE_PLIES: usize = 8;
+
+pub static TB_HITS: AtomicU64 = AtomicU64::new(0);
 
 pub trait NodeType {
     const PV: bool;
@@ -153,30 +200,25 @@ impl Board {
 
         let legal_moves = self.legal_moves();
         if legal_moves.is_empty() {
-            eprintln!("info string warning search called on a position with no legal moves");
-            if self.in_check() {
-                println!("info depth 0 score mate 0");
-            } else {
-                println!("info depth 0 score cp 0");
-            }
-            println!("bestmove (none)");
+            info.sink.best_move(self, Move::NULL);
             return (0, Move::NULL);
         }
-        if legal_moves.len() == 1 {
+        // when analysing, a forced move still gets a full search, so that the GUI is shown where it leads.
+        if legal_moves.len() == 1 && !info.analysing {
             info.time_manager.notify_one_legal_move();
         }
 
         // Probe the tablebases if we're in a TB position.
-        // TODO: make this behave nicely if we're in analysis mode.
-        if let Some((best_move, score)) = tablebases::probe::get_tablebase_move(self) {
+        // In analysis mode, we search as normal instead, as the probe only yields a single move.
+        let tb_move = if info.analysing { None } else { tablebases::probe::get_tablebase_move(self) };
+        if let Some((best_move, score)) = tb_move {
+            debug_info!("tablebase root probe hit, playing {best_move}");
             let mut pv = PVariation::default();
             pv.load_from(best_move, &PVariation::default());
             pv.score = score;
             TB_HITS.store(1, Ordering::SeqCst);
             readout_info(self, Bound::Exact, &pv, 0, info, tt, 1, true);
-            if info.print_to_stdout {
-                println!("bestmove {best_move}");
-            }
+            info.sink.best_move(self, best_move);
             return (score, best_move);
         }
 
@@ -205,21 +247,33 @@ impl Board {
         let pv = best_thread.pv().clone();
         let best_move = pv.moves().first().copied().unwrap_or_else(|| self.default_move(&thread_headers[0]));
 
-        if info.print_to_stdout && info.skip_print() {
-            // we haven't printed any ID logging yet, so give one as we leave search.
-            let nodes = info.nodes.get_global();
-            readout_info(self, Bound::Exact, &pv, depth_achieved, info, tt, nodes, true);
-        }
+        let nodes = info.nodes.get_global();
+        let report = Report { bound: Bound::Exact, pv: &pv, depth: depth_achieved, nodes, tt, force: true };
+        info.sink.finish(self, info, &report);
+
+        let variety = info.options.variety;
+        let best_move = if info.options.limit_strength {
+            let candidates = best_root_moves(&thread_headers[0]);
+            strength::pick(&candidates, info.options.elo, &mut root_rng(self, info)).unwrap_or(best_move)
+        } else if variety > 0 && !is_game_theoretic_score(pv.score) {
+            // play any of the moves that are within the margin of the best one.
+            let candidates = best_root_moves(&thread_headers[0]);
+            let best_score = candidates.iter().map(|&(_, score)| score).max().unwrap_or(pv.score);
+            let close = candidates
+                .iter()
+                .filter(|&&(_, score)| uci::normalise_score(best_score) - uci::normalise_score(score) <= variety)
+                .map(|&(m, _)| m)
+                .collect::<Vec<_>>();
+            close.choose(&mut root_rng(self, info)).copied().unwrap_or(best_move)
+        } else {
+            best_move
+        };
 
-        if info.print_to_stdout {
-            println!("bestmove {best_move}");
-            #[cfg(feature = "stats")]
-            info.print_stats();
-            #[cfg(feature = "stats")]
-            println!(
-                "branching factor: {}",
-                (info.nodes.get_global() as f64).powf(1.0 / thread_headers[0].completed as f64)
-            );
+        info.sink.best_move(self, best_move);
+
+        #[cfg(feature = "stats")]
+        {
+            info.stats.completed_depth = depth_achieved;
         }
 
         assert!(legal_moves.contains(&best_move), "search returned an illegal move.");
@@ -244,6 +298,10 @@ impl Board {
                 if (info.time_manager.is_dynamic() || info.time_manager.is_soft_nodes())
                     && info.time_manager.is_past_opt_time(info.nodes.get_global())
                 {
+                    debug_info!(
+                        "stopping before depth {d}: past the optimal time after {}ms",
+                        info.time_manager.elapsed().as_millis()
+                    );
                     info.stopped.store(true, Ordering::SeqCst);
                     break 'deepening;
                 }
@@ -257,8 +315,8 @@ impl Board {
                 break 'deepening;
             };
 
-            if depth > ASPIRATION_WINDOW_MIN_DEPTH {
-                aw = AspirationWindow::around_value(average_value, depth);
+            if depth > info.conf.aspiration_window_min_depth {
+                aw = AspirationWindow::around_value(average_value, depth, &info.conf);
             } else {
                 aw = AspirationWindow::infinite();
             }
@@ -273,7 +331,11 @@ impl Board {
                 } else {
                     None
                 };
-                info.time_manager.report_completed_depth(depth, pv.score, pv.moves[0], bm_frac, &info.conf);
+                let best_move = pv.moves[0];
+                info.time_manager.notify_best_move_recaptures(
+                    self.is_recapture(best_move) && self.static_exchange_eval(best_move, 0),
+                );
+                info.time_manager.report_completed_depth(depth, pv.score, best_move, bm_frac, &info.conf);
             }
 
             if info.check_up() {
@@ -300,14 +362,20 @@ impl Board {
             }
 
             if aw.alpha != -INFINITY && pv.score <= aw.alpha {
-                if ThTy::MAIN_THREAD && info.print_to_stdout {
+                if ThTy::MAIN_THREAD && info.sink.listening() {
                     let nodes = info.nodes.get_global();
                     let mut apv = t.pv().clone();
                     apv.score = pv.score;
                     readout_info(self, Bound::Upper, &apv, d, info, t.tt, nodes, false);
                 }
-                aw.widen_down(pv.score, depth);
+                aw.widen_down(pv.score, depth, &info.conf);
                 if ThTy::MAIN_THREAD {
+                    debug_info!(
+                        "aspiration window failed low at depth {d} with score {}, re-searching in ({}, {})",
+                        pv.score,
+                        aw.alpha,
+                        aw.beta
+                    );
                     info.time_manager.report_aspiration_fail(depth, Bound::Upper, &info.conf);
                 }
                 // search failed low, so we might have to
@@ -318,12 +386,18 @@ impl Board {
             // search is either exact or fail-high, so we can update the best line.
             t.update_best_line(pv);
             if aw.beta != INFINITY && pv.score >= aw.beta {
-                if ThTy::MAIN_THREAD && info.print_to_stdout {
+                if ThTy::MAIN_THREAD && info.sink.listening() {
                     let nodes = info.nodes.get_global();
                     readout_info(self, Bound::Lower, t.pv(), d, info, t.tt, nodes, false);
                 }
-                aw.widen_up(pv.score, depth);
+                aw.widen_up(pv.score, depth, &info.conf);
                 if ThTy::MAIN_THREAD {
+                    debug_info!(
+                        "aspiration window failed high at depth {d} with score {}, re-searching in ({}, {})",
+                        pv.score,
+                        aw.alpha,
+                        aw.beta
+                    );
                     info.time_manager.report_aspiration_fail(depth, Bound::Lower, &info.conf);
                 }
                 // decrement depth:
@@ -344,7 +418,7 @@ impl Board {
             let bestmove = t.pvs[t.completed].moves().first().copied().unwrap_or_else(|| self.default_move(t));
             *average_value = if *average_value == VALUE_NONE { score } else { (2 * score + *average_value) / 3 };
 
-            if ThTy::MAIN_THREAD && info.print_to_stdout {
+            if ThTy::MAIN_THREAD && info.sink.listening() {
                 let total_nodes = info.nodes.get_global();
                 readout_info(self, Bound::Exact, t.pv(), d, info, t.tt, total_nodes, false);
             }
@@ -397,8 +471,28 @@ impl Board {
     }
 
     /// Perform a tactical resolution search, searching only captures and promotions.
-    #[allow(clippy::too_many_lines)]
     pub fn quiescence<NT: NodeType>(
+        &mut self,
+        pv: &mut PVariation,
+        info: &mut SearchInfo,
+        t: &mut ThreadData,
+        alpha: i32,
+        beta: i32,
+    ) -> i32 {
+        #[cfg(feature = "treedump")]
+        if t.thread_id == 0 {
+            info.tree.enter(self.last_move(), alpha, beta, ZERO_PLY, true);
+        }
+        let score = self.quiescence_inner::<NT>(pv, info, t, alpha, beta);
+        #[cfg(feature = "treedump")]
+        if t.thread_id == 0 {
+            info.tree.exit(score);
+        }
+        score
+    }
+
+    #[allow(clippy::too_many_lines)]
+    fn quiescence_inner<NT: NodeType>(
         &mut self,
         pv: &mut PVariation,
         info: &mut SearchInfo,
@@ -409,8 +503,16 @@ impl Board {
         #[cfg(debug_assertions)]
         self.check_validity().unwrap();
 
-        if info.nodes.just_ticked_over() && info.check_up() {
-            return 0;
+        if info.should_check_up() {
+            if info.check_up() {
+                return 0;
+            }
+            self.report_while_searching(info, t);
+        }
+
+        #[cfg(feature = "stats")]
+        {
+            info.stats.qsearch_nodes += 1;
         }
 
         let key = self.hashkey();
@@ -424,9 +526,23 @@ impl Board {
 
         // check draw
         if self.is_draw() {
+            tree_note!(info, t, "draw");
             return draw_score(t, info.nodes.get_local(), self.turn());
         }
 
+        // upcoming-repetition detection.
+        let draw = draw_score(t, info.nodes.get_local(), self.turn());
+        if alpha < draw && self.has_game_cycle(height) {
+            if draw >= beta {
+                tree_note!(info, t, "upcoming repetition");
+                return draw;
+            }
+            // PV nodes must keep a window wider than a null window.
+            if draw + 1 < beta {
+                alpha = draw;
+            }
+        }
+
         let in_check = self.in_check();
 
         // are we too deep?
@@ -444,6 +560,7 @@ impl Board {
                     || (hit.bound == Bound::Lower && hit.value >= beta)
                     || (hit.bound == Bound::Upper && hit.value <= alpha))
             {
+                tree_note!(info, t, "tt cutoff");
                 return hit.value;
             }
 
@@ -451,8 +568,10 @@ impl Board {
         } else {
             None
         };
+        // whether this node is, or has previously been, on the principal variation.
+        let tt_pv = NT::PV || tt_hit.is_some_and(|hit| hit.was_pv);
 
-        let stand_pat = if in_check {
+        let raw_eval = if in_check {
             -INFINITY // could be being mated!
         } else if let Some(TTHit { eval: tt_eval, .. }) = &tt_hit {
             let v = *tt_eval; // if we have a TT hit, check the cached TT eval.
@@ -465,13 +584,15 @@ impl Board {
             let v = self.evaluate(t, info.nodes.get_local()); // otherwise, use the static evaluation.
                                                               // store the eval into the TT if we won't overwrite anything:
             if tt_hit.is_none() {
-                t.tt.store(key, height, Move::NULL, VALUE_NONE, v, Bound::None, ZERO_PLY);
+                t.tt.store(key, height, Move::NULL, VALUE_NONE, v, Bound::None, ZERO_PLY, tt_pv);
             }
             v
         };
+        let stand_pat = if in_check { raw_eval } else { t.correct_evaluation(self, raw_eval) };
 
         if stand_pat >= beta {
             // return stand_pat instead of beta, this is fail-soft
+            tree_note!(info, t, "stand pat");
             return stand_pat;
         }
 
@@ -491,14 +612,27 @@ impl Board {
             move_picker.skip_quiets = true;
         }
 
-        let futility = stand_pat + 150;
+        let futility = stand_pat + info.conf.qs_futility_margin;
 
         while let Some(MoveListEntry { mov: m, .. }) = move_picker.next(self, t) {
-            if !in_check && futility <= alpha && !self.static_exchange_eval(m, 1) {
-                if best_score < futility {
-                    best_score = futility;
+            if !in_check {
+                // delta pruning:
+                // if winning the target outright still leaves us below alpha, the capture can't help.
+                let delta = stand_pat + self.estimated_see(m) + info.conf.qs_delta_margin;
+                if delta <= alpha {
+                    if best_score < delta {
+                        best_score = delta;
+                    }
+                    tree_prune!(info, t, m, "delta pruning");
+                    continue;
+                }
+                if futility <= alpha && !self.static_exchange_eval(m, 1) {
+                    if best_score < futility {
+                        best_score = futility;
+                    }
+                    tree_prune!(info, t, m, "qsearch futility pruning");
+                    continue;
                 }
-                continue;
             }
             t.tt.prefetch(self.key_after(m));
             if !self.make_move(m, t) {
@@ -519,7 +653,7 @@ impl Board {
                 }
                 if alpha >= beta {
                     #[cfg(feature = "stats")]
-                    info.log_fail_high::<true>(moves_made - 1, 0);
+                    info.stats.log_fail_high::<true>(moves_made - 1, 0);
                     break; // fail-high
                 }
             }
@@ -537,7 +671,7 @@ impl Board {
             Bound::Upper
         };
 
-        t.tt.store(key, height, best_move, best_score, stand_pat, flag, ZERO_PLY);
+        t.tt.store(key, height, best_move, best_score, raw_eval, flag, ZERO_PLY, tt_pv);
 
         best_score
     }
@@ -549,8 +683,30 @@ impl Board {
     }
 
     /// Perform alpha-beta minimax search.
-    #[allow(clippy::too_many_lines, clippy::cognitive_complexity)]
     pub fn alpha_beta<NT: NodeType>(
+        &mut self,
+        pv: &mut PVariation,
+        info: &mut SearchInfo,
+        t: &mut ThreadData,
+        depth: Depth,
+        alpha: i32,
+        beta: i32,
+        cut_node: bool,
+    ) -> i32 {
+        #[cfg(feature = "treedump")]
+        if t.thread_id == 0 {
+            info.tree.enter(self.last_move(), alpha, beta, depth, false);
+        }
+        let score = self.alpha_beta_inner::<NT>(pv, info, t, depth, alpha, beta, cut_node);
+        #[cfg(feature = "treedump")]
+        if t.thread_id == 0 {
+            info.tree.exit(score);
+        }
+        score
+    }
+
+    #[allow(clippy::too_many_lines, clippy::cognitive_complexity)]
+    fn alpha_beta_inner<NT: NodeType>(
         &mut self,
         pv: &mut PVariation,
         info: &mut SearchInfo,
@@ -577,8 +733,16 @@ impl Board {
 
         pv.moves.clear();
 
-        if info.nodes.just_ticked_over() && info.check_up() {
-            return 0;
+        if info.should_check_up() {
+            if info.check_up() {
+                return 0;
+            }
+            self.report_while_searching(info, t);
+        }
+
+        #[cfg(feature = "stats")]
+        {
+            info.stats.main_nodes += 1;
         }
 
         let height = self.height();
@@ -590,10 +754,25 @@ impl Board {
 
         if !NT::ROOT {
             // check draw
-            if self.is_draw() {
+            if self.is_draw() || self.probe_kpk() == Some(KpkOutcome::Draw) {
+                tree_note!(info, t, "draw");
                 return draw_score(t, info.nodes.get_local(), self.turn());
             }
 
+            // upcoming-repetition detection.
+            // if we can force a repetition, we can't do worse than a draw.
+            let draw = draw_score(t, info.nodes.get_local(), self.turn());
+            if alpha < draw && self.has_game_cycle(height) {
+                if draw >= beta {
+                    tree_note!(info, t, "upcoming repetition");
+                    return draw;
+                }
+                // PV nodes must keep a window wider than a null window.
+                if draw + 1 < beta {
+                    alpha = draw;
+                }
+            }
+
             // are we too deep?
             let max_height = MAX_DEPTH.ply_to_horizon().min(uci::GO_MATE_MAX_DEPTH.load(Ordering::SeqCst));
             if height >= max_height {
@@ -604,6 +783,7 @@ impl Board {
             alpha = alpha.max(mated_in(height));
             beta = beta.min(mate_in(height + 1));
             if alpha >= beta {
+                tree_note!(info, t, "mate distance pruning");
                 return alpha;
             }
         }
@@ -613,7 +793,13 @@ impl Board {
         let excluded = t.excluded[height];
         let fifty_move_rule_near = self.fifty_move_counter() >= 80;
         let tt_hit = if excluded.is_null() {
-            if let Some(hit) = t.tt.probe(key, height) {
+            let probe = t.tt.probe(key, height);
+            #[cfg(feature = "stats")]
+            {
+                info.stats.tt_probes += 1;
+                info.stats.tt_hits += u64::from(probe.is_some());
+            }
+            if let Some(hit) = probe {
                 if !NT::PV
                     && hit.depth >= depth
                     && !fifty_move_rule_near
@@ -621,6 +807,7 @@ impl Board {
                         || (hit.bound == Bound::Lower && hit.value >= beta)
                         || (hit.bound == Bound::Upper && hit.value <= alpha))
                 {
+                    tree_note!(info, t, "tt cutoff");
                     return hit.value;
                 }
 
@@ -635,6 +822,8 @@ impl Board {
         } else {
             None // do not probe the TT if we're in a singular-verification search.
         };
+        // whether this node is, or has previously been, on the principal variation.
+        let tt_pv = NT::PV || tt_hit.is_some_and(|hit| hit.was_pv);
 
         // Probe the tablebases.
         let (mut syzygy_max, mut syzygy_min) = (MATE_SCORE, -MATE_SCORE);
@@ -665,7 +854,8 @@ impl Board {
                     || (tb_bound == Bound::Lower && tb_value >= beta)
                     || (tb_bound == Bound::Upper && tb_value <= alpha)
                 {
-                    t.tt.store(key, height, Move::NULL, tb_value, VALUE_NONE, tb_bound, depth);
+                    t.tt.store(key, height, Move::NULL, tb_value, VALUE_NONE, tb_bound, depth, tt_pv);
+                    tree_note!(info, t, "tablebase cutoff");
                     return tb_value;
                 }
 
@@ -680,7 +870,7 @@ impl Board {
             }
         }
 
-        let static_eval = if in_check {
+        let raw_eval = if in_check {
             -INFINITY // when we're in check, it could be checkmate, so it's unsound to use evaluate().
         } else if !excluded.is_null() {
             t.evals[height] // if we're in a singular-verification search, we already have the static eval.
@@ -694,6 +884,9 @@ impl Board {
         } else {
             self.evaluate(t, info.nodes.get_local()) // otherwise, use the static evaluation.
         };
+        // the TT holds the raw evaluation, and the correction history is applied on top of it.
+        // singular-verification searches reuse the already-corrected value from the parent node.
+        let static_eval = if in_check || !excluded.is_null() { raw_eval } else { t.correct_evaluation(self, raw_eval) };
 
         t.evals[height] = static_eval;
 
@@ -705,6 +898,7 @@ impl Board {
         let improving = !in_check && height >= 2 && static_eval >= t.evals[height - 2];
 
         t.double_extensions[height] = if NT::ROOT { 0 } else { t.double_extensions[height - 1] };
+        t.extensions[height] = if NT::ROOT { 0 } else { t.extensions[height - 1] };
 
         // clear out the next set of killer moves.
         t.killer_move_table[height + 1] = [Move::NULL; 2];
@@ -717,6 +911,7 @@ impl Board {
             if static_eval < alpha - info.conf.razoring_coeff_0 - info.conf.razoring_coeff_1 * depth * depth {
                 let v = self.quiescence::<OffPV>(pv, info, t, alpha - 1, alpha);
                 if v < alpha {
+                    tree_note!(info, t, "razoring");
                     return v;
                 }
             }
@@ -725,6 +920,7 @@ impl Board {
             // if the static eval is too high, we can prune the node.
             // this is a lot like stand_pat in quiescence search.
             if depth <= info.conf.rfp_depth && static_eval - Self::rfp_margin(info, depth, improving) > beta {
+                tree_note!(info, t, "reverse futility pruning");
                 return (static_eval + beta) / 2;
             }
 
@@ -733,7 +929,8 @@ impl Board {
             // null-move pruning.
             // if we can give the opponent a free move while retaining
             // a score above beta, we can prune the node.
-            if !last_move_was_null
+            if info.null_move_pruning
+                && !last_move_was_null
                 && depth >= Depth::new(3)
                 && static_eval + i32::from(improving) * info.conf.nmp_improving_margin >= beta
                 && !t.nmp_banned_for(self.turn())
@@ -747,6 +944,10 @@ impl Board {
                         info.conf.max_nmp_eval_reduction,
                     );
                 let nm_depth = depth - r;
+                #[cfg(feature = "stats")]
+                {
+                    info.stats.nmp_attempts += 1;
+                }
                 t.tt.prefetch(self.key_after(Move::NULL));
                 self.make_nullmove();
                 let mut null_score = -self.alpha_beta::<OffPV>(l_pv, info, t, nm_depth, -beta, -beta + 1, !cut_node);
@@ -755,12 +956,17 @@ impl Board {
                     return 0;
                 }
                 if null_score >= beta {
+                    #[cfg(feature = "stats")]
+                    {
+                        info.stats.nmp_cutoffs += 1;
+                    }
                     // don't return game-theoretic scores:
                     if null_score >= MINIMUM_TB_WIN_SCORE {
                         null_score = beta;
                     }
                     // unconditionally cutoff if we're just too shallow.
                     if depth < info.conf.nmp_verification_depth && !is_game_theoretic_score(beta) {
+                        tree_note!(info, t, "null move pruning");
                         return null_score;
                     }
                     // verify that it's *actually* fine to prune,
@@ -772,6 +978,7 @@ impl Board {
                     let veri_score = self.alpha_beta::<OffPV>(l_pv, info, t, nm_depth, beta - 1, beta, false);
                     t.unban_nmp_for(self.turn());
                     if veri_score >= beta {
+                        tree_note!(info, t, "verified null move pruning");
                         return null_score;
                     }
                 }
@@ -781,15 +988,15 @@ impl Board {
         let mut tt_move = tt_hit.map_or(Move::NULL, |hit| hit.mov);
         let tt_capture = !tt_move.is_null() && self.is_capture(tt_move);
 
-        if cut_node && depth >= TT_REDUCTION_DEPTH * 2 && tt_move.is_null() {
+        if cut_node && depth >= info.conf.tt_reduction_depth * 2 && tt_move.is_null() {
             depth -= 1;
         }
 
-        let see_table = [info.conf.see_tactical_margin * depth.squared(), info.conf.see_quiet_margin * depth.round()];
+        let see_table = [info.conf.see_tactical_margin * depth.squared(), info.conf.see_quiet_margin * depth.trunc()];
 
         // store the eval into the TT if we won't overwrite anything:
         if tt_hit.is_none() && !in_check && excluded.is_null() {
-            t.tt.store(key, height, Move::NULL, VALUE_NONE, static_eval, Bound::None, ZERO_PLY);
+            t.tt.store(key, height, Move::NULL, VALUE_NONE, raw_eval, Bound::None, ZERO_PLY, tt_pv);
         }
 
         // probcut:
@@ -800,7 +1007,8 @@ impl Board {
         // as usual, don't probcut in PV / check / singular verification / if there are GT truth scores in flight.
         // additionally, if we have a TT hit that's sufficiently deep, we skip trying probcut if the TT value indicates
         // that it's not going to be helpful.
-        if !NT::PV
+        if info.probcut
+            && !NT::PV
             && !in_check
             && excluded.is_null()
             && depth >= info.conf.probcut_min_depth
@@ -835,7 +1043,8 @@ impl Board {
                 self.unmake_move(t);
 
                 if value >= pc_beta {
-                    t.tt.store(key, height, m, value, static_eval, Bound::Lower, depth - 3);
+                    t.tt.store(key, height, m, value, raw_eval, Bound::Lower, depth - 3, tt_pv);
+                    tree_note!(info, t, "probcut");
                     return value;
                 }
             }
@@ -864,13 +1073,18 @@ impl Board {
         let killers = self.get_killer_set(t);
         let counter_move = t.get_counter_move(self);
         let mut move_picker = MainMovePicker::new(tt_move, killers, counter_move, info.conf.main_see_bound);
+        move_picker.history_weights =
+            [info.conf.main_history_weight, info.conf.cont1_history_weight, info.conf.cont2_history_weight];
+        // close to the horizon, advancing passed pawns survive late move pruning and futility pruning.
+        let protect_passed_pushes = depth <= info.conf.passed_push_depth;
+        move_picker.keep_passed_pushes = protect_passed_pushes;
 
         let mut quiets_tried = ArrayVec::<_, MAX_POSITION_MOVES>::new();
         let mut tacticals_tried = ArrayVec::<_, MAX_POSITION_MOVES>::new();
 
         while let Some(MoveListEntry { mov: m, score: movepick_score }) = move_picker.next(self, t) {
             debug_assert!(!quiets_tried.as_slice().contains(&m) && !tacticals_tried.as_slice().contains(&m));
-            if NT::ROOT && uci::is_multipv() {
+            if NT::ROOT && !t.multi_pv_excluded.is_empty() {
                 // handle multi-pv
                 if t.multi_pv_excluded.contains(&m) {
                     continue;
@@ -884,13 +1098,14 @@ impl Board {
             let lmr_depth = std::cmp::max(depth - lmr_reduction, ZERO_PLY);
             let is_quiet = !self.is_tactical(m);
             let is_winning_capture = movepick_score > WINNING_CAPTURE_SCORE;
+            let is_passed_push = self.is_passed_pawn_push(m);
 
             let mut stat_score = 0;
 
             if is_quiet {
-                stat_score += t.get_history_score(self, m);
-                stat_score += t.get_continuation_history_score(self, m, 0);
-                stat_score += t.get_continuation_history_score(self, m, 1);
+                stat_score += t.get_history_score(self, m) * info.conf.main_history_weight / 1024;
+                stat_score += t.get_continuation_history_score(self, m, 0) * info.conf.cont1_history_weight / 1024;
+                stat_score += t.get_continuation_history_score(self, m, 1) * info.conf.cont2_history_weight / 1024;
                 // stat_score += t.get_continuation_history_score(self, m, 3);
             }
 
@@ -899,7 +1114,14 @@ impl Board {
             if !NT::ROOT && !NT::PV && !in_check && best_score > -MINIMUM_TB_WIN_SCORE {
                 // late move pruning
                 // if we have made too many moves, we start skipping moves.
-                if lmr_depth <= info.conf.lmp_depth && moves_made >= lmp_threshold {
+                if info.late_move_pruning && lmr_depth <= info.conf.lmp_depth && moves_made >= lmp_threshold {
+                    #[cfg(feature = "stats")]
+                    {
+                        info.stats.lmp_triggers += u64::from(!move_picker.skip_quiets);
+                    }
+                    if !move_picker.skip_quiets {
+                        tree_note!(info, t, "late move pruning");
+                    }
                     move_picker.skip_quiets = true;
                 }
 
@@ -907,17 +1129,30 @@ impl Board {
                 // if this move's history score is too low, we start skipping moves.
                 if is_quiet
                     && !killer_or_counter
+                    && !(is_passed_push && protect_passed_pushes)
                     && lmr_depth < info.conf.history_pruning_depth
                     && stat_score < info.conf.history_pruning_margin * (depth - 1)
                 {
+                    #[cfg(feature = "stats")]
+                    {
+                        info.stats.history_prunes += 1;
+                    }
+                    tree_prune!(info, t, m, "history pruning");
                     move_picker.skip_quiets = true;
                     continue;
                 }
 
                 // futility pruning
                 // if the static eval is too low, we start skipping moves.
-                let fp_margin = lmr_depth.round() * info.conf.futility_coeff_1 + info.conf.futility_coeff_0;
+                let fp_margin = lmr_depth.trunc() * info.conf.futility_coeff_1 + info.conf.futility_coeff_0;
                 if is_quiet && lmr_depth < info.conf.futility_depth && static_eval + fp_margin <= alpha {
+                    #[cfg(feature = "stats")]
+                    {
+                        info.stats.futility_triggers += u64::from(!move_picker.skip_quiets);
+                    }
+                    if !move_picker.skip_quiets {
+                        tree_note!(info, t, "futility pruning");
+                    }
                     move_picker.skip_quiets = true;
                 }
             }
@@ -931,9 +1166,16 @@ impl Board {
                 && move_picker.stage > Stage::YieldGoodCaptures
                 && !self.static_exchange_eval(m, see_table[usize::from(is_quiet)])
             {
+                #[cfg(feature = "stats")]
+                {
+                    info.stats.see_prunes += 1;
+                }
+                tree_prune!(info, t, m, "see pruning");
                 continue;
             }
 
+            let is_recapture = self.is_recapture(m);
+
             t.tt.prefetch(self.key_after(m));
             if !self.make_move(m, t) {
                 continue;
@@ -953,6 +1195,11 @@ impl Board {
                 && excluded.is_null()
                 && matches!(tt_hit, Some(TTHit { mov, depth: tt_depth, bound: Bound::Lower | Bound::Exact, .. }) if mov == m && tt_depth >= depth - 3);
 
+            if NT::ROOT && t.thread_id == 0 && info.root_move != m {
+                info.root_move = m;
+                info.root_move_started = info.time_manager.time_since_start();
+            }
+
             let extension;
             if NT::ROOT {
                 extension = ZERO_PLY;
@@ -964,18 +1211,22 @@ impl Board {
                 if move_picker.stage == Stage::Done {
                     // got a multi-cut bubbled up from the singularity search
                     // so we just bail out.
+                    tree_note!(info, t, "multi-cut");
                     return Self::singularity_margin(tt_value, depth);
                 }
-            } else if self.in_check() {
+            } else if t.extensions[height] < MAX_CUMULATIVE_EXTENSIONS {
                 // self.in_check() determines if the opponent is in check,
                 // because we have already made the move.
-                extension = Depth::from(is_quiet || is_winning_capture);
+                let gives_check = self.in_check() && (is_quiet || is_winning_capture);
+                let good_recapture = is_recapture && is_winning_capture;
+                extension = Depth::from(gives_check || good_recapture || is_passed_push);
             } else {
                 extension = ZERO_PLY;
             }
             if extension >= ONE_PLY * 2 {
                 t.double_extensions[height] += 1;
             }
+            t.extensions[height] += extension.trunc().max(0);
 
             let mut score;
             if moves_made == 1 {
@@ -986,19 +1237,20 @@ impl Board {
                 // calculation of LMR stuff
                 let r = if depth >= Depth::new(3)
                     && moves_made >= (info.conf.lmr_base_moves as usize + usize::from(NT::PV))
+                    && !(is_passed_push && protect_passed_pushes)
                 {
                     let mut r = info.lm_table.lm_reduction(depth, moves_made);
                     if is_quiet {
                         // extend/reduce using the stat_score of the move
-                        r -= i32::clamp(
-                            stat_score / info.conf.history_lmr_divisor,
-                            -info.conf.history_lmr_bound,
-                            info.conf.history_lmr_bound,
-                        );
+                        r -= Depth::from_raw(i32::clamp(
+                            stat_score * ONE_PLY.raw_inner() / info.conf.history_lmr_divisor,
+                            -info.conf.history_lmr_bound * ONE_PLY.raw_inner(),
+                            info.conf.history_lmr_bound * ONE_PLY.raw_inner(),
+                        ));
                         // reduce special moves one less
                         r -= i32::from(killer_or_counter);
-                        // reduce more on non-PV nodes
-                        r += i32::from(!NT::PV);
+                        // reduce more on nodes that are not, and have never been, on the PV
+                        r += i32::from(!tt_pv);
                         // reduce more if it's a cut-node
                         r += i32::from(cut_node);
                         // reduce more if not improving
@@ -1009,7 +1261,7 @@ impl Board {
                         // reduce winning captures less
                         r -= 1;
                     }
-                    Depth::new(r).clamp(ONE_PLY, depth - 1)
+                    r.clamp(ONE_PLY, depth - 1)
                 } else {
                     ONE_PLY
                 };
@@ -1017,12 +1269,17 @@ impl Board {
                 let mut new_depth = depth + extension;
                 let reduced_depth = new_depth - r;
                 score = -self.alpha_beta::<OffPV>(l_pv, info, t, reduced_depth, -alpha - 1, -alpha, true);
+                #[cfg(feature = "stats")]
+                {
+                    info.stats.lmr_searches += u64::from(r > ONE_PLY);
+                    info.stats.lmr_researches += u64::from(r > ONE_PLY && score > alpha);
+                }
                 // if we beat alpha, and reduced more than one ply,
                 // then we do a zero-window search at full depth.
                 if score > alpha && r > ONE_PLY {
                     let do_deeper_search =
                         score > (best_score + info.conf.do_deeper_base_margin + info.conf.do_deeper_depth_margin * r);
-                    let do_shallower_search = score < best_score + new_depth.round();
+                    let do_shallower_search = score < best_score + new_depth.trunc();
                     // depending on the value that the reduced search kicked out,
                     // we might want to do a deeper search, or a shallower search.
                     new_depth += Depth::from(do_deeper_search) - Depth::from(do_shallower_search);
@@ -1049,11 +1306,20 @@ impl Board {
             if extension >= ONE_PLY * 2 {
                 t.double_extensions[height] -= 1;
             }
+            t.extensions[height] -= extension.trunc().max(0);
 
             if info.stopped() {
                 return 0;
             }
 
+            // the main thread keeps the latest score of every root move, for the move choice to pick from.
+            if NT::ROOT && t.thread_id == 0 && excluded.is_null() {
+                match t.root_scores.iter_mut().find(|(root_move, _)| *root_move == m) {
+                    Some(entry) => entry.1 = score,
+                    None => t.root_scores.push((m, score)),
+                }
+            }
+
             if score > best_score {
                 best_score = score;
                 if score > alpha {
@@ -1063,7 +1329,7 @@ impl Board {
                 }
                 if alpha >= beta {
                     #[cfg(feature = "stats")]
-                    info.log_fail_high::<false>(moves_made - 1, movepick_score);
+                    info.stats.log_fail_high::<false>(moves_made - 1, movepick_score);
                     break;
                 }
             }
@@ -1074,8 +1340,10 @@ impl Board {
                 return alpha;
             }
             if in_check {
+                tree_note!(info, t, "checkmate");
                 return mated_in(height);
             }
+            tree_note!(info, t, "stalemate");
             return draw_score(t, info.nodes.get_local(), self.turn());
         }
 
@@ -1117,7 +1385,20 @@ impl Board {
                 alpha != original_alpha || best_move.is_null(),
                 "alpha was not raised, but best_move was not null!"
             );
-            t.tt.store(key, height, best_move, best_score, static_eval, flag, depth);
+            t.tt.store(key, height, best_move, best_score, raw_eval, flag, depth, tt_pv);
+        }
+
+        // update the correction history with the difference between the search result and the static eval,
+        // where the bound allows it to tell us anything.
+        // noisy best moves are skipped, as the static eval isn't expected to see tactics coming.
+        if !in_check
+            && excluded.is_null()
+            && (best_move.is_null() || !self.is_tactical(best_move))
+            && !(flag == Bound::Lower && best_score <= static_eval)
+            && !(flag == Bound::Upper && best_score >= static_eval)
+            && !is_game_theoretic_score(best_score)
+        {
+            t.update_correction_history(self, depth, best_score - static_eval);
         }
 
         t.best_moves[height] = best_move;
@@ -1143,9 +1424,31 @@ impl Board {
         t.update_tactical_history(self, moves_to_adjust, best_move, depth);
     }
 
+    /// Reports on a long search in between the iteration reports: the node count and hash usage
+    /// every so often, and the line being searched once the main thread has been on the same root
+    /// move for a while.
+    fn report_while_searching(&self, info: &mut SearchInfo, t: &ThreadData) {
+        if info.deterministic || t.thread_id != 0 || !info.sink.listening() {
+            return;
+        }
+        let now = info.time_manager.time_since_start();
+        if now >= info.progress_reported + PROGRESS_INTERVAL {
+            info.progress_reported = now;
+            info.sink.progress(info, info.nodes.get_global(), t.tt.hashfull());
+        }
+        if info.show_current_line
+            && now >= info.root_move_started + CURRLINE_DELAY
+            && now >= info.current_line_reported + CURRLINE_INTERVAL
+        {
+            info.current_line_reported = now;
+            let line = self.line_from_root().take_while(|m| !m.is_null()).take(CURRLINE_PLIES).collect::<Vec<_>>();
+            info.sink.current_line(&line);
+        }
+    }
+
     /// The reduced beta margin for Singular Extension.
     fn singularity_margin(tt_value: i32, depth: Depth) -> i32 {
-        (tt_value - (depth * 3 / 4).round()).max(-MATE_SCORE)
+        (tt_value - (depth * 3 / 4).trunc()).max(-MATE_SCORE)
     }
 
     /// Produce extensions when a move is singular - that is, if it is a move that is
@@ -1204,11 +1507,10 @@ impl Board {
         let r_beta = (value - margin).max(-MATE_SCORE);
         let r_depth = (depth - 1) / 2;
         t.excluded[self.height()] = m;
-        let pts_prev = info.print_to_stdout;
-        info.print_to_stdout = false;
+        let sink = std::mem::replace(&mut info.sink, &Silent);
         let value =
             self.alpha_beta::<CheckForced>(&mut PVariation::default(), info, t, r_depth, r_beta - 1, r_beta, false);
-        info.print_to_stdout = pts_prev;
+        info.sink = sink;
         t.excluded[self.height()] = Move::NULL;
         value < r_beta
     }
@@ -1311,22 +1613,45 @@ pub fn select_best<'a>(
 ) -> &'a ThreadData<'a> {
     let (mut best_thread, rest) = thread_headers.split_first().unwrap();
 
+    // each thread votes for its best move, with a weight that grows with
+    // the depth it completed and with how good its score is compared to
+    // the worst score any thread found.
+    let score_of = |t: &ThreadData| t.pvs[t.completed].score();
+    let move_of = |t: &ThreadData| t.pvs[t.completed].moves().first().copied();
+    let min_score = thread_headers.iter().map(score_of).min().unwrap_or(0);
+    let mut votes = Vec::<(Move, i64)>::with_capacity(thread_headers.len());
+    for thread in thread_headers {
+        let Some(m) = move_of(thread) else { continue };
+        let weight = i64::from(score_of(thread) - min_score + 14) * i64::try_from(thread.completed).unwrap();
+        match votes.iter_mut().find(|(vm, _)| *vm == m) {
+            Some((_, total)) => *total += weight,
+            None => votes.push((m, weight)),
+        }
+    }
+    let votes_for =
+        |t: &ThreadData| move_of(t).and_then(|m| votes.iter().find(|(vm, _)| *vm == m)).map_or(0, |&(_, v)| v);
+
     for thread in rest {
-        let best_depth = best_thread.completed;
-        let best_score = best_thread.pvs[best_depth].score();
-        let this_depth = thread.completed;
-        let this_score = thread.pvs[this_depth].score();
-        if (this_depth == best_depth || this_score > MINIMUM_MATE_SCORE) && this_score > best_score {
-            best_thread = thread;
+        if move_of(thread).is_none() {
+            continue;
         }
-        if this_depth > best_depth && (this_score > best_score || best_score < MINIMUM_MATE_SCORE) {
+        let best_score = score_of(best_thread);
+        let this_score = score_of(thread);
+        if is_game_theoretic_score(best_score) {
+            // a proven result is only replaced by a better one, such as a shorter mate.
+            if this_score > best_score {
+                best_thread = thread;
+            }
+        } else if this_score >= MINIMUM_TB_WIN_SCORE
+            || (this_score > -MINIMUM_TB_WIN_SCORE && votes_for(thread) > votes_for(best_thread))
+        {
             best_thread = thread;
         }
     }
 
     // if we aren't using the main thread (thread 0) then we need to do
     // an extra uci info line to show the best move/score/pv
-    if best_thread.thread_id != 0 && info.print_to_stdout {
+    if best_thread.thread_id != 0 && info.sink.listening() {
         let pv = &best_thread.pvs[best_thread.completed];
         let depth = best_thread.completed;
         readout_info(board, Bound::Exact, pv, depth, info, tt, total_nodes, false);
@@ -1335,69 +1660,71 @@ pub fn select_best<'a>(
     best_thread
 }
 
-/// Print the info about an iteration of the search.
+/// Send the info about an iteration of the search to wherever the search reports to.
 fn readout_info(
     board: &mut Board,
-    mut bound: Bound,
+    bound: Bound,
     pv: &PVariation,
     depth: usize,
     info: &SearchInfo,
     tt: TTView,
     nodes: u64,
-    force_print: bool,
+    force: bool,
 ) {
-    #![allow(clippy::cast_precision_loss, clippy::cast_sign_loss, clippy::cast_possible_truncation)]
-    // don't print anything if we are in the first 50ms of the search and we are in a game,
-    // this helps in ultra-fast time controls where we only have a few ms to think.
-    if info.time_manager.is_dynamic() && info.skip_print() && !force_print {
-        return;
+    let extended;
+    let pv = if pv.moves().len() < depth {
+        extended = extend_pv_from_tt(board, pv, depth, tt);
+        &extended
+    } else {
+        pv
+    };
+    info.sink.report(board, info, &Report { bound, pv, depth, nodes, tt, force });
+}
+
+/// Lengthens a principal variation that was cut short (by TT cutoffs in the PV,
+/// or by hash replacement) with the best moves stored in the transposition table,
+/// until it reaches `depth` moves, or the table runs out or leads round in a cycle.
+fn extend_pv_from_tt(board: &Board, pv: &PVariation, depth: usize, tt: TTView) -> PVariation {
+    let mut extended = pv.clone();
+    let mut scratch = board.clone();
+    let mut seen = vec![scratch.hashkey()];
+    for &m in pv.moves() {
+        scratch.make_move_simple(m);
+        seen.push(scratch.hashkey());
     }
-    let sstr = uci::format_score(pv.score);
-    let normal_uci_output = !uci::PRETTY_PRINT.load(Ordering::SeqCst);
-    let nps = (nodes as f64 / info.time_manager.elapsed().as_secs_f64()) as u64;
-    if board.turn() == Colour::BLACK {
-        bound = match bound {
-            Bound::Upper => Bound::Lower,
-            Bound::Lower => Bound::Upper,
-            _ => Bound::Exact,
+    while extended.moves.len() < depth.min(MAX_PLY) {
+        let Some(hit) = tt.probe(scratch.hashkey(), scratch.height()) else {
+            break;
         };
+        if hit.mov.is_null() || !scratch.is_pseudo_legal(hit.mov) || !scratch.make_move_simple(hit.mov) {
+            break;
+        }
+        let key = scratch.hashkey();
+        if seen.contains(&key) {
+            break;
+        }
+        seen.push(key);
+        extended.moves.push(hit.mov);
     }
-    let bound_string = match bound {
-        Bound::Upper => " upperbound",
-        Bound::Lower => " lowerbound",
-        _ => "",
-    };
-    if normal_uci_output {
-        println!(
-            "info score {sstr}{bound_string} wdl {wdl} depth {depth} seldepth {} nodes {nodes} time {} nps {nps} hashfull {hashfull} tbhits {tbhits} {pv}",
-            info.seldepth.ply_to_horizon(),
-            info.time_manager.elapsed().as_millis(),
-            hashfull = tt.hashfull(),
-            tbhits = TB_HITS.load(Ordering::SeqCst),
-            wdl = uci::format_wdl(pv.score, board.ply()),
-        );
+    extended
+}
+
+/// The random number generator used when picking a root move other than the best.
+/// It's keyed on the position, so that with a fixed seed the same position always gets
+/// the same choice, however the game got there.
+/// The best few root moves by the scores the main thread last gave them, best first.
+fn best_root_moves(t: &ThreadData) -> Vec<(Move, i32)> {
+    let mut candidates = t.root_scores.clone();
+    candidates.sort_by_key(|&(_, score)| std::cmp::Reverse(score));
+    candidates.truncate(RANDOMISED_ROOT_CANDIDATES);
+    candidates
+}
+
+fn root_rng(board: &Board, info: &SearchInfo) -> Xoshiro {
+    if info.deterministic && uci::SEED.load(Ordering::SeqCst) == 0 {
+        Xoshiro::seed_from_u64(board.hashkey())
     } else {
-        let value = uci::pretty_format_score(pv.score, board.turn());
-        let mut pv_string = board.pv_san(pv).unwrap();
-        // truncate the pv string if it's too long
-        if pv_string.len() > 130 {
-            let final_space = pv_string.match_indices(' ').filter(|(i, _)| *i < 130).last().map_or(0, |(i, _)| i);
-            pv_string.truncate(final_space);
-            pv_string.push_str("...         ");
-        }
-        let endchr = if bound == Bound::Exact {
-            "\n"
-        } else {
-            "                                                                   \r"
-        };
-        eprint!(
-            " {depth:2}/{:<2} \u{001b}[38;5;243m{t} {knodes:8}kn\u{001b}[0m {value} ({wdl}) \u{001b}[38;5;243m{knps:5}kn/s\u{001b}[0m {pv_string}{endchr}",
-            info.seldepth.ply_to_horizon(),
-            t = uci::format_time(info.time_manager.elapsed().as_millis()),
-            knps = nps / 1_000,
-            knodes = nodes / 1_000,
-            wdl = uci::pretty_format_wdl(pv.score, board.ply()),
-        );
+        Xoshiro::from_user_seed(board.hashkey())
     }
 }
 
@@ -1407,22 +1734,21 @@ pub fn draw_score(t: &ThreadData, nodes: u64, stm: Colour) -> i32 {
     // higher contempt means we will play on in drawn positions more often,
     // so if we are to play in a drawn position, then we should return the
     // negative of the contempt score.
-    let contempt = uci::CONTEMPT.load(Ordering::Relaxed);
-    let contempt_component = if stm == t.stm_at_root { -contempt } else { contempt };
+    let contempt_component = if stm == t.stm_at_root { -t.contempt } else { t.contempt };
 
     random_component + contempt_component
 }
 
 #[derive(Clone, Debug)]
 pub struct LMTable {
-    /// The reduction table. rtable\[depth]\[played] is the base LMR reduction for a move
-    lm_reduction_table: [[i32; 64]; 64],
+    /// The reduction table. rtable\[depth]\[played] is the base LMR reduction for a move, which may be fractional.
+    lm_reduction_table: [[Depth; 64]; 64],
     /// The movecount table. ptable\[played]\[improving] is the movecount at which LMP is triggered.
     lmp_movecount_table: [[usize; 12]; 2],
 }
 
 impl LMTable {
-    pub const NULL: Self = Self { lm_reduction_table: [[0; 64]; 64], lmp_movecount_table: [[0; 12]; 2] };
+    pub const NULL: Self = Self { lm_reduction_table: [[ZERO_PLY; 64]; 64], lmp_movecount_table: [[0; 12]; 2] };
 
     pub fn new(config: &Config) -> Self {
         #![allow(clippy::cast_possible_truncation, clippy::cast_precision_loss, clippy::cast_sign_loss)]
@@ -1432,7 +1758,7 @@ impl LMTable {
             cfor!(let mut played = 1; played < 64; played += 1; {
                 let ld = f64::ln(depth as f64);
                 let lp = f64::ln(played as f64);
-                out.lm_reduction_table[depth][played] = (base + ld * lp / division) as i32;
+                out.lm_reduction_table[depth][played] = Depth::from(base + ld * lp / division);
             });
         });
         cfor!(let mut depth = 1; depth < 12; depth += 1; {
@@ -1442,7 +1768,7 @@ impl LMTable {
         out
     }
 
-    pub fn lm_reduction(&self, depth: Depth, moves_made: usize) -> i32 {
+    pub fn lm_reduction(&self, depth: Depth, moves_made: usize) -> Depth {
         let depth = depth.ply_to_horizon().min(63);
         let played = moves_made.min(63);
         self.lm_reduction_table[depth][played]
@@ -1468,8 +1794,8 @@ pub struct AspirationWindow {
     pub beta_fails: i32,
 }
 
-pub fn asp_window(depth: Depth) -> i32 {
-    (ASPIRATION_WINDOW + (50 / depth.round() - 3)).max(10)
+pub fn asp_window(depth: Depth, conf: &Config) -> i32 {
+    (conf.aspiration_window + (50 / depth.trunc() - 3)).max(10)
 }
 
 impl AspirationWindow {
@@ -1477,7 +1803,7 @@ impl AspirationWindow {
         Self { alpha: -INFINITY, beta: INFINITY, midpoint: 0, alpha_fails: 0, beta_fails: 0 }
     }
 
-    pub fn around_value(value: i32, depth: Depth) -> Self {
+    pub fn around_value(value: i32, depth: Depth, conf: &Config) -> Self {
         if is_game_theoretic_score(value) {
             // for mates / tbwins we expect a lot of fluctuation, so aspiration
             // windows are not useful.
@@ -1485,17 +1811,17 @@ impl AspirationWindow {
         } else {
             Self {
                 midpoint: value,
-                alpha: value - asp_window(depth),
-                beta: value + asp_window(depth),
+                alpha: value - asp_window(depth, conf),
+                beta: value + asp_window(depth, conf),
                 alpha_fails: 0,
                 beta_fails: 0,
             }
         }
     }
 
-    pub fn widen_down(&mut self, value: i32, depth: Depth) {
+    pub fn widen_down(&mut self, value: i32, depth: Depth, conf: &Config) {
         self.midpoint = value;
-        let margin = asp_window(depth) << (self.alpha_fails + 1);
+        let margin = asp_window(depth, conf) << (self.alpha_fails + 1);
         if margin > 1369 {
             self.alpha = -INFINITY;
             return;
@@ -1505,9 +1831,9 @@ impl AspirationWindow {
         self.alpha_fails += 1;
     }
 
-    pub fn widen_up(&mut self, value: i32, depth: Depth) {
+    pub fn widen_up(&mut self, value: i32, depth: Depth, conf: &Config) {
         self.midpoint = value;
-        let margin = asp_window(depth) << (self.beta_fails + 1);
+        let margin = asp_window(depth, conf) << (self.beta_fails + 1);
         if margin > 1369 {
             self.beta = INFINITY;
             return;
@@ -1520,8 +1846,10 @@ impl AspirationWindow {
 fn set_up_for_search(board: &mut Board, info: &mut SearchInfo, thread_headers: &mut [ThreadData]) {
     board.zero_height();
     info.set_up_for_search();
+    let contempt = if info.analysing { 0 } else { uci::CONTEMPT.load(Ordering::SeqCst) };
     for td in thread_headers {
         td.set_up_for_search(board);
+        td.contempt = contempt;
     }
     TB_HITS.store(0, Ordering::Relaxed);
 }
@@ -3,24 +3,27 @@ use std::fmt::Display;
 use crate::{
     timemgmt::{
         DEFAULT_MOVES_TO_GO, FAIL_LOW_TM_BONUS, INCREMENT_FRAC, NODE_TM_SUBTREE_MULTIPLIER, OPTIMAL_WINDOW_FRAC,
-        STRONG_FORCED_TM_FRAC, WEAK_FORCED_TM_FRAC,
+        RECAPTURE_TM_FRAC, SCORE_DROP_TM_BONUS, STRONG_FORCED_TM_FRAC, WEAK_FORCED_TM_FRAC,
     },
     util::depth::Depth,
 };
 
 use super::{
-    ASPIRATION_WINDOW, DOUBLE_EXTENSION_MARGIN, DO_DEEPER_BASE_MARGIN, DO_DEEPER_DEPTH_MARGIN, FUTILITY_COEFF_0,
-    FUTILITY_COEFF_1, FUTILITY_DEPTH, HISTORY_LMR_BOUND, HISTORY_LMR_DIVISOR, HISTORY_PRUNING_DEPTH,
-    HISTORY_PRUNING_MARGIN, LMP_BASE_MOVES, LMP_DEPTH, LMR_BASE, LMR_BASE_MOVES, LMR_DIVISION, MAIN_SEE_BOUND,
+    ASPIRATION_WINDOW, ASPIRATION_WINDOW_MIN_DEPTH, CONT1_HISTORY_WEIGHT, CONT2_HISTORY_WEIGHT,
+    DOUBLE_EXTENSION_MARGIN, DO_DEEPER_BASE_MARGIN, DO_DEEPER_DEPTH_MARGIN, FUTILITY_COEFF_0, FUTILITY_COEFF_1,
+    FUTILITY_DEPTH, HISTORY_LMR_BOUND, HISTORY_LMR_DIVISOR, HISTORY_PRUNING_DEPTH, HISTORY_PRUNING_MARGIN,
+    LMP_BASE_MOVES, LMP_DEPTH, LMR_BASE, LMR_BASE_MOVES, LMR_DIVISION, MAIN_HISTORY_WEIGHT, MAIN_SEE_BOUND,
     MAX_NMP_EVAL_REDUCTION, NMP_BASE_REDUCTION, NMP_IMPROVING_MARGIN, NMP_REDUCTION_DEPTH_DIVISOR,
-    NMP_REDUCTION_EVAL_DIVISOR, NMP_VERIFICATION_DEPTH, PROBCUT_IMPROVING_MARGIN, PROBCUT_MARGIN, PROBCUT_MIN_DEPTH,
-    PROBCUT_REDUCTION, QS_SEE_BOUND, RAZORING_COEFF_0, RAZORING_COEFF_1, RFP_DEPTH, RFP_IMPROVING_MARGIN, RFP_MARGIN,
-    SEE_DEPTH, SEE_QUIET_MARGIN, SEE_TACTICAL_MARGIN, SINGULARITY_DEPTH, TT_REDUCTION_DEPTH,
+    NMP_REDUCTION_EVAL_DIVISOR, NMP_VERIFICATION_DEPTH, PASSED_PUSH_DEPTH, PROBCUT_IMPROVING_MARGIN, PROBCUT_MARGIN,
+    PROBCUT_MIN_DEPTH, PROBCUT_REDUCTION, QS_DELTA_MARGIN, QS_FUTILITY_MARGIN, QS_SEE_BOUND, RAZORING_COEFF_0,
+    RAZORING_COEFF_1, RFP_DEPTH, RFP_IMPROVING_MARGIN, RFP_MARGIN, SEE_DEPTH, SEE_QUIET_MARGIN, SEE_TACTICAL_MARGIN,
+    SINGULARITY_DEPTH, TT_REDUCTION_DEPTH,
 };
 
 #[derive(Clone, Debug)]
 pub struct Config {
     pub aspiration_window: i32,
+    pub aspiration_window_min_depth: Depth,
     pub rfp_margin: i32,
     pub rfp_improving_margin: i32,
     pub nmp_improving_margin: i32,
@@ -56,6 +59,8 @@ pub struct Config {
     pub increment_frac: u32,
     pub node_tm_subtree_multiplier: u32,
     pub fail_low_tm_bonus: u32,
+    pub score_drop_tm_bonus: u32,
+    pub recapture_tm_frac: u32,
     pub lmr_base_moves: u32,
     pub history_lmr_divisor: i32,
     pub history_lmr_bound: i32,
@@ -65,12 +70,19 @@ pub struct Config {
     pub do_deeper_depth_margin: i32,
     pub history_pruning_depth: Depth,
     pub history_pruning_margin: i32,
+    pub main_history_weight: i32,
+    pub cont1_history_weight: i32,
+    pub cont2_history_weight: i32,
+    pub qs_futility_margin: i32,
+    pub qs_delta_margin: i32,
+    pub passed_push_depth: Depth,
 }
 
 impl Config {
     pub const fn default() -> Self {
         Self {
             aspiration_window: ASPIRATION_WINDOW,
+            aspiration_window_min_depth: ASPIRATION_WINDOW_MIN_DEPTH,
             rfp_margin: RFP_MARGIN,
             rfp_improving_margin: RFP_IMPROVING_MARGIN,
             nmp_improving_margin: NMP_IMPROVING_MARGIN,
@@ -106,6 +118,8 @@ impl Config {
             increment_frac: INCREMENT_FRAC,
             node_tm_subtree_multiplier: NODE_TM_SUBTREE_MULTIPLIER,
             fail_low_tm_bonus: FAIL_LOW_TM_BONUS,
+            score_drop_tm_bonus: SCORE_DROP_TM_BONUS,
+            recapture_tm_frac: RECAPTURE_TM_FRAC,
             lmr_base_moves: LMR_BASE_MOVES,
             history_lmr_divisor: HISTORY_LMR_DIVISOR,
             history_lmr_bound: HISTORY_LMR_BOUND,
@@ -115,6 +129,12 @@ impl Config {
             do_deeper_depth_margin: DO_DEEPER_DEPTH_MARGIN,
             history_pruning_depth: HISTORY_PRUNING_DEPTH,
             history_pruning_margin: HISTORY_PRUNING_MARGIN,
+            main_history_weight: MAIN_HISTORY_WEIGHT,
+            cont1_history_weight: CONT1_HISTORY_WEIGHT,
+            cont2_history_weight: CONT2_HISTORY_WEIGHT,
+            qs_futility_margin: QS_FUTILITY_MARGIN,
+            qs_delta_margin: QS_DELTA_MARGIN,
+            passed_push_depth: PASSED_PUSH_DEPTH,
         }
     }
 }
@@ -160,6 +180,7 @@ impl Config {
     pub fn ids_with_parsers(&mut self) -> Vec<(&str, LazyFieldParser)> {
         id_parser_gen![
             ASPIRATION_WINDOW = [self.aspiration_window],
+            ASPIRATION_WINDOW_MIN_DEPTH = [self.aspiration_window_min_depth],
             RFP_MARGIN = [self.rfp_margin],
             RFP_IMPROVING_MARGIN = [self.rfp_improving_margin],
             NMP_IMPROVING_MARGIN = [self.nmp_improving_margin],
@@ -195,6 +216,8 @@ impl Config {
             INCREMENT_FRAC = [self.increment_frac],
             NODE_TM_SUBTREE_MULTIPLIER = [self.node_tm_subtree_multiplier],
             FAIL_LOW_TM_BONUS = [self.fail_low_tm_bonus],
+            SCORE_DROP_TM_BONUS = [self.score_drop_tm_bonus],
+            RECAPTURE_TM_FRAC = [self.recapture_tm_frac],
             LMR_BASE_MOVES = [self.lmr_base_moves],
             HISTORY_LMR_DIVISOR = [self.history_lmr_divisor],
             HISTORY_LMR_BOUND = [self.history_lmr_bound],
@@ -203,7 +226,13 @@ impl Config {
             DO_DEEPER_BASE_MARGIN = [self.do_deeper_base_margin],
             DO_DEEPER_DEPTH_MARGIN = [self.do_deeper_depth_margin],
             HISTORY_PRUNING_DEPTH = [self.history_pruning_depth],
-            HISTORY_PRUNING_MARGIN = [self.history_pruning_margin]
+            HISTORY_PRUNING_MARGIN = [self.history_pruning_margin],
+            MAIN_HISTORY_WEIGHT = [self.main_history_weight],
+            CONT1_HISTORY_WEIGHT = [self.cont1_history_weight],
+            CONT2_HISTORY_WEIGHT = [self.cont2_history_weight],
+            QS_FUTILITY_MARGIN = [self.qs_futility_margin],
+            QS_DELTA_MARGIN = [self.qs_delta_margin],
+            PASSED_PUSH_DEPTH = [self.passed_push_depth]
         ]
     }
 
@@ -219,6 +248,7 @@ impl Config {
         #![allow(clippy::cast_precision_loss)]
         id_value_gen![
             ASPIRATION_WINDOW = [self.aspiration_window, 1, 50, 3],
+            ASPIRATION_WINDOW_MIN_DEPTH = [self.aspiration_window_min_depth, 1, 10, 1],
             RFP_MARGIN = [self.rfp_margin, 40, 200, 10],
             RFP_IMPROVING_MARGIN = [self.rfp_improving_margin, 30, 150, 10],
             NMP_IMPROVING_MARGIN = [self.nmp_improving_margin, 30, 200, 10],
@@ -254,6 +284,8 @@ impl Config {
             INCREMENT_FRAC = [self.increment_frac, 1, 100, 10],
             NODE_TM_SUBTREE_MULTIPLIER = [self.node_tm_subtree_multiplier, 1, 1000, 15],
             FAIL_LOW_TM_BONUS = [self.fail_low_tm_bonus, 1, 1000, 30],
+            SCORE_DROP_TM_BONUS = [self.score_drop_tm_bonus, 1, 1000, 40],
+            RECAPTURE_TM_FRAC = [self.recapture_tm_frac, 1, 1000, 40],
             LMR_BASE_MOVES = [self.lmr_base_moves, 1, 5, 1],
             HISTORY_LMR_DIVISOR = [self.history_lmr_divisor, 1, 16383, 100],
             HISTORY_LMR_BOUND = [self.history_lmr_bound, 1, 8, 1],
@@ -262,7 +294,13 @@ impl Config {
             DO_DEEPER_BASE_MARGIN = [self.do_deeper_base_margin, 1, 200, 20],
             DO_DEEPER_DEPTH_MARGIN = [self.do_deeper_depth_margin, 1, 50, 2],
             HISTORY_PRUNING_DEPTH = [self.history_pruning_depth, 2, 14, 1],
-            HISTORY_PRUNING_MARGIN = [self.history_pruning_margin, -5000, 1000, 500]
+            HISTORY_PRUNING_MARGIN = [self.history_pruning_margin, -5000, 1000, 500],
+            MAIN_HISTORY_WEIGHT = [self.main_history_weight, 256, 2048, 64],
+            CONT1_HISTORY_WEIGHT = [self.cont1_history_weight, 256, 2048, 64],
+            CONT2_HISTORY_WEIGHT = [self.cont2_history_weight, 256, 2048, 64],
+            QS_FUTILITY_MARGIN = [self.qs_futility_margin, 50, 400, 20],
+            QS_DELTA_MARGIN = [self.qs_delta_margin, 100, 600, 25],
+            PASSED_PUSH_DEPTH = [self.passed_push_depth, 2, 12, 1]
         ]
     }
 
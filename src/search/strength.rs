@@ -0,0 +1,64 @@
+// Strength limiting, for UCI_LimitStrength and UCI_Elo.
+// Weaker play comes from two places: the search is given fewer nodes, and instead of
+// always playing the best move, a move is sampled from a softmax over the scores of the
+// best few root moves, with a temperature that rises as the requested Elo falls.
+// The calibration is rough - it is meant to make the engine a reasonable sparring partner,
+// not to match any particular rating list.
+
+use rand::Rng;
+
+use crate::chessmove::Move;
+
+pub const MIN_ELO: i32 = 500;
+pub const MAX_ELO: i32 = 3000;
+pub const DEFAULT_ELO: i32 = 1500;
+
+/// The node limit for a single search at a given strength.
+pub fn node_limit(elo: i32) -> u64 {
+    #![allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let elo = elo.clamp(MIN_ELO, MAX_ELO);
+    (25.0 * f64::powf(2.0, f64::from(elo - MIN_ELO) / 150.0)) as u64
+}
+
+/// The softmax temperature at a given strength, in internal score units.
+fn temperature(elo: i32) -> f64 {
+    let elo = elo.clamp(MIN_ELO, MAX_ELO);
+    f64::from(MAX_ELO - elo) / 8.0 + 1.0
+}
+
+/// Samples a move from the candidates, weighted by how good their scores are.
+pub fn pick(candidates: &[(Move, i32)], elo: i32, rng: &mut impl Rng) -> Option<Move> {
+    let best = candidates.iter().map(|&(_, score)| score).max()?;
+    let temperature = temperature(elo);
+    let weights =
+        candidates.iter().map(|&(_, score)| f64::exp(f64::from(score - best) / temperature)).collect::<Vec<_>>();
+    let mut x = rng.gen::<f64>() * weights.iter().sum::<f64>();
+    for (&(m, _), weight) in candidates.iter().zip(&weights) {
+        if x < *weight {
+            return Some(m);
+        }
+        x -= weight;
+    }
+    candidates.last().map(|&(m, _)| m)
+}
+
+mod tests {
+    #[test]
+    fn weaker_settings_blunder_more() {
+        use super::{pick, MAX_ELO, MIN_ELO};
+        use crate::{chessmove::Move, util::Square};
+        use rand::{rngs::StdRng, SeedableRng};
+
+        let best = Move::new(Square::E2, Square::E4);
+        let blunder = Move::new(Square::F2, Square::F3);
+        let candidates = [(best, 50), (blunder, -150)];
+        let mut rng = StdRng::seed_from_u64(0);
+        let blunders_at =
+            |elo, rng: &mut StdRng| (0..1000).filter(|_| pick(&candidates, elo, rng) == Some(blunder)).count();
+        let strong = blunders_at(MAX_ELO, &mut rng);
+        let weak = blunders_at(MIN_ELO, &mut rng);
+        assert_eq!(strong, 0);
+        assert!(weak > 100 && weak < 500, "blundered {weak} times");
+        assert!(super::node_limit(MIN_ELO) < super::node_limit(MAX_ELO));
+    }
+}
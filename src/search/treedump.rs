@@ -0,0 +1,206 @@
+// Recording of the search tree, for working out why the search did (or didn't) find a move.
+// Only the main thread's tree is recorded, and only down to a maximum height and up to a
+// maximum number of nodes, as the full tree of even a shallow search is far too large to
+// make sense of. The tree is written out as JSON, one object per node:
+// {"move": "e2e4", "alpha": -20, "beta": 20, "depth": 5, "qsearch": false, "score": 13, "notes": [...], "children": [...]}
+// where "move" is null for root nodes, and "score" is null for moves that were pruned without being searched.
+// Limits are set with "treedump limit <height> <nodes>", and the tree from the last search is written
+// out with "treedump <path>".
+
+use std::io::{self, Write};
+
+use crate::{chessmove::Move, util::depth::Depth};
+
+pub const DEFAULT_MAX_HEIGHT: usize = 4;
+pub const DEFAULT_MAX_NODES: usize = 100_000;
+
+#[derive(Clone, Debug)]
+struct Node {
+    mov: Move,
+    alpha: i32,
+    beta: i32,
+    depth: Depth,
+    qsearch: bool,
+    score: Option<i32>,
+    notes: Vec<&'static str>,
+    children: Vec<usize>,
+}
+
+#[derive(Clone, Debug)]
+pub struct TreeDump {
+    nodes: Vec<Node>,
+    /// Every call to the search at the root, in order.
+    roots: Vec<usize>,
+    /// The recorded nodes on the path to the current node,
+    /// with `None` for nodes that are past the height or node caps.
+    path: Vec<Option<usize>>,
+    pub max_height: usize,
+    pub max_nodes: usize,
+}
+
+impl TreeDump {
+    pub const fn new() -> Self {
+        Self {
+            nodes: Vec::new(),
+            roots: Vec::new(),
+            path: Vec::new(),
+            max_height: DEFAULT_MAX_HEIGHT,
+            max_nodes: DEFAULT_MAX_NODES,
+        }
+    }
+
+    /// Discards the recorded tree, keeping the caps.
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+        self.roots.clear();
+        self.path.clear();
+    }
+
+    /// Records entry into a node, reached by `mov`. The move is ignored for root nodes.
+    pub fn enter(&mut self, mov: Move, alpha: i32, beta: i32, depth: Depth, qsearch: bool) {
+        let parent = self.path.last().copied();
+        let mov = if parent.is_none() { Move::NULL } else { mov };
+        let recorded = matches!(parent, None | Some(Some(_)))
+            && self.path.len() <= self.max_height
+            && self.nodes.len() < self.max_nodes;
+        if !recorded {
+            self.path.push(None);
+            return;
+        }
+        let index = self.nodes.len();
+        self.nodes.push(Node {
+            mov,
+            alpha,
+            beta,
+            depth,
+            qsearch,
+            score: None,
+            notes: Vec::new(),
+            children: Vec::new(),
+        });
+        match parent.flatten() {
+            Some(parent) => self.nodes[parent].children.push(index),
+            None => self.roots.push(index),
+        }
+        self.path.push(Some(index));
+    }
+
+    pub fn exit(&mut self, score: i32) {
+        if let Some(Some(index)) = self.path.pop() {
+            self.nodes[index].score = Some(score);
+        }
+    }
+
+    /// Attaches a note to the current node, usually the reason that it returned early.
+    pub fn note(&mut self, note: &'static str) {
+        if let Some(&Some(index)) = self.path.last() {
+            self.nodes[index].notes.push(note);
+        }
+    }
+
+    /// Records a move of the current node that was pruned without being searched.
+    pub fn prune(&mut self, mov: Move, reason: &'static str) {
+        let Some(&Some(parent)) = self.path.last() else {
+            return;
+        };
+        if self.nodes.len() >= self.max_nodes {
+            return;
+        }
+        let index = self.nodes.len();
+        let Node { alpha, beta, depth, qsearch, .. } = self.nodes[parent];
+        self.nodes.push(Node {
+            mov,
+            alpha: -beta,
+            beta: -alpha,
+            depth: depth - 1,
+            qsearch,
+            score: None,
+            notes: vec![reason],
+            children: Vec::new(),
+        });
+        self.nodes[parent].children.push(index);
+    }
+
+    pub fn write_json(&self, mut out: impl Write) -> io::Result<()> {
+        write!(out, "[")?;
+        for (i, &root) in self.roots.iter().enumerate() {
+            if i != 0 {
+                write!(out, ",")?;
+            }
+            self.write_node(&mut out, root)?;
+        }
+        writeln!(out, "]")
+    }
+
+    fn write_node(&self, out: &mut impl Write, index: usize) -> io::Result<()> {
+        let node = &self.nodes[index];
+        write!(out, "{{\"move\":")?;
+        if node.mov.is_null() {
+            write!(out, "null")?;
+        } else {
+            write!(out, "\"{}\"", node.mov)?;
+        }
+        write!(
+            out,
+            ",\"alpha\":{},\"beta\":{},\"depth\":{},\"qsearch\":{},\"score\":",
+            node.alpha,
+            node.beta,
+            f64::from(node.depth),
+            node.qsearch
+        )?;
+        match node.score {
+            Some(score) => write!(out, "{score}")?,
+            None => write!(out, "null")?,
+        }
+        write!(out, ",\"notes\":[")?;
+        for (i, note) in node.notes.iter().enumerate() {
+            if i != 0 {
+                write!(out, ",")?;
+            }
+            write!(out, "\"{note}\"")?;
+        }
+        write!(out, "],\"children\":[")?;
+        for (i, &child) in node.children.iter().enumerate() {
+            if i != 0 {
+                write!(out, ",")?;
+            }
+            self.write_node(out, child)?;
+        }
+        write!(out, "]}}")
+    }
+}
+
+mod tests {
+    #[test]
+    fn caps_limit_the_recorded_tree() {
+        use super::TreeDump;
+        use crate::{
+            chessmove::Move,
+            util::{depth::Depth, Square},
+        };
+
+        let mut tree = TreeDump::new();
+        tree.max_height = 1;
+        let e4 = Move::new(Square::E2, Square::E4);
+        let e5 = Move::new(Square::E7, Square::E5);
+        tree.enter(e5, -10, 10, Depth::new(2), false);
+        tree.enter(e4, -10, 10, Depth::new(1), false);
+        // past the height cap, so this node is not recorded.
+        tree.enter(e5, -10, 10, Depth::new(0), true);
+        tree.note("unrecorded");
+        tree.exit(3);
+        tree.note("rfp");
+        tree.exit(-5);
+        tree.prune(e5, "see");
+        tree.exit(5);
+
+        let mut out = Vec::new();
+        tree.write_json(&mut out).unwrap();
+        let json = String::from_utf8(out).unwrap();
+        assert!(json.starts_with("[{\"move\":null,"), "{json}");
+        assert!(json.contains("\"move\":\"e2e4\""), "{json}");
+        assert!(json.contains("\"notes\":[\"rfp\"]"), "{json}");
+        assert!(json.contains("\"score\":null,\"notes\":[\"see\"]"), "{json}");
+        assert!(!json.contains("unrecorded"), "{json}");
+    }
+}
@@ -1,20 +1,249 @@
-use std::sync::{
-    atomic::{AtomicBool, AtomicU64, Ordering},
-    mpsc, Mutex,
+use std::{
+    fmt::{self, Debug},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc, Mutex,
+    },
+    time::Duration,
 };
 
 use crate::{
-    search::{parameters::Config, LMTable},
+    board::Board,
+    chessmove::Move,
+    engine::SearchUpdate,
+    piece::Colour,
+    search::{parameters::Config, pv::PVariation, strength, LMTable, TB_HITS},
     timemgmt::{SearchLimit, TimeManager},
+    transpositiontable::{Bound, TTView},
     uci,
     util::{
         depth::{Depth, ZERO_PLY},
         BatchedAtomicCounter,
     },
+    xboard,
 };
 
 #[cfg(feature = "stats")]
 use crate::board::movegen::MAX_POSITION_MOVES;
+#[cfg(feature = "treedump")]
+use crate::search::treedump::TreeDump;
+
+/// Receives the search's progress reports, when the engine is embedded as a library.
+#[derive(Clone, Copy)]
+pub struct InfoCallback<'a>(pub &'a (dyn Fn(&SearchUpdate) + Sync));
+
+impl Debug for InfoCallback<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("InfoCallback")
+    }
+}
+
+/// Settings that change how a search is run and which move it plays. The UCI loop keeps one
+/// set, changed through `setoption`, and each library [`Engine`](crate::Engine) keeps its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct SearchOptions {
+    /// Search on one thread and keep wall-clock time out of the search, so that results are reproducible.
+    pub deterministic: bool,
+    /// Play weaker moves, at the strength given by `elo` (`UCI_LimitStrength`).
+    pub limit_strength: bool,
+    /// The strength to play at when `limit_strength` is set (`UCI_Elo`).
+    pub elo: i32,
+    /// Play any root move within this many centipawns of the best one, chosen at random.
+    pub variety: i32,
+    /// Analyse rather than play (`UCI_AnalyseMode`): no contempt, no instant moves, and
+    /// optionally no speculative pruning.
+    pub analyse_mode: bool,
+    /// Whether null move pruning stays on in analysis mode.
+    pub analysis_null_move: bool,
+    /// Whether probcut stays on in analysis mode.
+    pub analysis_probcut: bool,
+    /// Whether late move pruning stays on in analysis mode.
+    pub analysis_lmp: bool,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            deterministic: false,
+            limit_strength: false,
+            elo: strength::DEFAULT_ELO,
+            variety: 0,
+            analyse_mode: false,
+            analysis_null_move: true,
+            analysis_probcut: true,
+            analysis_lmp: true,
+        }
+    }
+}
+
+/// A progress report from the search: the line found by an iteration,
+/// or a bound on the score from a failed aspiration window.
+pub struct Report<'a> {
+    pub bound: Bound,
+    pub pv: &'a PVariation,
+    pub depth: usize,
+    pub nodes: u64,
+    pub tt: TTView<'a>,
+    /// Show this report even if output is being held back at the start of a fast game.
+    pub force: bool,
+}
+
+/// Where a search sends its output. The search itself never prints anything.
+pub trait InfoSink: Sync + Debug {
+    /// Receives a progress report.
+    fn report(&self, board: &mut Board, info: &SearchInfo, report: &Report);
+    /// Receives the final line once the search is over, in case it was never reported.
+    fn finish(&self, _board: &mut Board, _info: &SearchInfo, _report: &Report) {}
+    /// Receives the move that the search settled on, or the null move if there were no legal moves.
+    fn best_move(&self, _board: &Board, _m: Move) {}
+    /// Receives the node count and hash usage in the middle of an iteration.
+    fn progress(&self, _info: &SearchInfo, _nodes: u64, _hashfull: usize) {}
+    /// Receives the line the main thread is searching, starting with the root move.
+    fn current_line(&self, _line: &[Move]) {}
+    /// Whether reports go anywhere, so that the search can skip putting them together.
+    fn listening(&self) -> bool {
+        true
+    }
+}
+
+/// Writes the search's output to stdout, as UCI, the human-readable format, or xboard thinking output.
+#[derive(Debug)]
+pub struct UciWriter;
+
+/// Discards the search's output, for bench, datagen, and internal matches.
+#[derive(Debug)]
+pub struct Silent;
+
+impl InfoSink for UciWriter {
+    fn report(&self, board: &mut Board, info: &SearchInfo, report: &Report) {
+        #![allow(clippy::cast_precision_loss, clippy::cast_sign_loss, clippy::cast_possible_truncation)]
+        let (pv, depth, nodes, tt) = (report.pv, report.depth, report.nodes, report.tt);
+        // don't print anything if we are in the first 50ms of the search and we are in a game,
+        // this helps in ultra-fast time controls where we only have a few ms to think.
+        if info.time_manager.is_dynamic() && info.skip_print() && !report.force {
+            return;
+        }
+        if xboard::ACTIVE.load(Ordering::SeqCst) {
+            return xboard::print_thinking(
+                board,
+                report.pv,
+                report.depth,
+                info.time_manager.elapsed().as_millis(),
+                report.nodes,
+            );
+        }
+        let sstr = uci::format_score(report.pv.score);
+        let normal_uci_output = !uci::PRETTY_PRINT.load(Ordering::SeqCst);
+        // in deterministic mode, report zero time so that the output doesn't depend on the machine.
+        let elapsed = if info.deterministic { Duration::ZERO } else { info.time_manager.elapsed() };
+        let nps = if elapsed.is_zero() { 0 } else { (nodes as f64 / elapsed.as_secs_f64()) as u64 };
+        let mut bound = report.bound;
+        if board.turn() == Colour::BLACK {
+            bound = match bound {
+                Bound::Upper => Bound::Lower,
+                Bound::Lower => Bound::Upper,
+                _ => Bound::Exact,
+            };
+        }
+        let bound_string = match bound {
+            Bound::Upper => " upperbound",
+            Bound::Lower => " lowerbound",
+            _ => "",
+        };
+        if normal_uci_output {
+            let pv_string = if uci::PRETTY_PV.load(Ordering::SeqCst) && !pv.moves().is_empty() {
+                format!("pv {}", board.pv_san(pv).unwrap())
+            } else {
+                pv.to_string()
+            };
+            println_logged!(
+                "info score {sstr}{bound_string} wdl {wdl} depth {depth} seldepth {} nodes {nodes} time {} nps {nps} hashfull {hashfull} tbhits {tbhits} {pv_string}",
+                info.seldepth.ply_to_horizon(),
+                elapsed.as_millis(),
+                hashfull = tt.hashfull(),
+                tbhits = TB_HITS.load(Ordering::SeqCst),
+                wdl = uci::format_wdl(pv.score, board.ply()),
+            );
+        } else {
+            let value = uci::pretty_format_score(pv.score, board.turn());
+            let mut pv_string = board.pv_san(pv).unwrap();
+            // truncate the pv string if it's too long
+            if pv_string.len() > 130 {
+                let final_space = pv_string.match_indices(' ').filter(|(i, _)| *i < 130).last().map_or(0, |(i, _)| i);
+                pv_string.truncate(final_space);
+                pv_string.push_str("...         ");
+            }
+            let endchr = if bound == Bound::Exact {
+                "\n"
+            } else {
+                "                                                                   \r"
+            };
+            eprint!(
+                " {depth:2}/{:<2} \u{001b}[38;5;243m{t} {knodes:8}kn\u{001b}[0m {value} {bar} ({wdl}) \u{001b}[38;5;243m{knps:5}kn/s\u{001b}[0m {pv_string}{endchr}",
+                info.seldepth.ply_to_horizon(),
+                t = uci::format_time(elapsed.as_millis()),
+                knps = nps / 1_000,
+                knodes = nodes / 1_000,
+                wdl = uci::pretty_format_wdl(pv.score, board.ply()),
+                bar = uci::pretty_format_eval_bar(pv.score, board.ply(), board.turn()),
+            );
+        }
+    }
+
+    fn finish(&self, board: &mut Board, info: &SearchInfo, report: &Report) {
+        if info.skip_print() {
+            // we haven't printed any ID logging yet, so give one as we leave search.
+            self.report(board, info, report);
+        }
+    }
+
+    fn progress(&self, info: &SearchInfo, nodes: u64, hashfull: usize) {
+        #![allow(clippy::cast_precision_loss, clippy::cast_sign_loss, clippy::cast_possible_truncation)]
+        if xboard::ACTIVE.load(Ordering::SeqCst) || uci::PRETTY_PRINT.load(Ordering::SeqCst) {
+            return;
+        }
+        let elapsed = info.time_manager.elapsed();
+        let nps = (nodes as f64 / elapsed.as_secs_f64()) as u64;
+        println_logged!("info nodes {nodes} nps {nps} hashfull {hashfull} time {}", elapsed.as_millis());
+    }
+
+    fn current_line(&self, line: &[Move]) {
+        if xboard::ACTIVE.load(Ordering::SeqCst) || uci::PRETTY_PRINT.load(Ordering::SeqCst) {
+            return;
+        }
+        let moves = line.iter().map(Move::to_string).collect::<Vec<_>>();
+        println_logged!("info currline {}", moves.join(" "));
+    }
+
+    fn best_move(&self, board: &Board, m: Move) {
+        if m.is_null() {
+            eprintln_logged!("info string warning search called on a position with no legal moves");
+            if board.in_check() {
+                println_logged!("info depth 0 score mate 0");
+            } else {
+                println_logged!("info depth 0 score cp 0");
+            }
+            println_logged!("bestmove (none)");
+        } else if !xboard::ACTIVE.load(Ordering::SeqCst) {
+            println_logged!("bestmove {m}");
+        }
+    }
+}
+
+impl InfoSink for Silent {
+    fn report(&self, _board: &mut Board, _info: &SearchInfo, _report: &Report) {}
+
+    fn listening(&self) -> bool {
+        false
+    }
+}
+
+impl InfoSink for InfoCallback<'_> {
+    fn report(&self, board: &mut Board, info: &SearchInfo, report: &Report) {
+        (self.0)(&SearchUpdate::new(board, report.bound, report.pv, report.depth, info, report.nodes));
+    }
+}
 
 #[allow(clippy::struct_excessive_bools)]
 #[derive(Clone, Debug)]
@@ -30,31 +259,42 @@ pub struct SearchInfo<'a> {
     pub seldepth: Depth,
     /// A handle to a receiver for stdin.
     pub stdin_rx: Option<&'a Mutex<mpsc::Receiver<String>>>,
-    /// Whether to print the search info to stdout.
-    pub print_to_stdout: bool,
+    /// Where the search's output goes.
+    pub sink: &'a dyn InfoSink,
     /// Search parameters.
     pub conf: Config,
     /// LMR + LMP lookup table.
     pub lm_table: LMTable,
     /// The time manager.
     pub time_manager: TimeManager,
-
-    /* Conditionally-compiled stat trackers: */
-    /// The number of fail-highs found (beta cutoffs).
-    #[cfg(feature = "stats")]
-    pub failhigh: u64,
-    /// The number of fail-highs that occurred on a given ply.
-    #[cfg(feature = "stats")]
-    pub failhigh_index: [u64; MAX_POSITION_MOVES],
-    /// Tracks fail-highs of different types.
+    /// The settings the search was started with.
+    pub options: SearchOptions,
+    /// Whether to check the search limits on every node, and keep
+    /// wall-clock time out of the output, so that searches are reproducible.
+    pub deterministic: bool,
+    /// Whether the GUI has asked for analysis (`UCI_AnalyseMode`) rather than play.
+    pub analysing: bool,
+    /// Whether the speculative pruning techniques are enabled. In analysis mode,
+    /// each of these can be switched off to avoid missing tactics that they overlook.
+    pub null_move_pruning: bool,
+    pub probcut: bool,
+    pub late_move_pruning: bool,
+    /// Whether to report the line being searched with `info currline` (`UCI_ShowCurrLine`).
+    pub show_current_line: bool,
+    /// The root move the main thread is searching, and when it started on it.
+    pub root_move: Move,
+    pub root_move_started: Duration,
+    /// When the current line was last reported.
+    pub current_line_reported: Duration,
+    /// When the node count and hash usage were last reported.
+    pub progress_reported: Duration,
+
+    /// Search statistics, for the `searchstats` command.
     #[cfg(feature = "stats")]
-    pub failhigh_types: [u64; 8],
-    /// The number of fail-highs found in quiescence search.
-    #[cfg(feature = "stats")]
-    pub qfailhigh: u64,
-    /// The number of fail-highs that occurred on a given ply in quiescence search.
-    #[cfg(feature = "stats")]
-    pub qfailhigh_index: [u64; MAX_POSITION_MOVES],
+    pub stats: SearchStats,
+    /// The recorded search tree, for the `treedump` command.
+    #[cfg(feature = "treedump")]
+    pub tree: TreeDump,
 }
 
 impl<'a> SearchInfo<'a> {
@@ -65,20 +305,25 @@ impl<'a> SearchInfo<'a> {
             stopped,
             seldepth: ZERO_PLY,
             stdin_rx: None,
-            print_to_stdout: true,
+            sink: &UciWriter,
             conf: Config::default(),
             lm_table: LMTable::default(),
             time_manager: TimeManager::default(),
+            options: SearchOptions::default(),
+            deterministic: false,
+            analysing: false,
+            null_move_pruning: true,
+            probcut: true,
+            late_move_pruning: true,
+            show_current_line: false,
+            root_move: Move::NULL,
+            root_move_started: Duration::ZERO,
+            current_line_reported: Duration::ZERO,
+            progress_reported: Duration::ZERO,
             #[cfg(feature = "stats")]
-            failhigh: 0,
-            #[cfg(feature = "stats")]
-            failhigh_index: [0; MAX_POSITION_MOVES],
-            #[cfg(feature = "stats")]
-            failhigh_types: [0; 8],
-            #[cfg(feature = "stats")]
-            qfailhigh: 0,
-            #[cfg(feature = "stats")]
-            qfailhigh_index: [0; MAX_POSITION_MOVES],
+            stats: SearchStats::new(),
+            #[cfg(feature = "treedump")]
+            tree: TreeDump::new(),
         };
         assert!(!out.stopped.load(Ordering::SeqCst));
         out
@@ -95,14 +340,21 @@ impl<'a> SearchInfo<'a> {
         self.nodes.reset();
         self.root_move_nodes = [[0; 64]; 64];
         self.time_manager.reset_for_id(&self.conf);
+        self.deterministic = self.options.deterministic;
+        self.analysing = self.options.analyse_mode;
+        self.null_move_pruning = !self.analysing || self.options.analysis_null_move;
+        self.probcut = !self.analysing || self.options.analysis_probcut;
+        self.late_move_pruning = !self.analysing || self.options.analysis_lmp;
+        self.show_current_line = uci::SHOW_CURRLINE.load(Ordering::SeqCst);
+        self.root_move = Move::NULL;
+        self.current_line_reported = Duration::ZERO;
+        self.progress_reported = Duration::ZERO;
         #[cfg(feature = "stats")]
         {
-            self.failhigh = 0;
-            self.failhigh_index = [0; MAX_POSITION_MOVES];
-            self.failhigh_types = [0; 8];
-            self.qfailhigh = 0;
-            self.qfailhigh_index = [0; MAX_POSITION_MOVES];
+            self.stats = SearchStats::new();
         }
+        #[cfg(feature = "treedump")]
+        self.tree.clear();
     }
 
     pub fn set_stdin(&mut self, stdin_rx: &'a Mutex<mpsc::Receiver<String>>) {
@@ -127,15 +379,83 @@ impl<'a> SearchInfo<'a> {
         }
     }
 
+    /// Whether it's time to check the search limits and stdin.
+    /// Normally this happens once every batch of nodes, but in deterministic mode
+    /// it happens on every node, so node limits are hit exactly.
+    pub const fn should_check_up(&self) -> bool {
+        self.deterministic || self.nodes.just_ticked_over()
+    }
+
     pub fn skip_print(&self) -> bool {
-        self.time_manager.time_since_start().as_millis() < 50
+        !self.deterministic && self.time_manager.time_since_start().as_millis() < 50
     }
 
     pub fn stopped(&self) -> bool {
         self.stopped.load(Ordering::SeqCst)
     }
+}
+
+/// Counters that are collected during search when the `stats` feature is enabled.
+/// Only the main thread's counters are kept.
+#[cfg(feature = "stats")]
+#[derive(Clone, Debug)]
+pub struct SearchStats {
+    /// The number of fail-highs found (beta cutoffs).
+    pub failhigh: u64,
+    /// The number of fail-highs that occurred on a given ply.
+    pub failhigh_index: [u64; MAX_POSITION_MOVES],
+    /// Tracks fail-highs of different types.
+    pub failhigh_types: [u64; 8],
+    /// The number of fail-highs found in quiescence search.
+    pub qfailhigh: u64,
+    /// The number of fail-highs that occurred on a given ply in quiescence search.
+    pub qfailhigh_index: [u64; MAX_POSITION_MOVES],
+    /// The number of nodes visited by the main search and by quiescence search.
+    pub main_nodes: u64,
+    pub qsearch_nodes: u64,
+    /// The number of transposition table probes in the main search, and how many of them hit.
+    pub tt_probes: u64,
+    pub tt_hits: u64,
+    /// The number of null-move searches, and how many of them failed high.
+    pub nmp_attempts: u64,
+    pub nmp_cutoffs: u64,
+    /// The number of reduced searches, and how many of them had to be re-searched.
+    pub lmr_searches: u64,
+    pub lmr_researches: u64,
+    /// The number of times that each move-pruning technique fired.
+    pub lmp_triggers: u64,
+    pub history_prunes: u64,
+    pub futility_triggers: u64,
+    pub see_prunes: u64,
+    /// The depth that the last search completed.
+    pub completed_depth: usize,
+}
+
+#[cfg(feature = "stats")]
+impl SearchStats {
+    pub const fn new() -> Self {
+        Self {
+            failhigh: 0,
+            failhigh_index: [0; MAX_POSITION_MOVES],
+            failhigh_types: [0; 8],
+            qfailhigh: 0,
+            qfailhigh_index: [0; MAX_POSITION_MOVES],
+            main_nodes: 0,
+            qsearch_nodes: 0,
+            tt_probes: 0,
+            tt_hits: 0,
+            nmp_attempts: 0,
+            nmp_cutoffs: 0,
+            lmr_searches: 0,
+            lmr_researches: 0,
+            lmp_triggers: 0,
+            history_prunes: 0,
+            futility_triggers: 0,
+            see_prunes: 0,
+            completed_depth: 0,
+        }
+    }
 
-    #[cfg(feature = "stats")]
     pub fn log_fail_high<const QSEARCH: bool>(&mut self, move_index: usize, ordering_score: i32) {
         use crate::board::movegen::movepicker::{
             COUNTER_MOVE_SCORE, FIRST_KILLER_SCORE, SECOND_KILLER_SCORE, TT_MOVE_SCORE, WINNING_CAPTURE_SCORE,
@@ -166,8 +486,7 @@ impl<'a> SearchInfo<'a> {
         }
     }
 
-    #[cfg(feature = "stats")]
-    pub fn print_stats(&self) {
+    pub fn print(&self) {
         #[allow(clippy::cast_precision_loss)]
         let fail_high_percentages =
             self.failhigh_index.iter().map(|&x| (x as f64 * 100.0) / self.failhigh as f64).take(10).collect::<Vec<_>>();
@@ -181,17 +500,43 @@ impl<'a> SearchInfo<'a> {
         for ((i1, &x1), (i2, &x2)) in
             fail_high_percentages.iter().enumerate().zip(qs_fail_high_percentages.iter().enumerate())
         {
-            println!("failhigh {x1:5.2}% at move {i1}     qfailhigh {x2:5.2}% at move {i2}");
+            println_logged!("failhigh {x1:5.2}% at move {i1}     qfailhigh {x2:5.2}% at move {i2}");
         }
         let type_percentages =
             self.failhigh_types.iter().map(|&x| (x as f64 * 100.0) / self.failhigh as f64).collect::<Vec<_>>();
-        println!("failhigh ttmove        {:5.2}%", type_percentages[0]);
-        println!("failhigh good tactical {:5.2}%", type_percentages[1]);
-        println!("failhigh killer1       {:5.2}%", type_percentages[2]);
-        println!("failhigh killer2       {:5.2}%", type_percentages[3]);
-        println!("failhigh countermove   {:5.2}%", type_percentages[4]);
-        println!("failhigh good quiet    {:5.2}%", type_percentages[5]);
-        println!("failhigh bad quiet     {:5.2}%", type_percentages[6]);
+        println_logged!("failhigh ttmove        {:5.2}%", type_percentages[0]);
+        println_logged!("failhigh good tactical {:5.2}%", type_percentages[1]);
+        println_logged!("failhigh killer1       {:5.2}%", type_percentages[2]);
+        println_logged!("failhigh killer2       {:5.2}%", type_percentages[3]);
+        println_logged!("failhigh countermove   {:5.2}%", type_percentages[4]);
+        println_logged!("failhigh good quiet    {:5.2}%", type_percentages[5]);
+        println_logged!("failhigh bad quiet     {:5.2}%", type_percentages[6]);
+        #[allow(clippy::cast_precision_loss)]
+        let percent = |part: u64, whole: u64| if whole == 0 { 0.0 } else { part as f64 * 100.0 / whole as f64 };
+        let nodes = self.main_nodes + self.qsearch_nodes;
+        println_logged!("qsearch node share     {:5.2}%", percent(self.qsearch_nodes, nodes));
+        println_logged!(
+            "tt hit rate            {:5.2}% of {} probes",
+            percent(self.tt_hits, self.tt_probes),
+            self.tt_probes
+        );
+        println_logged!(
+            "nmp cutoff rate        {:5.2}% of {} attempts",
+            percent(self.nmp_cutoffs, self.nmp_attempts),
+            self.nmp_attempts
+        );
+        println_logged!(
+            "lmr re-search rate     {:5.2}% of {} reduced searches",
+            percent(self.lmr_researches, self.lmr_searches),
+            self.lmr_searches
+        );
+        println_logged!("lmp triggers           {}", self.lmp_triggers);
+        println_logged!("history prunes         {}", self.history_prunes);
+        println_logged!("futility triggers      {}", self.futility_triggers);
+        println_logged!("see prunes             {}", self.see_prunes);
+        #[allow(clippy::cast_precision_loss)]
+        let branching_factor = (nodes as f64).powf(1.0 / self.completed_depth.max(1) as f64);
+        println_logged!("branching factor       {branching_factor:.2}");
     }
 }
 
@@ -0,0 +1,136 @@
+// Self-play games written out as PGN, for quick sample games or eval-annotated material.
+// Both sides are played by one in-process `Engine`, on a clock or at a fixed node count,
+// and every move is annotated with the score, depth, and time of the search that chose it,
+// in the "{+0.35/12 0.84s}" form that most GUIs and tools understand.
+
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    board::{Board, GameOutcome},
+    engine::{Engine, Limit, Score, SearchResult},
+    gauntlet::{white_centipawns, write_pgn, Adjudicator, GameResult},
+    rng::Xoshiro,
+    sprt::{load_book, random_opening},
+    NAME, VERSION,
+};
+
+/// How long each move may take.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveLimit {
+    /// A clock for each side, with an increment.
+    Clock { time: Duration, increment: Duration },
+    /// A fixed number of nodes per move.
+    Nodes(u64),
+}
+
+/// Configuration options for a self-play run.
+pub struct SelfplayOptions {
+    /// A file of opening positions, one FEN or EPD per line.
+    pub book: Option<PathBuf>,
+    pub limit: MoveLimit,
+    pub games: usize,
+    /// Where to write the games.
+    pub pgn: PathBuf,
+    /// The hash table size, in megabytes.
+    pub hash_mb: usize,
+}
+
+/// The PGN comment for a move: the score from the mover's point of view, in pawns, with the depth and time.
+fn move_comment(result: &SearchResult, elapsed: Duration) -> String {
+    let score = match result.score {
+        Score::Centipawns(cp) => format!("{:+.2}", f64::from(cp) / 100.0),
+        Score::Mate(n) if n > 0 => format!("+M{n}"),
+        Score::Mate(n) => format!("-M{}", -n),
+    };
+    format!("{{{score}/{} {:.2}s}}", result.depth, elapsed.as_secs_f64())
+}
+
+/// Plays one game from `opening`, returning the result and the annotated moves in SAN.
+fn play_game(opening: &Board, engine: &mut Engine, limit: MoveLimit) -> Result<(GameResult, Vec<String>), String> {
+    let start_fen = opening.fen();
+    let mut board = opening.clone();
+    let mut uci_moves = Vec::new();
+    let mut annotated = Vec::new();
+    let mut clocks = match limit {
+        MoveLimit::Clock { time, .. } => [time; 2],
+        MoveLimit::Nodes(_) => [Duration::ZERO; 2],
+    };
+    let mut adjudicator = Adjudicator::default();
+    engine.new_game();
+    loop {
+        let outcome = board.outcome();
+        if outcome != GameOutcome::Ongoing {
+            return Ok((GameResult::from_outcome(outcome), annotated));
+        }
+        let mover = board.turn();
+        let played = uci_moves.iter().map(String::as_str).collect::<Vec<_>>();
+        engine.set_position(Some(&start_fen), &played).map_err(|e| e.to_string())?;
+        let start = Instant::now();
+        let result = match limit {
+            MoveLimit::Clock { increment, .. } => {
+                engine.search(Limit::Clock { time: clocks[mover.index()], increment }, |_| {})
+            }
+            MoveLimit::Nodes(nodes) => engine.search(Limit::Nodes(nodes), |_| {}),
+        };
+        let elapsed = start.elapsed();
+        if let MoveLimit::Clock { increment, .. } = limit {
+            let clock = &mut clocks[mover.index()];
+            *clock = clock.saturating_sub(elapsed) + increment;
+        }
+
+        let text = result.best_move.clone().ok_or("the engine found no move in an ongoing game")?;
+        let m = board.parse_uci(&text).map_err(|e| format!("the engine played an illegal move {text}: {e}"))?;
+        let san = board.san(m).unwrap_or_else(|| text.clone());
+        annotated.push(format!("{san} {}", move_comment(&result, elapsed)));
+        uci_moves.push(text);
+        board.make_move_simple(m);
+
+        let white_cp = white_centipawns(result.score, mover);
+        if let Some(result) = adjudicator.update(Some(white_cp), board.ply()) {
+            return Ok((result, annotated));
+        }
+    }
+}
+
+pub fn run_selfplay(options: &SelfplayOptions) -> Result<(), String> {
+    let book = options.book.as_ref().map_or_else(|| Ok(Vec::new()), load_book)?;
+    let file = File::create(&options.pgn).map_err(|e| format!("failed to create {}: {e}", options.pgn.display()))?;
+    let mut pgn = BufWriter::new(file);
+    let mut engine = Engine::new();
+    engine.set_hash_size(options.hash_mb);
+    let name = format!("{NAME} {VERSION}");
+    let event = format!("{NAME} self-play");
+
+    let mut rng = Xoshiro::from_user_seed(0);
+    for game in 0..options.games {
+        let opening = if book.is_empty() { random_opening(&mut rng) } else { book[game % book.len()].clone() };
+        let (result, moves) = play_game(&opening, &mut engine, options.limit)?;
+        write_pgn(&mut pgn, &event, game + 1, [&name, &name], &opening, &result, &moves)
+            .map_err(|e| format!("failed to write PGN: {e}"))?;
+        println!("Game {}: {} ({}) after {} plies", game + 1, result.pgn_result(), result.termination, moves.len());
+    }
+    println!("Wrote {} games to {}", options.games, options.pgn.display());
+    Ok(())
+}
+
+mod tests {
+    #[test]
+    fn move_comments() {
+        use super::move_comment;
+        use crate::engine::{Score, SearchResult};
+        use std::time::Duration;
+
+        let mut result =
+            SearchResult { best_move: None, score: Score::Centipawns(35), depth: 12, nodes: 0, pv: Vec::new() };
+        assert_eq!(move_comment(&result, Duration::from_millis(840)), "{+0.35/12 0.84s}");
+        result.score = Score::Centipawns(-120);
+        assert_eq!(move_comment(&result, Duration::ZERO), "{-1.20/12 0.00s}");
+        result.score = Score::Mate(-3);
+        assert_eq!(move_comment(&result, Duration::ZERO), "{-M3/12 0.00s}");
+    }
+}
@@ -0,0 +1,94 @@
+// Consistency checks that don't fit into perft. Currently this checks that the
+// evaluation is colour-symmetric: a position and its mirror image (ranks flipped,
+// colours swapped) must get the same score from the perspective of the side to move.
+// Asymmetries tend to creep in whenever a new evaluation term is added, and they
+// are very hard to spot from search behaviour alone.
+
+use crate::{bench::BENCH_POSITIONS, board::Board, threadlocal::ThreadData, transpositiontable::TT, util::MEGABYTE};
+
+/// Endgames that take the special-cased paths through the evaluation.
+const ENDGAME_POSITIONS: [&str; 4] = [
+    // insufficient material.
+    "8/8/4k3/8/8/3KN3/8/8 w - - 0 1",
+    // KPK, drawn and won.
+    "8/4k3/8/4K3/4P3/8/8/8 w - - 0 1",
+    "4k3/8/4K3/4P3/8/8/8/8 w - - 0 1",
+    // mop-up against a bare king.
+    "8/8/8/3k4/8/8/2QK4/8 w - - 0 1",
+];
+
+/// The bench positions and some endgames, along with every position one legal move away from them.
+fn symmetry_positions() -> Vec<Board> {
+    let mut positions = Vec::new();
+    for fen in BENCH_POSITIONS.iter().chain(&ENDGAME_POSITIONS) {
+        let mut board = Board::from_fen(fen).unwrap();
+        positions.push(board.clone());
+        for m in board.legal_moves() {
+            board.make_move_simple(m);
+            positions.push(board.clone());
+            board.unmake_move_base();
+        }
+    }
+    positions
+}
+
+/// Evaluates `board` and its mirror image, returning both scores
+/// if they differ from the perspective of the side to move.
+fn check_eval_symmetry(board: &Board, t: &mut ThreadData) -> Result<(), (i32, i32)> {
+    let mirrored = board.mirrored();
+    t.set_up_for_search(board);
+    let eval = board.evaluate(t, 0);
+    t.set_up_for_search(&mirrored);
+    let mirrored_eval = mirrored.evaluate(t, 0);
+    if eval == mirrored_eval {
+        Ok(())
+    } else {
+        Err((eval, mirrored_eval))
+    }
+}
+
+pub fn run() {
+    let mut tt = TT::new();
+    tt.resize(MEGABYTE);
+    let positions = symmetry_positions();
+    let mut t = ThreadData::new(0, &positions[0], tt.view());
+    println!("checking evaluation symmetry on {} positions", positions.len());
+    let mut failures = 0;
+    for board in &positions {
+        if let Err((eval, mirrored_eval)) = check_eval_symmetry(board, &mut t) {
+            println!("FAIL: fen {}: eval {eval}, mirrored eval {mirrored_eval}", board.fen());
+            failures += 1;
+        }
+    }
+    assert!(failures == 0, "evaluation is asymmetric in {failures} positions");
+    println!("PASS: evaluation is symmetric");
+}
+
+mod tests {
+    #[test]
+    fn mirroring_is_an_involution() {
+        use crate::board::Board;
+        let board = Board::from_fen("r3k2r/8/8/8/3pP3/8/8/R3K1R1 b Qkq e3 0 1").unwrap();
+        let mirrored = board.mirrored();
+        assert_eq!(mirrored.fen(), "r3k1r1/8/8/3Pp3/8/8/8/R3K2R w KQq e6 0 1");
+        assert_eq!(mirrored.mirrored().fen(), board.fen());
+    }
+
+    #[test]
+    fn eval_is_symmetric() {
+        use super::{check_eval_symmetry, symmetry_positions, ENDGAME_POSITIONS};
+        use crate::{board::Board, threadlocal::ThreadData, transpositiontable::TT, util::MEGABYTE};
+        let mut tt = TT::new();
+        tt.resize(MEGABYTE);
+        // a subset of the positions, as the full set is slow in debug builds.
+        let positions = symmetry_positions()
+            .into_iter()
+            .step_by(16)
+            .chain(ENDGAME_POSITIONS.iter().map(|fen| Board::from_fen(fen).unwrap()))
+            .collect::<Vec<_>>();
+        let mut t = ThreadData::new(0, &positions[0], tt.view());
+        for board in &positions {
+            assert_eq!(check_eval_symmetry(board, &mut t), Ok(()), "fen {}", board.fen());
+        }
+    }
+}
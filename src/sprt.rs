@@ -0,0 +1,337 @@
+// A self-play SPRT harness, for testing changes to search parameters without
+// external tooling. Two configurations play game pairs against each other from
+// the same opening with colours reversed, and the log-likelihood ratio is
+// updated after every game until it crosses one of the bounds.
+
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+use rand::{Rng, SeedableRng};
+
+use crate::{
+    board::{evaluation::is_game_theoretic_score, Board, GameOutcome},
+    piece::Colour,
+    rng::{self, Xoshiro},
+    search::{parameters::Config, LMTable},
+    searchinfo::{SearchInfo, Silent},
+    threadlocal::ThreadData,
+    timemgmt::{SearchLimit, TimeManager},
+    transpositiontable::TT,
+    util::MEGABYTE,
+};
+
+/// The number of random plies played from the starting position when no book is given.
+const RANDOM_OPENING_PLIES: usize = 8;
+
+/// Configuration options for an SPRT run.
+pub struct SprtOptions {
+    /// Comma-separated `NAME=VALUE` overrides for the configuration under test.
+    pub test: String,
+    /// Comma-separated `NAME=VALUE` overrides for the baseline configuration.
+    pub base: Option<String>,
+    /// A file of opening positions, one FEN or EPD per line.
+    pub book: Option<PathBuf>,
+    /// The soft node limit for each move.
+    pub nodes: u64,
+    /// The Elo bounds of the test.
+    pub elo0: f64,
+    pub elo1: f64,
+    /// The number of threads to play games on.
+    pub threads: usize,
+    /// The maximum number of games to play before giving up.
+    pub max_games: usize,
+}
+
+/// Game results, from the perspective of the configuration (or engine) under test.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Tally {
+    pub wins: u64,
+    pub draws: u64,
+    pub losses: u64,
+}
+
+impl Tally {
+    pub const fn games(&self) -> u64 {
+        self.wins + self.draws + self.losses
+    }
+
+    pub fn score(&self) -> f64 {
+        #![allow(clippy::cast_precision_loss)]
+        (self.wins as f64 + self.draws as f64 / 2.0) / self.games() as f64
+    }
+
+    pub fn elo(&self) -> f64 {
+        let score = self.score().clamp(1e-6, 1.0 - 1e-6);
+        -400.0 * f64::log10(1.0 / score - 1.0)
+    }
+
+    /// The log-likelihood ratio of H1 (elo = `elo1`) against H0 (elo = `elo0`),
+    /// using the normal approximation to the trinomial GSPRT.
+    fn llr(&self, elo0: f64, elo1: f64) -> f64 {
+        #![allow(clippy::cast_precision_loss, clippy::suboptimal_flops)]
+        if self.games() == 0 {
+            return 0.0;
+        }
+        let n = self.games() as f64;
+        let (w, d, l) = (self.wins as f64 / n, self.draws as f64 / n, self.losses as f64 / n);
+        let score = w + d / 2.0;
+        let variance = w * (1.0 - score).powi(2) + d * (0.5 - score).powi(2) + l * score.powi(2);
+        if variance <= 0.0 {
+            return 0.0;
+        }
+        let (s0, s1) = (expected_score(elo0), expected_score(elo1));
+        n * (s1 - s0) * (2.0 * score - s0 - s1) / (2.0 * variance)
+    }
+}
+
+fn expected_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+/// Applies comma-separated `NAME=VALUE` overrides to the default configuration.
+fn parse_config(overrides: &str) -> Result<Config, String> {
+    let mut config = Config::default();
+    for pair in overrides.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        let (name, value) = pair.split_once('=').ok_or_else(|| format!("expected NAME=VALUE, got \"{pair}\""))?;
+        let mut parsers = config.ids_with_parsers();
+        let (_, parser) = parsers
+            .iter_mut()
+            .find(|(id, _)| *id == name.trim())
+            .ok_or_else(|| format!("unknown parameter \"{}\"", name.trim()))?;
+        parser(value.trim()).map_err(|e| format!("invalid value for {}: {e}", name.trim()))?;
+    }
+    Ok(config)
+}
+
+pub fn load_book(path: &PathBuf) -> Result<Vec<Board>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            // EPD lines carry opcodes instead of move counters, so only keep the position.
+            let fields = line.split_whitespace().take(4).collect::<Vec<_>>().join(" ");
+            Board::from_fen(&format!("{fields} 0 1")).map_err(|e| format!("bad position \"{line}\": {e}"))
+        })
+        .collect()
+}
+
+pub fn random_opening(rng: &mut impl Rng) -> Board {
+    'retry: loop {
+        let mut board = Board::default();
+        for _ in 0..RANDOM_OPENING_PLIES {
+            let moves = board.legal_moves();
+            if moves.is_empty() {
+                continue 'retry;
+            }
+            board.make_move_simple(moves[rng.gen_range(0..moves.len())]);
+        }
+        if board.outcome() == GameOutcome::Ongoing {
+            return board;
+        }
+    }
+}
+
+/// One side of a game: a configuration, along with its own search state.
+struct Player<'a> {
+    info: SearchInfo<'a>,
+    thread_data: ThreadData<'a>,
+    tt: &'a TT,
+}
+
+fn play_game(mut board: Board, players: &mut [Player; 2]) -> GameOutcome {
+    for player in players.iter_mut() {
+        player.tt.clear(1);
+        player.thread_data.clear_tables();
+    }
+    let mut win_adj_counter = 0;
+    let mut draw_adj_counter = 0;
+    loop {
+        let outcome = board.outcome();
+        if outcome != GameOutcome::Ongoing {
+            return outcome;
+        }
+
+        let player = &mut players[board.turn().index()];
+        player.tt.increase_age();
+        let (score, best_move) =
+            board.search_position(&mut player.info, std::array::from_mut(&mut player.thread_data), player.tt.view());
+
+        // the same adjudication rules as in datagen.
+        if score.abs() >= 2000 || is_game_theoretic_score(score) {
+            win_adj_counter += 1;
+            draw_adj_counter = 0;
+        } else if score.abs() <= 4 {
+            draw_adj_counter += 1;
+            win_adj_counter = 0;
+        } else {
+            win_adj_counter = 0;
+            draw_adj_counter = 0;
+        }
+        if win_adj_counter >= 4 {
+            return if score > 0 { GameOutcome::WhiteWinAdjudication } else { GameOutcome::BlackWinAdjudication };
+        }
+        if draw_adj_counter >= 12 {
+            return GameOutcome::DrawAdjudication;
+        }
+
+        board.make_move_simple(best_move);
+    }
+}
+
+fn run_on_thread(
+    options: &SprtOptions,
+    configs: &[Config; 2],
+    book: &[Board],
+    next_pair: &AtomicUsize,
+    tally: &Mutex<Tally>,
+    finished: &AtomicBool,
+    seed: u64,
+) {
+    let stopped = [AtomicBool::new(false), AtomicBool::new(false)];
+    let nodes = [AtomicU64::new(0), AtomicU64::new(0)];
+    let mut tts = [TT::new(), TT::new()];
+    for tt in &mut tts {
+        tt.resize(16 * MEGABYTE);
+    }
+    let board = Board::default();
+    // players[0] is the configuration under test, players[1] is the baseline.
+    let mut players = [0, 1].map(|i| Player {
+        info: SearchInfo {
+            sink: &Silent,
+            conf: configs[i].clone(),
+            lm_table: LMTable::new(&configs[i]),
+            time_manager: TimeManager::default_with_limit(SearchLimit::SoftNodes {
+                soft_limit: options.nodes,
+                hard_limit: options.nodes * 8,
+            }),
+            ..SearchInfo::new(&stopped[i], &nodes[i])
+        },
+        thread_data: ThreadData::new(0, &board, tts[i].view()),
+        tt: &tts[i],
+    });
+
+    while !finished.load(Ordering::SeqCst) {
+        let pair = next_pair.fetch_add(1, Ordering::SeqCst);
+        if pair * 2 >= options.max_games {
+            break;
+        }
+        // each pair's opening comes from its own stream, so the openings don't depend on thread scheduling.
+        let opening = if book.is_empty() {
+            random_opening(&mut Xoshiro::seed_from_u64(seed.wrapping_add(pair as u64)))
+        } else {
+            book[pair % book.len()].clone()
+        };
+        for test_colour in [Colour::WHITE, Colour::BLACK] {
+            if test_colour == Colour::BLACK {
+                players.swap(0, 1);
+            }
+            let outcome = play_game(opening.clone(), &mut players);
+            if test_colour == Colour::BLACK {
+                players.swap(0, 1);
+            }
+            // as_packed_u8 gives 2 for a white win, 1 for a draw, and 0 for a black win.
+            let white_result = outcome.as_packed_u8();
+            let test_result = if test_colour == Colour::WHITE { white_result } else { 2 - white_result };
+
+            let tally = {
+                let mut tally = tally.lock().unwrap();
+                match test_result {
+                    2 => tally.wins += 1,
+                    1 => tally.draws += 1,
+                    _ => tally.losses += 1,
+                }
+                *tally
+            };
+            let llr = tally.llr(options.elo0, options.elo1);
+            let (lower, upper) = llr_bounds();
+            println!(
+                "Games: {} (+{} ={} -{}) | Elo: {:.2} | LLR: {llr:.2} ({lower:.2}, {upper:.2})",
+                tally.games(),
+                tally.wins,
+                tally.draws,
+                tally.losses,
+                tally.elo()
+            );
+            if llr <= lower || llr >= upper {
+                finished.store(true, Ordering::SeqCst);
+            }
+        }
+    }
+}
+
+/// The (lower, upper) LLR bounds for alpha = beta = 0.05.
+fn llr_bounds() -> (f64, f64) {
+    let (alpha, beta) = (0.05f64, 0.05f64);
+    (f64::ln(beta / (1.0 - alpha)), f64::ln((1.0 - beta) / alpha))
+}
+
+pub fn run_sprt(options: &SprtOptions) {
+    let configs = match (
+        parse_config(&options.test),
+        options.base.as_deref().map_or_else(|| Ok(Config::default()), parse_config),
+    ) {
+        (Ok(test), Ok(base)) => [test, base],
+        (Err(e), _) | (_, Err(e)) => return eprintln!("Failed to parse parameters: {e}"),
+    };
+    let book = match options.book.as_ref().map_or_else(|| Ok(Vec::new()), load_book) {
+        Ok(book) => book,
+        Err(e) => return eprintln!("Failed to load opening book: {e}"),
+    };
+
+    println!(
+        "Running SPRT [{}, {}] at {} nodes per move on {} threads.",
+        options.elo0, options.elo1, options.nodes, options.threads
+    );
+    let seed = rng::user_seed();
+    if book.is_empty() {
+        println!("No opening book given, playing from random {RANDOM_OPENING_PLIES}-ply openings (seed {seed}).");
+    }
+
+    let next_pair = AtomicUsize::new(0);
+    let tally = Mutex::new(Tally::default());
+    let finished = AtomicBool::new(false);
+    std::thread::scope(|s| {
+        for _ in 0..options.threads {
+            s.spawn(|| run_on_thread(options, &configs, &book, &next_pair, &tally, &finished, seed));
+        }
+    });
+
+    let tally = tally.into_inner().unwrap();
+    let llr = tally.llr(options.elo0, options.elo1);
+    let (lower, upper) = llr_bounds();
+    if llr >= upper {
+        println!("H1 accepted: the test configuration is stronger.");
+    } else if llr <= lower {
+        println!("H0 accepted: the test configuration is not stronger.");
+    } else {
+        println!("Test inconclusive after {} games.", tally.games());
+    }
+}
+
+mod tests {
+    #[test]
+    fn llr_moves_in_the_right_direction() {
+        use super::Tally;
+        let even = Tally { wins: 300, draws: 400, losses: 300 };
+        assert!(even.llr(0.0, 5.0) < 0.0);
+        let winning = Tally { wins: 400, draws: 400, losses: 200 };
+        assert!(winning.llr(0.0, 5.0) > 0.0);
+        assert!(winning.elo() > 0.0);
+        let all_draws = Tally { wins: 0, draws: 100, losses: 0 };
+        assert!(all_draws.llr(0.0, 5.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn parameter_overrides_are_applied() {
+        let config = super::parse_config("RFP_MARGIN=80, LMP_DEPTH=7").unwrap();
+        assert_eq!(config.rfp_margin, 80);
+        assert!(super::parse_config("NOT_A_PARAMETER=3").is_err());
+        assert!(super::parse_config("RFP_MARGIN").is_err());
+    }
+}
@@ -0,0 +1,171 @@
+// A runner for EPD test suites, such as WAC, STS, or the Arasan suite.
+// Each record gives a position and either the best move(s) ("bm") or move(s) to avoid ("am").
+// The engine searches every position for a fixed time, and a position counts as solved if the
+// move played is one of the best moves (and none of the moves to avoid). The solution time is
+// when the engine settled on the move it finally played. STS records also carry a "c0" comment
+// that awards points to several moves, which gives the STS score.
+
+use std::{path::Path, sync::Mutex, time::Duration};
+
+use crate::{
+    board::Board,
+    engine::{Engine, Limit},
+};
+
+/// A single test position.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Record {
+    id: String,
+    fen: String,
+    /// Best moves, in UCI notation.
+    best: Vec<String>,
+    /// Moves to avoid, in UCI notation.
+    avoid: Vec<String>,
+    /// STS points for moves, in UCI notation.
+    points: Vec<(String, u32)>,
+}
+
+/// Options for a test-suite run.
+pub struct TestsuiteOptions<'a> {
+    pub path: &'a Path,
+    pub movetime: Duration,
+    pub threads: usize,
+    pub hash_mb: usize,
+}
+
+fn parse_record(line: &str, number: usize) -> Result<Record, String> {
+    let fields = line.split_whitespace().take(4).collect::<Vec<_>>();
+    if fields.len() < 4 {
+        return Err(format!("record {number} has too few fields: \"{line}\""));
+    }
+    let fen = format!("{} 0 1", fields.join(" "));
+    let board = Board::from_fen(&fen).map_err(|e| format!("record {number} has a bad position: {e}"))?;
+    // skip past the four position fields to the opcodes.
+    let mut rest = line.trim_start();
+    for _ in 0..4 {
+        rest = rest.trim_start();
+        rest = &rest[rest.find(char::is_whitespace).unwrap_or(rest.len())..];
+    }
+    let to_uci = |san: &str| {
+        board.parse_move(san).map(|m| m.to_string()).map_err(|e| format!("record {number}: bad move \"{san}\": {e}"))
+    };
+    let mut record = Record { id: format!("#{number}"), fen, best: Vec::new(), avoid: Vec::new(), points: Vec::new() };
+    for op in rest.split(';').map(str::trim).filter(|op| !op.is_empty()) {
+        let (opcode, operand) = op.split_once(char::is_whitespace).unwrap_or((op, ""));
+        let operand = operand.trim().trim_matches('"');
+        match opcode {
+            "id" => record.id = operand.to_string(),
+            "bm" => record.best = operand.split_whitespace().map(to_uci).collect::<Result<_, _>>()?,
+            "am" => record.avoid = operand.split_whitespace().map(to_uci).collect::<Result<_, _>>()?,
+            // STS: c0 "f5=10, Be5+=2, Bf2=3, Bg4=2"
+            "c0" if operand.contains('=') => {
+                for award in operand.split(',') {
+                    let Some((san, points)) = award.trim().split_once('=') else {
+                        continue;
+                    };
+                    let points = points.parse().map_err(|e| format!("record {number}: bad points \"{award}\": {e}"))?;
+                    record.points.push((to_uci(san)?, points));
+                }
+            }
+            _ => {}
+        }
+    }
+    if record.best.is_empty() && record.avoid.is_empty() {
+        return Err(format!("record {number} ({}) has neither \"bm\" nor \"am\"", record.id));
+    }
+    Ok(record)
+}
+
+impl Record {
+    fn is_solved_by(&self, m: &str) -> bool {
+        (self.best.is_empty() || self.best.iter().any(|b| b == m)) && !self.avoid.iter().any(|a| a == m)
+    }
+}
+
+pub fn run(options: &TestsuiteOptions) -> Result<(), String> {
+    #![allow(clippy::cast_precision_loss)]
+    let text =
+        std::fs::read_to_string(options.path).map_err(|e| format!("failed to read {}: {e}", options.path.display()))?;
+    let records = text
+        .lines()
+        .map(str::trim)
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(i, line)| parse_record(line, i + 1))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut engine = Engine::new();
+    engine.set_hash_size(options.hash_mb);
+    engine.set_threads(options.threads);
+
+    let mut solved = 0;
+    let mut solution_time = Duration::ZERO;
+    let mut sts_score = 0;
+    let mut sts_max = 0;
+    for record in &records {
+        engine.new_game();
+        engine.set_position(Some(&record.fen), &[]).map_err(|e| e.to_string())?;
+        // the time at which the engine switched to its current best move, if that move solves the position.
+        let solved_at = Mutex::new(None);
+        let result = engine.search(Limit::Time(options.movetime), |update| {
+            let mut solved_at = solved_at.lock().unwrap();
+            match update.pv.first() {
+                Some(m) if record.is_solved_by(m) => *solved_at = solved_at.or(Some(update.elapsed)),
+                _ => *solved_at = None,
+            }
+        });
+        let played = result.best_move.unwrap_or_else(|| "(none)".to_string());
+        let success = record.is_solved_by(&played);
+        if success {
+            solved += 1;
+            solution_time += solved_at.into_inner().unwrap().unwrap_or(options.movetime);
+        }
+        let points = record.points.iter().find(|(m, _)| *m == played).map_or(0, |&(_, p)| p);
+        sts_score += points;
+        sts_max += record.points.iter().map(|&(_, p)| p).max().unwrap_or(0);
+        let expected = if record.best.is_empty() {
+            format!("am {}", record.avoid.join(" "))
+        } else {
+            format!("bm {}", record.best.join(" "))
+        };
+        println!("{:<16} {} {played:<6} ({expected})", record.id, if success { "solved" } else { "failed" });
+    }
+
+    println!("solved {solved}/{} positions", records.len());
+    if solved > 0 {
+        println!("average solution time {:.3}s", solution_time.as_secs_f64() / f64::from(solved));
+    }
+    if sts_max > 0 {
+        println!("STS score {sts_score}/{sts_max}");
+    }
+    Ok(())
+}
+
+mod tests {
+    #[test]
+    fn parse_epd_records() {
+        use super::parse_record;
+
+        let wac =
+            parse_record("2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id \"WAC.001\";", 1).unwrap();
+        assert_eq!(wac.id, "WAC.001");
+        assert_eq!(wac.best, ["g3g6"]);
+        assert!(wac.is_solved_by("g3g6"));
+        assert!(!wac.is_solved_by("f1e1"));
+
+        let sts = parse_record(
+            "1kr5/3n4/q3p2p/p2n2p1/PppB1P2/5BP1/1P2Q2P/3R2K1 w - - bm f5; id \"STS(v1.0) Undermine.001\"; c0 \"f5=10, Be5+=2, Bf2=3, Bg4=2\";",
+            2,
+        )
+        .unwrap();
+        assert_eq!(sts.points.len(), 4);
+        assert_eq!(sts.points[0], ("f4f5".to_string(), 10));
+
+        let avoid = parse_record("4k3/8/8/8/8/8/4P3/4K3 w - - am Kd1;", 3).unwrap();
+        assert_eq!(avoid.id, "#3");
+        assert!(avoid.is_solved_by("e2e4"));
+        assert!(!avoid.is_solved_by("e1d1"));
+
+        assert!(parse_record("4k3/8/8/8/8/8/4P3/4K3 w - - id \"nothing\";", 4).is_err());
+    }
+}
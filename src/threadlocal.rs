@@ -1,7 +1,8 @@
 use crate::{
     board::Board,
     chessmove::Move,
-    historytable::{CaptureHistoryTable, DoubleHistoryTable, MoveTable, ThreatsHistoryTable},
+    evalcache::EvalCache,
+    historytable::{CaptureHistoryTable, CorrectionHistoryTable, DoubleHistoryTable, MoveTable, ThreatsHistoryTable},
     nnue,
     piece::Colour,
     search::pv::PVariation,
@@ -19,16 +20,24 @@ pub struct ThreadData<'a> {
     // singular verification will try to access the next ply
     // in an edge case.
     pub double_extensions: [i32; MAX_PLY + 1],
+    // number of plies that the current line has been extended by,
+    // padded in the same way as the double-extension array.
+    pub extensions: [i32; MAX_PLY + 1],
     pub checks: [bool; MAX_PLY],
     pub banned_nmp: u8,
     pub multi_pv_excluded: Vec<Move>,
+    /// The latest score the main search gave each root move. Moves that never raised alpha
+    /// only have the upper bound from their null-window search.
+    pub root_scores: Vec<(Move, i32)>,
     pub nnue: Box<nnue::network::NNUEState>,
+    pub eval_cache: EvalCache,
 
     pub main_history: ThreatsHistoryTable,
     pub tactical_history: Box<CaptureHistoryTable>,
     pub cont_hists: [Box<DoubleHistoryTable>; 2],
     pub killer_move_table: [[Move; 2]; MAX_PLY + 1],
     pub counter_move_table: MoveTable,
+    pub correction_history: Box<CorrectionHistoryTable>,
 
     pub thread_id: usize,
 
@@ -37,6 +46,8 @@ pub struct ThreadData<'a> {
     pub depth: usize,
 
     pub stm_at_root: Colour,
+    /// The contempt for draws in this search, which is zero when analysing.
+    pub contempt: i32,
 
     pub tt: TTView<'a>,
 }
@@ -51,20 +62,25 @@ impl<'a> ThreadData<'a> {
             excluded: [Move::NULL; MAX_PLY],
             best_moves: [Move::NULL; MAX_PLY],
             double_extensions: [0; MAX_PLY + 1],
+            extensions: [0; MAX_PLY + 1],
             checks: [false; MAX_PLY],
             banned_nmp: 0,
             multi_pv_excluded: Vec::new(),
+            root_scores: Vec::new(),
             nnue: nnue::network::NNUEState::new(board),
+            eval_cache: EvalCache::new(),
             main_history: ThreatsHistoryTable::new(),
             tactical_history: CaptureHistoryTable::boxed(),
             cont_hists: [(); 2].map(|()| DoubleHistoryTable::boxed()),
             killer_move_table: [[Move::NULL; 2]; MAX_PLY + 1],
             counter_move_table: MoveTable::new(),
+            correction_history: CorrectionHistoryTable::boxed(),
             thread_id,
             pvs: vec![PVariation::default(); MAX_PLY],
             completed: 0,
             depth: 0,
             stm_at_root: board.turn(),
+            contempt: 0,
             tt,
         };
 
@@ -91,6 +107,8 @@ impl<'a> ThreadData<'a> {
         self.cont_hists.iter_mut().for_each(|h| h.clear());
         self.killer_move_table.fill([Move::NULL; 2]);
         self.counter_move_table.clear();
+        self.correction_history.clear();
+        self.eval_cache.clear();
         self.depth = 0;
         self.completed = 0;
         self.pvs.fill(PVariation::default());
@@ -105,6 +123,7 @@ impl<'a> ThreadData<'a> {
         self.depth = 0;
         self.completed = 0;
         self.pvs.fill(PVariation::default());
+        self.root_scores.clear();
         self.nnue.reinit_from(board);
         self.stm_at_root = board.turn();
     }
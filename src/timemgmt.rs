@@ -21,6 +21,8 @@ pub const OPTIMAL_WINDOW_FRAC: u32 = 60;
 pub const INCREMENT_FRAC: u32 = 75;
 pub const NODE_TM_SUBTREE_MULTIPLIER: u32 = 135;
 pub const FAIL_LOW_TM_BONUS: u32 = 250;
+pub const SCORE_DROP_TM_BONUS: u32 = 400;
+pub const RECAPTURE_TM_FRAC: u32 = 400;
 
 #[derive(PartialEq, Eq, Clone, Copy, Debug)]
 pub enum ForcedMoveType {
@@ -125,9 +127,13 @@ pub struct TimeManager {
     /// The nature of the forced move (if any)
     found_forced_move: ForcedMoveType,
     /// The last set of multiplicative factors.
-    last_factors: [f64; 2],
+    last_factors: [f64; 3],
     /// Fraction of nodes that were underneath the best move.
     best_move_nodes_fraction: Option<f64>,
+    /// Whether the best move from the last iteration is a recapture that doesn't lose material.
+    best_move_recaptures: bool,
+    /// A node count at which the search stops, whatever the limit.
+    node_cap: Option<u64>,
 }
 
 impl Default for TimeManager {
@@ -144,8 +150,10 @@ impl Default for TimeManager {
             failed_low: 0,
             mate_counter: 0,
             found_forced_move: ForcedMoveType::None,
-            last_factors: [1.0, 1.0],
+            last_factors: [1.0, 1.0, 1.0],
             best_move_nodes_fraction: None,
+            best_move_recaptures: false,
+            node_cap: None,
         }
     }
 }
@@ -155,6 +163,11 @@ impl TimeManager {
         self.limit = limit;
     }
 
+    /// Caps the number of nodes searched, on top of the limit.
+    pub const fn set_node_cap(&mut self, node_cap: Option<u64>) {
+        self.node_cap = node_cap;
+    }
+
     pub fn start(&mut self) {
         self.start_time = Instant::now();
     }
@@ -178,8 +191,9 @@ impl TimeManager {
         self.failed_low = 0;
         self.mate_counter = 0;
         self.found_forced_move = ForcedMoveType::None;
-        self.last_factors = [1.0, 1.0];
+        self.last_factors = [1.0, 1.0, 1.0];
         self.best_move_nodes_fraction = None;
+        self.best_move_recaptures = false;
 
         if let SearchLimit::Dynamic { our_clock, our_inc, moves_to_go, .. } = self.limit {
             let (opt_time, hard_time, max_time) =
@@ -187,10 +201,19 @@ impl TimeManager {
             self.max_time = Duration::from_millis(max_time);
             self.hard_time = Duration::from_millis(hard_time);
             self.opt_time = Duration::from_millis(opt_time);
+            debug_info!(
+                "time allocation: optimal {opt_time}ms, hard {hard_time}ms, maximum {max_time}ms \
+                 (clock {our_clock}ms, increment {our_inc}ms, moves to go {})",
+                moves_to_go.map_or_else(|| "unknown".to_string(), |n| n.to_string())
+            );
         }
     }
 
     pub fn check_up(&mut self, stopped: &AtomicBool, nodes_so_far: u64) -> bool {
+        if self.node_cap.is_some_and(|cap| nodes_so_far >= cap) {
+            stopped.store(true, Ordering::SeqCst);
+            return true;
+        }
         match self.limit {
             SearchLimit::Depth(_) | SearchLimit::Mate { .. } | SearchLimit::Infinite => stopped.load(Ordering::SeqCst),
             SearchLimit::Nodes(nodes) => {
@@ -297,10 +320,11 @@ impl TimeManager {
             self.opt_time = self.opt_time * conf.strong_forced_tm_frac / 1000;
             self.found_forced_move = ForcedMoveType::Strong;
         }
+        debug_info!("best move is forced at depth {depth}, cutting optimal time to {}ms", self.opt_time.as_millis());
     }
 
     pub fn check_for_forced_move(&self, depth: Depth) -> Option<i32> {
-        if self.found_forced_move == ForcedMoveType::None && self.is_dynamic() {
+        if self.found_forced_move == ForcedMoveType::None && self.is_dynamic() && !self.is_obvious_recapture() {
             if depth >= Self::SLIGHTLY_FORCED {
                 Some(170)
             } else if depth >= Self::VERY_FORCED {
@@ -314,10 +338,34 @@ impl TimeManager {
     }
 
     pub fn notify_one_legal_move(&mut self) {
+        debug_info!("only one legal move, moving immediately");
         self.opt_time = Duration::from_millis(0);
         self.found_forced_move = ForcedMoveType::OneLegal;
     }
 
+    /// Tells the time manager whether the best move from the iteration about to be reported
+    /// is a recapture that doesn't lose material.
+    pub const fn notify_best_move_recaptures(&mut self, recaptures: bool) {
+        self.best_move_recaptures = recaptures;
+    }
+
+    /// A recapture that has stayed the best move for a few iterations is almost
+    /// always forced, and the forced-move verification search is too expensive
+    /// to be worth running in such positions, so we skip it and shrink the
+    /// time windows instead.
+    const fn is_obvious_recapture(&self) -> bool {
+        const OBVIOUS_RECAPTURE_STABILITY: usize = 4;
+        self.best_move_recaptures && self.stability >= OBVIOUS_RECAPTURE_STABILITY
+    }
+
+    fn recapture_multiplier(&self, conf: &Config) -> f64 {
+        if self.is_obvious_recapture() {
+            f64::from(conf.recapture_tm_frac) / 1000.0
+        } else {
+            1.0
+        }
+    }
+
     fn best_move_stability_multiplier(stability: usize) -> f64 {
         // approach to this is adapted from Stash.
         const VALUES: [f64; 5] = [2.50, 1.20, 0.90, 0.80, 0.75];
@@ -329,6 +377,17 @@ impl TimeManager {
         VALUES[stability]
     }
 
+    fn score_trend_multiplier(prev_score: i32, score: i32, conf: &Config) -> f64 {
+        // spend more time when the score is falling, as the position is probably
+        // more difficult than it first appeared. rising scores don't save time,
+        // as the stability multiplier already handles easy positions.
+        if is_mate_score(prev_score) || is_mate_score(score) {
+            return 1.0;
+        }
+        let drop = (prev_score - score).clamp(0, 100);
+        f64::from(drop).mul_add(f64::from(conf.score_drop_tm_bonus) / 100_000.0, 1.0)
+    }
+
     fn best_move_subtree_size_multiplier(nodes_fraction: f64, conf: &Config) -> f64 {
         (1.5 - nodes_fraction) * f64::from(conf.node_tm_subtree_multiplier) / 100.0
     }
@@ -356,15 +415,26 @@ impl TimeManager {
             self.best_move_nodes_fraction = best_move_nodes_fraction;
 
             let stability_multiplier = Self::best_move_stability_multiplier(self.stability);
+            // the first iteration has nothing to compare against
+            let score_trend_multiplier = if self.prev_move == Move::NULL {
+                1.0
+            } else {
+                Self::score_trend_multiplier(self.prev_score, eval, conf)
+            };
             // retain time added by windows that failed low
             let failed_low_multiplier =
                 f64::from(self.failed_low).mul_add(f64::from(conf.fail_low_tm_bonus) / 1000.0, 1.0);
             let forced_move_multiplier = self.found_forced_move.tm_multiplier(conf);
+            let recapture_multiplier = self.recapture_multiplier(conf);
             let subtree_size_multiplier =
                 self.best_move_nodes_fraction.map_or(1.0, |frac| Self::best_move_subtree_size_multiplier(frac, conf));
 
-            let multiplier =
-                stability_multiplier * failed_low_multiplier * forced_move_multiplier * subtree_size_multiplier;
+            let multiplier = stability_multiplier
+                * score_trend_multiplier
+                * failed_low_multiplier
+                * forced_move_multiplier
+                * recapture_multiplier
+                * subtree_size_multiplier;
 
             let hard_time = Duration::from_secs_f64(hard_time.as_secs_f64() * multiplier);
             let opt_time = Duration::from_secs_f64(opt_time.as_secs_f64() * multiplier);
@@ -372,7 +442,7 @@ impl TimeManager {
             self.hard_time = hard_time.min(max_time);
             self.opt_time = opt_time.min(max_time);
 
-            self.last_factors = [stability_multiplier, failed_low_multiplier];
+            self.last_factors = [stability_multiplier, failed_low_multiplier, score_trend_multiplier];
         }
 
         self.prev_move = best_move;
@@ -394,15 +464,21 @@ impl TimeManager {
             let opt_time = Duration::from_millis(opt_time);
 
             let stability_multiplier = self.last_factors[0];
+            let score_trend_multiplier = self.last_factors[2];
             // calculate the failed low multiplier
             let failed_low_multiplier =
                 f64::from(self.failed_low).mul_add(f64::from(conf.fail_low_tm_bonus) / 1000.0, 1.0);
             let forced_move_multiplier = self.found_forced_move.tm_multiplier(conf);
+            let recapture_multiplier = self.recapture_multiplier(conf);
             let subtree_size_multiplier =
                 self.best_move_nodes_fraction.map_or(1.0, |frac| Self::best_move_subtree_size_multiplier(frac, conf));
 
-            let multiplier =
-                stability_multiplier * failed_low_multiplier * forced_move_multiplier * subtree_size_multiplier;
+            let multiplier = stability_multiplier
+                * score_trend_multiplier
+                * failed_low_multiplier
+                * forced_move_multiplier
+                * recapture_multiplier
+                * subtree_size_multiplier;
 
             let hard_time = Duration::from_secs_f64(hard_time.as_secs_f64() * multiplier);
             let opt_time = Duration::from_secs_f64(opt_time.as_secs_f64() * multiplier);
@@ -1,3 +1,5 @@
+mod pages;
+
 use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
 
 use crate::{
@@ -10,6 +12,9 @@ use crate::{
     },
 };
 
+pub use self::pages::PageKind;
+use self::pages::Table;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
 pub enum Bound {
@@ -32,6 +37,13 @@ macro_rules! impl_from_bound {
 impl_from_bound!(u8);
 impl_from_bound!(i32);
 
+/// A cheap bijective mix of the second word of an entry, which is xored into the first word
+/// so that torn entries can be detected. Maps zero to zero, so a cleared slot reads as `TTEntry::NULL`.
+const fn scramble(data: u64) -> u64 {
+    let data = (data ^ (data >> 32)).wrapping_mul(0xD6E8_FEB8_6659_FD93);
+    data ^ (data >> 32)
+}
+
 fn divide_into_chunks<T>(slice: &[T], n_chunks: usize) -> impl Iterator<Item = &[T]> {
     let chunk_size = slice.len() / n_chunks + 1; // +1 to avoid 0
     slice.chunks(chunk_size)
@@ -45,12 +57,16 @@ pub struct AgeAndFlag {
 impl AgeAndFlag {
     const NULL: Self = Self { data: 0 };
 
-    const fn new(age: u8, flag: Bound) -> Self {
-        Self { data: (age << 2) | flag as u8 }
+    const fn new(age: u8, flag: Bound, pv: bool) -> Self {
+        Self { data: (age << 3) | (pv as u8) << 2 | flag as u8 }
     }
 
     const fn age(self) -> u8 {
-        self.data >> 2
+        self.data >> 3
+    }
+
+    const fn pv(self) -> bool {
+        self.data & 0b100 != 0
     }
 
     fn flag(self) -> Bound {
@@ -70,10 +86,10 @@ pub struct TTEntry {
     pub key: u16,                   // 16 bits
     pub m: Move,                    // 16 bits
     pub score: i16,                 // 16 bits
-    pub depth: CompactDepthStorage, // 8 bits, wrapper around a u8
-    pub age_and_flag: AgeAndFlag,   // 6 + 2 bits, wrapper around a u8
+    pub depth: CompactDepthStorage, // 16 bits, wrapper around a u16
     pub evaluation: i16,            // 16 bits
-    pub dummy: [u8; 6],             // 48 bits
+    pub age_and_flag: AgeAndFlag,   // 5 + 1 + 2 bits, wrapper around a u8
+    pub dummy: [u8; 5],             // 40 bits
 }
 
 const _TT_ENTRIES_ARE_ONE_WORD: () = assert!(std::mem::size_of::<TTEntry>() == 16, "TT entry is not one word");
@@ -84,9 +100,9 @@ impl TTEntry {
         m: Move::NULL,
         score: 0,
         depth: CompactDepthStorage::NULL,
-        age_and_flag: AgeAndFlag::NULL,
         evaluation: 0,
-        dummy: [0; 6],
+        age_and_flag: AgeAndFlag::NULL,
+        dummy: [0; 5],
     };
 }
 
@@ -106,10 +122,12 @@ impl From<TTEntry> for [u64; 2] {
 }
 
 const TT_ENTRY_SIZE: usize = std::mem::size_of::<TTEntry>();
+/// The number of slots looked at to estimate how full the table is.
+const HASHFULL_SAMPLES: usize = 1000;
 
 #[derive(Debug)]
 pub struct TT {
-    table: Vec<[AtomicU64; 2]>,
+    table: Table,
     age: AtomicU8,
 }
 
@@ -126,28 +144,26 @@ pub struct TTHit {
     pub bound: Bound,
     pub value: i32,
     pub eval: i32,
+    pub was_pv: bool,
 }
 
 impl TT {
     const NULL_VALUE: u64 = 0;
 
     pub const fn new() -> Self {
-        Self { table: Vec::new(), age: AtomicU8::new(0) }
+        Self { table: Table::empty(), age: AtomicU8::new(0) }
     }
 
     pub fn resize(&mut self, bytes: usize) {
         let new_len = bytes / TT_ENTRY_SIZE;
-        // dealloc the old table:
-        self.table = Vec::new();
-        // construct a new vec:
-        unsafe {
-            let layout = std::alloc::Layout::array::<[AtomicU64; 2]>(new_len).unwrap();
-            let ptr = std::alloc::alloc_zeroed(layout);
-            if ptr.is_null() {
-                std::alloc::handle_alloc_error(layout);
-            }
-            self.table = Vec::from_raw_parts(ptr.cast(), new_len, new_len);
-        }
+        // dealloc the old table before allocating the new one:
+        self.table = Table::empty();
+        self.table = Table::allocate(new_len);
+    }
+
+    /// The kind of memory pages backing the table.
+    pub const fn pages(&self) -> PageKind {
+        self.table.pages()
     }
 
     pub fn clear(&self, threads: usize) {
@@ -178,7 +194,7 @@ impl TT {
     }
 
     pub fn increase_age(&self) {
-        let new_age = (self.age.load(Ordering::Relaxed) + 1) & 0b11_1111; // keep age in range [0, 63]
+        let new_age = (self.age.load(Ordering::Relaxed) + 1) & 0b1_1111; // keep age in range [0, 31]
         self.age.store(new_age, Ordering::Relaxed);
     }
 
@@ -197,7 +213,17 @@ impl<'a> TTView<'a> {
     }
 
     #[allow(clippy::too_many_arguments)]
-    pub fn store(&self, key: u64, ply: usize, mut best_move: Move, score: i32, eval: i32, flag: Bound, depth: Depth) {
+    pub fn store(
+        &self,
+        key: u64,
+        ply: usize,
+        mut best_move: Move,
+        score: i32,
+        eval: i32,
+        flag: Bound,
+        depth: Depth,
+        pv: bool,
+    ) {
         debug_assert!((ZERO_PLY..=MAX_DEPTH).contains(&depth), "depth: {depth}");
         debug_assert!(score >= -INFINITY);
         debug_assert!((0..=MAX_DEPTH.ply_to_horizon()).contains(&ply));
@@ -207,8 +233,7 @@ impl<'a> TTView<'a> {
         // create a small key from the full key:
         let key = TT::pack_key(key);
         // load the entry:
-        let parts = [self.table[index][0].load(Ordering::Relaxed), self.table[index][1].load(Ordering::Relaxed)];
-        let entry: TTEntry = parts.into();
+        let entry = self.load(index);
 
         if best_move.is_null() && entry.key == key {
             // if we don't have a best move, and the entry is for the same position,
@@ -225,7 +250,7 @@ impl<'a> TTView<'a> {
         let record_flag_bonus = i32::from(entry.age_and_flag.flag());
 
         // preferentially overwrite entries that are from searches on previous positions in the game.
-        let age_differential = (i32::from(self.age) + 64 - i32::from(entry.age_and_flag.age())) & 0b11_1111;
+        let age_differential = (i32::from(self.age) + 32 - i32::from(entry.age_and_flag.age())) & 0b1_1111;
 
         // we use quadratic scaling of the age to allow entries that aren't too old to be kept,
         // but to ensure that *really* old entries are overwritten even if they are of high depth.
@@ -241,22 +266,20 @@ impl<'a> TTView<'a> {
             || flag == Bound::Exact && entry.age_and_flag.flag() != Bound::Exact
             || insert_priority * 3 >= record_prority * 2
         {
-            let write: [u64; 2] = TTEntry {
+            let write = TTEntry {
                 key,
                 m: best_move,
                 score: score.try_into().expect(
                     "attempted to store a score with value outwith [i16::MIN, i16::MAX] in the transposition table",
                 ),
                 depth: depth.try_into().unwrap(),
-                age_and_flag: AgeAndFlag::new(self.age, flag),
+                age_and_flag: AgeAndFlag::new(self.age, flag, pv),
                 evaluation: eval.try_into().expect(
                     "attempted to store an eval with value outwith [i16::MIN, i16::MAX] in the transposition table",
                 ),
                 dummy: Default::default(),
-            }
-            .into();
-            self.table[index][0].store(write[0], Ordering::Relaxed);
-            self.table[index][1].store(write[1], Ordering::Relaxed);
+            };
+            self.write(index, write);
         }
     }
 
@@ -265,8 +288,7 @@ impl<'a> TTView<'a> {
         let key = TT::pack_key(key);
 
         // load the entry:
-        let parts = [self.table[index][0].load(Ordering::Relaxed), self.table[index][1].load(Ordering::Relaxed)];
-        let entry: TTEntry = parts.into();
+        let entry = self.load(index);
 
         if entry.key != key {
             return None;
@@ -282,7 +304,34 @@ impl<'a> TTView<'a> {
         // because we need to do mate score preprocessing.
         let tt_value = reconstruct_gt_truth_score(entry.score.into(), ply);
 
-        Some(TTHit { mov: tt_move, depth: tt_depth, bound: tt_bound, value: tt_value, eval: entry.evaluation.into() })
+        Some(TTHit {
+            mov: tt_move,
+            depth: tt_depth,
+            bound: tt_bound,
+            value: tt_value,
+            eval: entry.evaluation.into(),
+            was_pv: entry.age_and_flag.pv(),
+        })
+    }
+
+    /// Loads the entry at `index`.
+    /// Entries are written as two separate words, so another thread can write to the same slot
+    /// between our two loads, or between its own two stores, leaving a mix of two entries in the slot.
+    /// To detect this, the first word is stored xored with a hash of the second, so if the
+    /// words come from different entries, the key read back is garbage and the probe misses.
+    fn load(&self, index: usize) -> TTEntry {
+        let slot = &self.table[index];
+        let data = slot[1].load(Ordering::Relaxed);
+        let check = slot[0].load(Ordering::Relaxed);
+        [check ^ scramble(data), data].into()
+    }
+
+    /// Stores an entry at `index`. See `load` for the encoding.
+    fn write(&self, index: usize, entry: TTEntry) {
+        let [head, data]: [u64; 2] = entry.into();
+        let slot = &self.table[index];
+        slot[0].store(head ^ scramble(data), Ordering::Relaxed);
+        slot[1].store(data, Ordering::Relaxed);
     }
 
     pub fn prefetch(&self, key: u64) {
@@ -307,8 +356,19 @@ impl<'a> TTView<'a> {
         }
     }
 
+    /// The permille of the table that holds entries from the current search, estimated from
+    /// slots spread evenly across the whole table, as the start of the table isn't representative.
     pub fn hashfull(&self) -> usize {
-        self.table.iter().take(1000).filter(|e| e[0].load(Ordering::Relaxed) != 0).count()
+        let len = self.table.len();
+        let samples = HASHFULL_SAMPLES.min(len);
+        if samples == 0 {
+            return 0;
+        }
+        let used = (0..samples)
+            .map(|i| self.load(i * len / samples))
+            .filter(|entry| *entry != TTEntry::NULL && entry.age_and_flag.age() == self.age)
+            .count();
+        used * 1000 / samples
     }
 }
 
@@ -345,15 +405,51 @@ mod tests {
             m: Move::new(Square::A1, Square::A2),
             score: 0,
             depth: ZERO_PLY.try_into().unwrap(),
-            age_and_flag: AgeAndFlag::new(63, Bound::Exact),
+            age_and_flag: AgeAndFlag::new(31, Bound::Exact, true),
             evaluation: 1337,
-            dummy: [0; 6],
+            dummy: [0; 5],
         };
         let packed: [u64; 2] = entry.into();
         let unpacked: TTEntry = packed.into();
         assert_eq!(entry, unpacked);
     }
 
+    #[test]
+    fn torn_entries_are_rejected() {
+        let mut tt = TT::new();
+        tt.resize(1024 * TT_ENTRY_SIZE);
+        let view = tt.view();
+        let key = 0xDEAD_BEEF_0123_4567;
+        view.store(key, 0, Move::new(Square::E2, Square::E4), 25, 10, Bound::Exact, Depth::new(5), true);
+        let stored = view.probe(key, 0).unwrap();
+        assert_eq!(stored.mov, Move::new(Square::E2, Square::E4));
+        assert_eq!(stored.eval, 10);
+        assert!(stored.was_pv);
+
+        // simulate another thread's write landing between the two halves of ours:
+        let index = view.wrap_key(key);
+        let [_, other_data]: [u64; 2] = TTEntry { evaluation: -300, ..TTEntry::NULL }.into();
+        tt.table[index][1].store(other_data, Ordering::Relaxed);
+        assert!(tt.view().probe(key, 0).is_none());
+    }
+
+    #[test]
+    fn hashfull_samples_the_whole_table() {
+        let mut tt = TT::new();
+        tt.resize(4096 * TT_ENTRY_SIZE);
+        let view = tt.view();
+        assert_eq!(view.hashfull(), 0);
+        // fill the second half of the table, which the first thousand slots wouldn't see:
+        for i in 0..2048 {
+            let key = 1 << 63 | i << 52;
+            view.store(key, 0, Move::new(Square::E2, Square::E4), 0, 0, Bound::Exact, Depth::new(1), false);
+        }
+        assert_eq!(view.hashfull(), 500);
+        // entries from earlier searches don't count:
+        tt.increase_age();
+        assert_eq!(tt.view().hashfull(), 0);
+    }
+
     #[test]
     fn null_tt_entry_is_zero() {
         let entry = TTEntry::NULL;
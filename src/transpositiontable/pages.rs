@@ -0,0 +1,131 @@
+// Memory for the transposition table. A big table is touched at random, so with ordinary 4 KiB pages
+// nearly every probe misses the TLB. On Linux, the table is instead backed by huge pages where the OS
+// allows it: we align the allocation to 2 MiB and ask for transparent huge pages with madvise. If that
+// fails, or on other platforms, the table uses ordinary pages.
+
+use std::{
+    alloc::{self, Layout},
+    fmt::{self, Display},
+    ops::Deref,
+    ptr::NonNull,
+    sync::atomic::AtomicU64,
+};
+
+type Slot = [AtomicU64; 2];
+
+/// The size of a huge page on x86-64 and most aarch64 Linux configurations.
+const HUGE_PAGE_SIZE: usize = 2 * 1024 * 1024;
+
+/// The kind of pages backing the table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageKind {
+    Normal,
+    /// Transparent huge pages, requested with madvise.
+    TransparentHuge,
+}
+
+impl Display for PageKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Normal => write!(f, "normal pages"),
+            Self::TransparentHuge => write!(f, "transparent huge pages"),
+        }
+    }
+}
+
+/// A zeroed, fixed-size array of table slots.
+#[derive(Debug)]
+pub struct Table {
+    ptr: NonNull<Slot>,
+    len: usize,
+    /// The start of the underlying allocation, which `ptr` may be offset into for alignment.
+    base: NonNull<u8>,
+    layout: Layout,
+    pages: PageKind,
+}
+
+// SAFETY: the table owns its memory, and the slots are atomics.
+unsafe impl Send for Table {}
+unsafe impl Sync for Table {}
+
+impl Table {
+    pub const fn empty() -> Self {
+        Self {
+            ptr: NonNull::dangling(),
+            len: 0,
+            base: NonNull::dangling(),
+            layout: Layout::new::<Slot>(),
+            pages: PageKind::Normal,
+        }
+    }
+
+    pub fn allocate(len: usize) -> Self {
+        if len == 0 {
+            return Self::empty();
+        }
+        let bytes = len * std::mem::size_of::<Slot>();
+
+        // the allocator can only hand out zeroed memory cheaply at ordinary alignments: large
+        // zeroed allocations then come straight from the OS, and pages are only faulted in
+        // (and zeroed by the kernel, on whichever thread first touches them) when used.
+        // clearing the table ourselves would walk the whole of it on one thread, so instead
+        // we over-allocate by a huge page and align the table within the allocation.
+        let padding = if bytes >= HUGE_PAGE_SIZE { HUGE_PAGE_SIZE } else { 0 };
+        let layout = Layout::from_size_align(bytes + padding, std::mem::align_of::<Slot>()).unwrap();
+        // SAFETY: the layout has a non-zero size.
+        let Some(base) = NonNull::new(unsafe { alloc::alloc_zeroed(layout) }) else {
+            alloc::handle_alloc_error(layout);
+        };
+        let offset = if padding == 0 { 0 } else { base.as_ptr().align_offset(HUGE_PAGE_SIZE) };
+        // SAFETY: the offset is less than the padding, so the table lies within the allocation.
+        let ptr = unsafe { base.add(offset) };
+        // none of the table's pages have been touched yet, so they can still be backed by huge pages.
+        let pages = if padding != 0 && advise_huge_pages(ptr, bytes) {
+            PageKind::TransparentHuge
+        } else {
+            PageKind::Normal
+        };
+        Self { ptr: ptr.cast(), len, base, layout, pages }
+    }
+
+    pub const fn pages(&self) -> PageKind {
+        self.pages
+    }
+}
+
+impl Deref for Table {
+    type Target = [Slot];
+
+    fn deref(&self) -> &[Slot] {
+        // SAFETY: the pointer is either dangling with a length of zero, or owns `len` zero-initialised slots.
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl Drop for Table {
+    fn drop(&mut self) {
+        if self.len == 0 {
+            return;
+        }
+        // SAFETY: the memory came from the global allocator with this layout.
+        unsafe { alloc::dealloc(self.base.as_ptr(), self.layout) };
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn advise_huge_pages(ptr: NonNull<u8>, bytes: usize) -> bool {
+    const MADV_HUGEPAGE: i32 = 14;
+    extern "C" {
+        fn madvise(addr: *mut std::ffi::c_void, len: usize, advice: i32) -> i32;
+    }
+    // with THP switched off entirely, madvise still succeeds but does nothing.
+    let disabled = std::fs::read_to_string("/sys/kernel/mm/transparent_hugepage/enabled")
+        .map_or(true, |setting| setting.contains("[never]"));
+    // SAFETY: the range is a single live allocation.
+    !disabled && unsafe { madvise(ptr.as_ptr().cast(), bytes, MADV_HUGEPAGE) } == 0
+}
+
+#[cfg(not(target_os = "linux"))]
+const fn advise_huge_pages(_ptr: NonNull<u8>, _bytes: usize) -> bool {
+    false
+}
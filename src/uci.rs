@@ -1,9 +1,12 @@
 #![deny(clippy::panic, clippy::unwrap_used, clippy::todo, clippy::unimplemented)]
 
+mod config;
+
 use std::{
     fmt::{self, Display},
-    io::Write,
+    io::{self, Write},
     num::{ParseFloatError, ParseIntError},
+    path::Path,
     str::{FromStr, ParseBoolError},
     sync::{
         atomic::{self, AtomicBool, AtomicI32, AtomicU64, AtomicU8, AtomicUsize, Ordering},
@@ -16,41 +19,44 @@ use crate::{
     bench::BENCH_POSITIONS,
     board::{
         evaluation::{is_game_theoretic_score, is_mate_score, MATE_SCORE, TB_WIN_SCORE},
-        movegen::MoveList,
         Board,
     },
     errors::{FenParseError, MoveParseError},
-    nnue, perft,
-    piece::Colour,
-    search::{parameters::Config, LMTable},
-    searchinfo::SearchInfo,
+    kpk, logging, nnue, perft,
+    piece::{Colour, PieceType},
+    search::{parameters::Config, strength, LMTable},
+    searchinfo::{SearchInfo, SearchOptions, Silent},
     tablebases,
     threadlocal::ThreadData,
     timemgmt::SearchLimit,
     transpositiontable::TT,
-    util::{MAX_DEPTH, MEGABYTE},
-    NAME, VERSION,
+    util::{File, Rank, Square, MAX_DEPTH, MEGABYTE},
+    xboard, NAME, VERSION,
 };
 
 const UCI_DEFAULT_HASH_MEGABYTES: usize = 16;
 const UCI_MAX_HASH_MEGABYTES: usize = 1_048_576;
 const UCI_MAX_THREADS: usize = 512;
 const UCI_MAX_MULTIPV: usize = 500;
+const UCI_MAX_VARIETY: i32 = 100;
+const UCI_MAX_SEED: u64 = u32::MAX as u64;
 
 static STDIN_READER_THREAD_KEEP_RUNNING: AtomicBool = AtomicBool::new(true);
 pub static QUIT: AtomicBool = AtomicBool::new(false);
+pub static DEBUG: AtomicBool = AtomicBool::new(false);
 pub static GO_MATE_MAX_DEPTH: AtomicUsize = AtomicUsize::new(MAX_DEPTH.ply_to_horizon());
 pub static PRETTY_PRINT: AtomicBool = AtomicBool::new(true);
+pub static PRETTY_PV: AtomicBool = AtomicBool::new(false);
+pub static SEED: AtomicU64 = AtomicU64::new(0);
 pub static SYZYGY_PROBE_LIMIT: AtomicU8 = AtomicU8::new(6);
 pub static SYZYGY_PROBE_DEPTH: AtomicI32 = AtomicI32::new(1);
 pub static SYZYGY_PATH: Mutex<String> = Mutex::new(String::new());
+pub static LOG_FILE: Mutex<String> = Mutex::new(String::new());
 pub static SYZYGY_ENABLED: AtomicBool = AtomicBool::new(false);
 pub static MULTI_PV: AtomicUsize = AtomicUsize::new(1);
 pub static CONTEMPT: AtomicI32 = AtomicI32::new(0);
 pub static CHESS960: AtomicBool = AtomicBool::new(false);
-pub fn is_multipv() -> bool {
-    MULTI_PV.load(Ordering::SeqCst) > 1
-}
+pub static SHOW_CURRLINE: AtomicBool = AtomicBool::new(false);
 
 #[derive(Debug, PartialEq, Eq)]
 enum UciError {
@@ -114,6 +120,7 @@ impl Display for UciError {
 // position fen
 // position startpos
 // ... moves e2e4 e7e5 b7b8q
+// the position is only updated if the whole command is valid.
 fn parse_position(text: &str, pos: &mut Board) -> Result<(), UciError> {
     let mut parts = text.split_ascii_whitespace();
     let command =
@@ -124,8 +131,9 @@ fn parse_position(text: &str, pos: &mut Board) -> Result<(), UciError> {
     let determiner = parts
         .next()
         .ok_or_else(|| UciError::UnexpectedCommandTermination("No determiner after \"position\"".into()))?;
+    let mut new_pos = Board::new();
     if determiner == "startpos" {
-        pos.set_startpos();
+        new_pos.set_startpos();
         let moves = parts.next(); // skip "moves"
         if !(matches!(moves, Some("moves") | None)) {
             return Err(UciError::InvalidFormat(
@@ -144,14 +152,17 @@ fn parse_position(text: &str, pos: &mut Board) -> Result<(), UciError> {
             fen.push_str(part);
             fen.push(' ');
         }
-        pos.set_from_fen(&fen)?;
+        for dropped in new_pos.set_from_fen_sanitised(&fen)? {
+            eprintln_logged!("info string ignoring {dropped}");
+        }
     }
     for san in parts {
-        pos.zero_height(); // stuff breaks really hard without this lmao
-        let m = pos.parse_uci(san)?;
-        pos.make_move_simple(m);
+        new_pos.zero_height(); // stuff breaks really hard without this lmao
+        let m = new_pos.parse_move(san)?;
+        new_pos.make_move_simple(m);
     }
-    pos.zero_height();
+    new_pos.zero_height();
+    *pos = new_pos;
     Ok(())
 }
 
@@ -220,7 +231,13 @@ fn parse_go(text: &str, info: &mut SearchInfo, pos: &Board) -> Result<(), UciErr
         limit = SearchLimit::Nodes(nodes);
     }
 
+    // a weakened engine thinks for at most a fixed number of nodes, on top of whatever limit
+    // the GUI gave, but never stops an infinite search early.
+    let node_cap =
+        (info.options.limit_strength && limit != SearchLimit::Infinite).then(|| strength::node_limit(info.options.elo));
+
     info.time_manager.set_limit(limit);
+    info.time_manager.set_node_cap(node_cap);
     info.time_manager.start();
 
     Ok(())
@@ -240,6 +257,7 @@ where
 
 struct SetOptions {
     pub search_config: Config,
+    pub search_options: SearchOptions,
     pub hash_mb: usize,
     pub threads: usize,
 }
@@ -320,6 +338,43 @@ fn parse_setoption(text: &str, pre_config: SetOptions) -> Result<SetOptions, Uci
             let value: bool = opt_value.parse()?;
             PRETTY_PRINT.store(value, Ordering::SeqCst);
         }
+        "PrettyPV" => {
+            let value: bool = opt_value.parse()?;
+            PRETTY_PV.store(value, Ordering::SeqCst);
+        }
+        "Deterministic" => {
+            let value: bool = opt_value.parse()?;
+            out.search_options.deterministic = value;
+        }
+        "UCI_LimitStrength" => {
+            let value: bool = opt_value.parse()?;
+            out.search_options.limit_strength = value;
+        }
+        "UCI_Elo" => {
+            let value: i32 = opt_value.parse()?;
+            if !(strength::MIN_ELO..=strength::MAX_ELO).contains(&value) {
+                return Err(UciError::IllegalValue(format!(
+                    "UCI_Elo value must be between {} and {}",
+                    strength::MIN_ELO,
+                    strength::MAX_ELO
+                )));
+            }
+            out.search_options.elo = value;
+        }
+        "Variety" => {
+            let value: i32 = opt_value.parse()?;
+            if !(0..=UCI_MAX_VARIETY).contains(&value) {
+                return Err(UciError::IllegalValue(format!("Variety value must be between 0 and {UCI_MAX_VARIETY}")));
+            }
+            out.search_options.variety = value;
+        }
+        "Seed" => {
+            let value: u64 = opt_value.parse()?;
+            if value > UCI_MAX_SEED {
+                return Err(UciError::IllegalValue(format!("Seed value must be between 0 and {UCI_MAX_SEED}")));
+            }
+            SEED.store(value, Ordering::SeqCst);
+        }
         "SyzygyPath" => {
             let path = opt_value.to_string();
             tablebases::probe::init(&path);
@@ -330,6 +385,20 @@ fn parse_setoption(text: &str, pre_config: SetOptions) -> Result<SetOptions, Uci
                 return Err(UciError::InternalError("failed to take lock on SyzygyPath".into()));
             }
         }
+        "LogFile" => {
+            let path = opt_value.to_string();
+            if path.is_empty() || path == "<empty>" {
+                logging::close();
+            } else {
+                logging::open(Path::new(&path))
+                    .map_err(|e| UciError::IllegalValue(format!("failed to open log file {path}: {e}")))?;
+            }
+            if let Ok(mut lock) = LOG_FILE.lock() {
+                *lock = path;
+            } else {
+                return Err(UciError::InternalError("failed to take lock on LogFile".into()));
+            }
+        }
         "SyzygyProbeLimit" => {
             let value: u8 = opt_value.parse()?;
             if value > 6 {
@@ -355,14 +424,34 @@ fn parse_setoption(text: &str, pre_config: SetOptions) -> Result<SetOptions, Uci
             let val = opt_value.parse()?;
             CHESS960.store(val, Ordering::SeqCst);
         }
+        "UCI_AnalyseMode" => {
+            let value: bool = opt_value.parse()?;
+            out.search_options.analyse_mode = value;
+        }
+        "UCI_ShowCurrLine" => {
+            let value: bool = opt_value.parse()?;
+            SHOW_CURRLINE.store(value, Ordering::SeqCst);
+        }
+        "AnalysisNullMove" => {
+            let value: bool = opt_value.parse()?;
+            out.search_options.analysis_null_move = value;
+        }
+        "AnalysisProbCut" => {
+            let value: bool = opt_value.parse()?;
+            out.search_options.analysis_probcut = value;
+        }
+        "AnalysisLMP" => {
+            let value: bool = opt_value.parse()?;
+            out.search_options.analysis_lmp = value;
+        }
         _ => {
-            eprintln!("info string ignoring option {opt_name}, type \"uci\" for a list of options");
+            eprintln_logged!("info string ignoring option {opt_name}, type \"uci\" for a list of options");
         }
     }
     Ok(out)
 }
 
-fn stdin_reader() -> mpsc::Receiver<String> {
+pub fn stdin_reader() -> mpsc::Receiver<String> {
     let (sender, receiver) = mpsc::channel();
     std::thread::Builder::new()
         .name("stdin-reader".into())
@@ -385,8 +474,9 @@ fn stdin_reader_worker(sender: mpsc::Sender<String>) {
             linebuf.clear();
             continue;
         }
+        logging::record_input(cmd);
         if let Err(e) = sender.send(cmd.to_owned()) {
-            eprintln!("info string error sending command to main thread: {e}");
+            eprintln_logged!("info string error sending command to main thread: {e}");
             break;
         }
         if !STDIN_READER_THREAD_KEEP_RUNNING.load(atomic::Ordering::SeqCst) {
@@ -415,6 +505,14 @@ impl Display for ScoreFormatWrapper {
         }
     }
 }
+/// Converts an internal score to centipawns, as reported to the user.
+pub const fn normalise_score(score: i32) -> i32 {
+    if is_game_theoretic_score(score) {
+        score
+    } else {
+        score * 100 / NORMALISE_TO_PAWN_VALUE
+    }
+}
 pub const fn format_score(score: i32) -> ScoreFormatWrapper {
     ScoreFormatWrapper(score)
 }
@@ -493,54 +591,126 @@ pub const fn format_time(millis: u128) -> HumanTimeFormatWrapper {
 
 fn print_uci_response(info: &SearchInfo, full: bool) {
     let version_extension = if cfg!(feature = "final-release") { "" } else { "-dev" };
-    println!("id name {NAME} {VERSION}{version_extension}");
-    println!("id author Cosmo");
-    println!("option name Hash type spin default {UCI_DEFAULT_HASH_MEGABYTES} min 1 max {UCI_MAX_HASH_MEGABYTES}");
-    println!("option name Threads type spin default 1 min 1 max 512");
-    println!("option name PrettyPrint type check default false");
-    println!("option name SyzygyPath type string default <empty>");
-    println!("option name SyzygyProbeLimit type spin default 6 min 0 max 6");
-    println!("option name SyzygyProbeDepth type spin default 1 min 1 max 100");
-    println!("option name Contempt type spin default 0 min -10000 max 10000");
-    println!("option name UCI_Chess960 type check default false");
+    println_logged!("id name {NAME} {VERSION}{version_extension}");
+    println_logged!("id author Cosmo");
+    println_logged!(
+        "option name Hash type spin default {UCI_DEFAULT_HASH_MEGABYTES} min 1 max {UCI_MAX_HASH_MEGABYTES}"
+    );
+    println_logged!("option name Threads type spin default 1 min 1 max 512");
+    println_logged!("option name PrettyPrint type check default false");
+    println_logged!("option name PrettyPV type check default false");
+    println_logged!("option name Deterministic type check default false");
+    println_logged!("option name Variety type spin default 0 min 0 max {UCI_MAX_VARIETY}");
+    println_logged!("option name Seed type spin default 0 min 0 max {UCI_MAX_SEED}");
+    println_logged!("option name UCI_LimitStrength type check default false");
+    println_logged!(
+        "option name UCI_Elo type spin default {} min {} max {}",
+        strength::DEFAULT_ELO,
+        strength::MIN_ELO,
+        strength::MAX_ELO
+    );
+    println_logged!("option name SyzygyPath type string default <empty>");
+    println_logged!("option name SyzygyProbeLimit type spin default 6 min 0 max 6");
+    println_logged!("option name SyzygyProbeDepth type spin default 1 min 1 max 100");
+    println_logged!("option name LogFile type string default <empty>");
+    println_logged!("option name Contempt type spin default 0 min -10000 max 10000");
+    println_logged!("option name UCI_Chess960 type check default false");
+    println_logged!("option name UCI_AnalyseMode type check default false");
+    println_logged!("option name UCI_ShowCurrLine type check default false");
+    println_logged!("option name AnalysisNullMove type check default true");
+    println_logged!("option name AnalysisProbCut type check default true");
+    println_logged!("option name AnalysisLMP type check default true");
+    println_logged!("option name Clear Hash type button");
     // println!("option name MultiPV type spin default 1 min 1 max 500");
     if full {
         for (id, default, min, max, _) in info.conf.base_config() {
-            println!("option name {id} type spin default {default} min {min} max {max}");
+            println_logged!("option name {id} type spin default {default} min {min} max {max}");
         }
     }
-    println!("uciok");
+    println_logged!("uciok");
+}
+
+/// Runs the interactive UCI loop. Options from the startup configuration file at `config`,
+/// or from `viridithas.toml` in the working directory if there is one, are applied first.
+pub fn main_loop(global_bench: bool, pretty: bool, config: Option<&Path>) {
+    let startup = config::load(config).unwrap_or_else(|e| {
+        eprintln_logged!("info string {e}");
+        Vec::new()
+    });
+    command_loop(stdin_reader(), startup, true, global_bench, pretty);
 }
 
+/// Runs the UCI commands in a file, or on stdin if the path is "-", one after another, and then exits.
+/// Blank lines and lines starting with '#' are skipped. Unlike in the interactive loop, a search is
+/// never interrupted by the commands that follow it, so every "go" runs to its limit.
+pub fn run_script(path: &Path, pretty: bool) -> io::Result<()> {
+    let text = if path == Path::new("-") {
+        io::read_to_string(io::stdin())?
+    } else {
+        std::fs::read_to_string(path)?
+    };
+    let (sender, receiver) = mpsc::channel();
+    for line in text.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+        // the receiver is still alive, so this can't fail.
+        let _ = sender.send(line.to_owned());
+    }
+    std::mem::drop(sender);
+    command_loop(receiver, Vec::new(), false, false, pretty);
+    Ok(())
+}
+
+/// Reads and executes commands until the channel closes or we're told to quit.
+/// The `startup` commands are run before any of them.
+/// When `interactive` is set, commands are coming from a user or GUI, and can interrupt a running search.
 #[allow(clippy::too_many_lines, clippy::cognitive_complexity)]
-pub fn main_loop(global_bench: bool) {
+fn command_loop(
+    commands: mpsc::Receiver<String>,
+    startup: Vec<String>,
+    interactive: bool,
+    global_bench: bool,
+    pretty: bool,
+) {
+    PRETTY_PRINT.store(pretty, Ordering::SeqCst);
+
+    // generate the KPK bitbase up-front, so that the first search doesn't pay for it.
+    kpk::init();
+
     let mut pos = Board::default();
 
     let mut tt = TT::new();
     tt.resize(UCI_DEFAULT_HASH_MEGABYTES * MEGABYTE); // default hash size
 
     let stopped = AtomicBool::new(false);
-    let stdin = Mutex::new(stdin_reader());
+    let stdin = Mutex::new(commands);
     let nodes = AtomicU64::new(0);
     let mut info = SearchInfo::new(&stopped, &nodes);
-    info.set_stdin(&stdin);
+    if interactive {
+        info.set_stdin(&stdin);
+    }
 
     let mut thread_data = vec![ThreadData::new(0, &pos, tt.view())];
 
     let version_extension = if cfg!(feature = "final-release") { "" } else { "-dev" };
-    println!("{NAME} {VERSION}{version_extension} by Cosmo");
+    println_logged!("{NAME} {VERSION}{version_extension} by Cosmo");
 
     if global_bench {
         bench("openbench", &info.conf).expect("bench failed");
         return;
     }
 
+    let mut startup = startup.into_iter();
     loop {
         std::io::stdout().flush().expect("couldn't flush stdout");
-        let Ok(line) = stdin.lock().expect("failed to take lock on stdin").recv() else {
+        let configuring = startup.len() > 0;
+        let received = startup.next().map_or_else(|| stdin.lock().expect("failed to take lock on stdin").recv(), Ok);
+        let Ok(line) = received else {
             break;
         };
         let input = line.trim();
+        if !interactive {
+            // the stdin reader records interactive input as it arrives.
+            logging::record_input(input);
+        }
 
         let res = match input {
             "\n" => continue,
@@ -559,29 +729,54 @@ pub fn main_loop(global_bench: bool) {
             }
             arg @ ("ucidump" | "ucidumpfull") => {
                 // dump the values of the current UCI options
-                println!("Hash: {}", tt.size() / MEGABYTE);
-                println!("Threads: {}", thread_data.len());
-                println!("PrettyPrint: {}", PRETTY_PRINT.load(Ordering::SeqCst));
-                println!("SyzygyPath: {}", SYZYGY_PATH.lock().expect("failed to lock syzygy path"));
-                println!("SyzygyProbeLimit: {}", SYZYGY_PROBE_LIMIT.load(Ordering::SeqCst));
-                println!("SyzygyProbeDepth: {}", SYZYGY_PROBE_DEPTH.load(Ordering::SeqCst));
-                println!("Contempt: {}", CONTEMPT.load(Ordering::SeqCst));
+                println_logged!("Hash: {}", tt.size() / MEGABYTE);
+                println_logged!("Threads: {}", thread_data.len());
+                println_logged!("PrettyPrint: {}", PRETTY_PRINT.load(Ordering::SeqCst));
+                println_logged!("PrettyPV: {}", PRETTY_PV.load(Ordering::SeqCst));
+                println_logged!("Deterministic: {}", info.options.deterministic);
+                println_logged!("Variety: {}", info.options.variety);
+                println_logged!("Seed: {}", SEED.load(Ordering::SeqCst));
+                println_logged!("UCI_LimitStrength: {}", info.options.limit_strength);
+                println_logged!("UCI_Elo: {}", info.options.elo);
+                println_logged!("SyzygyPath: {}", SYZYGY_PATH.lock().expect("failed to lock syzygy path"));
+                println_logged!("SyzygyProbeLimit: {}", SYZYGY_PROBE_LIMIT.load(Ordering::SeqCst));
+                println_logged!("SyzygyProbeDepth: {}", SYZYGY_PROBE_DEPTH.load(Ordering::SeqCst));
+                println_logged!("LogFile: {}", LOG_FILE.lock().expect("failed to lock log file path"));
+                println_logged!("Contempt: {}", CONTEMPT.load(Ordering::SeqCst));
+                println_logged!("UCI_AnalyseMode: {}", info.options.analyse_mode);
+                println_logged!("UCI_ShowCurrLine: {}", SHOW_CURRLINE.load(Ordering::SeqCst));
+                println_logged!("AnalysisNullMove: {}", info.options.analysis_null_move);
+                println_logged!("AnalysisProbCut: {}", info.options.analysis_probcut);
+                println_logged!("AnalysisLMP: {}", info.options.analysis_lmp);
                 // println!("MultiPV: {}", MULTI_PV.load(Ordering::SeqCst));
                 if arg == "ucidumpfull" {
                     for (id, default) in Config::default().ids_with_values() {
-                        println!("{id}: {default}");
+                        println_logged!("{id}: {default}");
                     }
                 }
                 Ok(())
             }
             "isready" => {
-                println!("readyok");
+                println_logged!("readyok");
                 Ok(())
             }
             "quit" => {
                 QUIT.store(true, Ordering::SeqCst);
                 break;
             }
+            "xboard" => {
+                // the GUI speaks CECP, so hand over to the xboard loop for the rest of the session.
+                xboard::main_loop(&stdin);
+                break;
+            }
+            "debug on" => {
+                DEBUG.store(true, Ordering::SeqCst);
+                Ok(())
+            }
+            "debug off" => {
+                DEBUG.store(false, Ordering::SeqCst);
+                Ok(())
+            }
             "ucinewgame" => do_newgame(&mut pos, &tt, &mut thread_data),
             "eval" => {
                 let eval = if pos.in_check() {
@@ -589,7 +784,7 @@ pub fn main_loop(global_bench: bool) {
                 } else {
                     pos.evaluate(thread_data.first_mut().expect("the thread headers are empty."), 0)
                 };
-                println!("{eval}");
+                println_logged!("{eval}");
                 Ok(())
             }
             "raweval" => {
@@ -598,32 +793,104 @@ pub fn main_loop(global_bench: bool) {
                 } else {
                     thread_data.first_mut().expect("the thread headers are empty.").nnue.evaluate(pos.turn())
                 };
-                println!("{eval}");
+                println_logged!("{eval}");
+                Ok(())
+            }
+            "flip" => {
+                // the mirrored position is set up from scratch, so the game history is lost.
+                pos = pos.mirrored();
+                for t in &mut thread_data {
+                    t.nnue.reinit_from(&pos);
+                }
                 Ok(())
             }
             "show" => {
-                println!("{pos}");
+                if PRETTY_PRINT.load(Ordering::SeqCst) {
+                    println_logged!("{}", pretty_format_board(&pos));
+                } else {
+                    println_logged!("{pos}");
+                }
+                Ok(())
+            }
+            "d" => {
+                let eval = if pos.in_check() {
+                    0
+                } else {
+                    pos.evaluate(thread_data.first_mut().expect("the thread headers are empty."), 0)
+                };
+                if PRETTY_PRINT.load(Ordering::SeqCst) {
+                    print_logged!("{}", pretty_format_board(&pos));
+                } else {
+                    print_logged!("{pos}");
+                }
+                println_logged!("Key: {:016X}", pos.hashkey());
+                println_logged!("Eval: {eval} (side to move)");
+                if PRETTY_PRINT.load(Ordering::SeqCst) {
+                    println_logged!("{}", pretty_format_eval_bar(eval, pos.ply(), pos.turn()));
+                }
+                println_logged!("Phase: {}/24", pos.phase());
                 Ok(())
             }
+            "searchstats" => {
+                #[cfg(feature = "stats")]
+                {
+                    info.stats.print();
+                    Ok(())
+                }
+                #[cfg(not(feature = "stats"))]
+                {
+                    Err(UciError::InternalError(
+                        "searchstats requires a build with the \"stats\" feature enabled".to_string(),
+                    ))
+                }
+            }
+            input if input.starts_with("treedump") => {
+                #[cfg(feature = "treedump")]
+                {
+                    treedump(input, &mut info)
+                }
+                #[cfg(not(feature = "treedump"))]
+                {
+                    Err(UciError::InternalError(
+                        "treedump requires a build with the \"treedump\" feature enabled".to_string(),
+                    ))
+                }
+            }
             "nnuebench" => {
                 nnue::network::inference_benchmark(&thread_data[0].nnue);
                 Ok(())
             }
+            // buttons have no value, so this can't go through parse_setoption.
+            "setoption name Clear Hash" => {
+                clear_hash(&tt, &mut thread_data);
+                Ok(())
+            }
             input if input.starts_with("setoption") => {
                 let pre_config = SetOptions {
                     search_config: info.conf.clone(),
+                    search_options: info.options,
                     hash_mb: tt.size() / MEGABYTE,
                     threads: thread_data.len(),
                 };
+                let pre_config_hash_mb = pre_config.hash_mb;
                 let res = parse_setoption(input, pre_config);
                 match res {
                     Ok(conf) => {
                         info.conf = conf.search_config;
+                        info.options = conf.search_options;
                         info.lm_table = LMTable::new(&info.conf);
-                        let new_size = conf.hash_mb * MEGABYTE;
-                        // drop all the thread_data, as they are borrowing the old tt
-                        std::mem::drop(thread_data);
-                        tt.resize(new_size);
+                        // only reallocate the table when its size changes, as a large one takes a while.
+                        if conf.hash_mb != pre_config_hash_mb {
+                            // drop all the thread_data, as they are borrowing the old tt
+                            std::mem::drop(thread_data);
+                            tt.resize(conf.hash_mb * MEGABYTE);
+                            println_logged!("info string hash table uses {}", tt.pages());
+                        }
+                        debug_info!(
+                            "hash table is {} MB, searching with {} threads",
+                            tt.size() / MEGABYTE,
+                            conf.threads
+                        );
                         // recreate the thread_data with the new tt
                         thread_data = (0..conf.threads)
                             .zip(std::iter::repeat(&pos))
@@ -644,6 +911,8 @@ pub fn main_loop(global_bench: bool) {
                 res
             }
             input if input.starts_with("go perft") || input.starts_with("perft") => {
+                // like Stockfish, "go perft" breaks the count down by root move; "perft" only gives the total.
+                let divide_by_default = input.starts_with("go perft");
                 let tail = input.trim_start_matches("go perft ").trim_start_matches("perft ");
                 match tail.split_whitespace().next() {
                     Some("divide" | "split") => {
@@ -651,12 +920,18 @@ pub fn main_loop(global_bench: bool) {
                         depth
                             .parse::<usize>()
                             .map_err(|_| UciError::InvalidFormat(format!("cannot parse \"{depth}\" as usize")))
-                            .map(|depth| divide_perft(depth, &mut pos))
+                            .map(|depth| perft::run_divide(&mut pos, depth, thread_data.len()))
                     }
                     Some(depth) => depth
                         .parse::<usize>()
                         .map_err(|_| UciError::InvalidFormat(format!("cannot parse \"{depth}\" as usize")))
-                        .map(|depth| block_perft(depth, &mut pos)),
+                        .map(|depth| {
+                            if divide_by_default {
+                                perft::run_divide(&mut pos, depth, thread_data.len());
+                            } else {
+                                perft::run_perft(&mut pos, depth, thread_data.len());
+                            }
+                        }),
                     None => Err(UciError::InvalidFormat("expected a depth after 'go perft'".to_string())),
                 }
             }
@@ -664,7 +939,9 @@ pub fn main_loop(global_bench: bool) {
                 let res = parse_go(input, &mut info, &pos);
                 if res.is_ok() {
                     tt.increase_age();
-                    pos.search_position(&mut info, &mut thread_data, tt.view());
+                    // helper threads make the search depend on OS scheduling.
+                    let threads = if info.options.deterministic { 1 } else { thread_data.len() };
+                    pos.search_position(&mut info, &mut thread_data[..threads], tt.view());
                 }
                 res
             }
@@ -673,15 +950,17 @@ pub fn main_loop(global_bench: bool) {
         };
 
         if let Err(e) = res {
-            eprintln!("info string {e}");
+            eprintln_logged!("info string {e}");
         }
 
-        if QUIT.load(Ordering::SeqCst) {
-            // quit can be set true in parse_go
+        // quit can be set true in parse_go, or by the stdin reader at EOF, which can happen
+        // before we're done with the startup options, so don't let it skip the first command.
+        if QUIT.load(Ordering::SeqCst) && !configuring {
             break;
         }
     }
     STDIN_READER_THREAD_KEEP_RUNNING.store(false, atomic::Ordering::SeqCst);
+    logging::close();
 }
 
 const BENCH_DEPTH: usize = 16;
@@ -691,7 +970,7 @@ fn bench(benchcmd: &str, search_params: &Config) -> Result<(), UciError> {
     let stopped = AtomicBool::new(false);
     let nodes = AtomicU64::new(0);
     let mut info = SearchInfo::with_search_params(&stopped, &nodes, search_params);
-    info.print_to_stdout = false;
+    info.sink = &Silent;
     let mut pos = Board::default();
     let mut tt = TT::new();
     tt.resize(16 * MEGABYTE);
@@ -703,80 +982,66 @@ fn bench(benchcmd: &str, search_params: &Config) -> Result<(), UciError> {
     let start = Instant::now();
     let max_fen_len = BENCH_POSITIONS.iter().map(|s| s.len()).max().expect("this array is nonempty.");
     for fen in BENCH_POSITIONS {
-        let res = do_newgame(&mut pos, &tt, &mut thread_data);
-        if let Err(e) = res {
-            info.print_to_stdout = true;
-            return Err(e);
-        }
-        let res = parse_position(&format!("position fen {fen}\n"), &mut pos);
-        if let Err(e) = res {
-            info.print_to_stdout = true;
-            return Err(e);
-        }
+        do_newgame(&mut pos, &tt, &mut thread_data)?;
+        parse_position(&format!("position fen {fen}\n"), &mut pos)?;
         for t in &mut thread_data {
             t.nnue.reinit_from(&pos);
         }
-        let res = parse_go(&bench_string, &mut info, &pos);
-        if let Err(e) = res {
-            info.print_to_stdout = true;
-            return Err(e);
-        }
+        parse_go(&bench_string, &mut info, &pos)?;
         tt.increase_age();
         pos.search_position(&mut info, &mut thread_data, tt.view());
         node_sum += info.nodes.get_global();
         if matches!(benchcmd, "benchfull" | "openbench") {
-            println!("{fen:<max_fen_len$} | {:>7} nodes", info.nodes.get_global());
+            println_logged!("{fen:<max_fen_len$} | {:>7} nodes", info.nodes.get_global());
         }
     }
     let time = start.elapsed();
     #[allow(clippy::cast_precision_loss)]
     let nps = node_sum as f64 / time.as_secs_f64();
     if benchcmd == "openbench" {
-        println!("{node_sum} nodes {nps:.0} nps");
+        println_logged!("{node_sum} nodes {nps:.0} nps");
     } else {
-        println!("{node_sum} nodes in {time:.3}s ({nps:.0} nps)", time = time.as_secs_f64());
+        println_logged!("{node_sum} nodes in {time:.3}s ({nps:.0} nps)", time = time.as_secs_f64());
     }
-    info.print_to_stdout = true;
     Ok(())
 }
 
-fn block_perft(depth: usize, pos: &mut Board) {
-    #![allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
-    let start_time = Instant::now();
-    let nodes = perft::perft(pos, depth);
-    let elapsed = start_time.elapsed();
-    let nps = nodes as f64 / elapsed.as_secs_f64();
-    println!("info depth {depth} nodes {nodes} time {elapsed} nps {nps:.0}", elapsed = elapsed.as_millis());
-}
-
-fn divide_perft(depth: usize, pos: &mut Board) {
-    #![allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
-    let start_time = Instant::now();
-    let mut nodes = 0;
-    let mut ml = MoveList::new();
-    pos.generate_moves(&mut ml);
-    for &m in ml.iter_moves() {
-        if !pos.make_move_simple(m) {
-            continue;
+#[cfg(feature = "treedump")]
+fn treedump(input: &str, info: &mut SearchInfo) -> Result<(), UciError> {
+    let parse = |word: &str| {
+        word.parse::<usize>().map_err(|_| UciError::InvalidFormat(format!("cannot parse \"{word}\" as usize")))
+    };
+    let mut words = input.split_whitespace().skip(1);
+    match (words.next(), words.next(), words.next()) {
+        (Some("limit"), Some(height), Some(nodes)) => {
+            info.tree.max_height = parse(height)?;
+            info.tree.max_nodes = parse(nodes)?;
+            Ok(())
         }
-        let arm_nodes = perft::perft(pos, depth - 1);
-        nodes += arm_nodes;
-        println!("{m}: {arm_nodes}");
-        pos.unmake_move_base();
+        (Some(path), None, None) => {
+            let file = std::fs::File::create(path)
+                .map_err(|e| UciError::InternalError(format!("failed to create {path}: {e}")))?;
+            info.tree
+                .write_json(std::io::BufWriter::new(file))
+                .map_err(|e| UciError::InternalError(format!("failed to write {path}: {e}")))
+        }
+        _ => Err(UciError::InvalidFormat(
+            "expected \"treedump <path>\" or \"treedump limit <height> <nodes>\"".to_string(),
+        )),
     }
-    let elapsed = start_time.elapsed();
-    println!(
-        "info depth {depth} nodes {nodes} time {elapsed} nps {nps:.0}",
-        elapsed = elapsed.as_millis(),
-        nps = nodes as f64 / elapsed.as_secs_f64()
-    );
 }
 
 fn do_newgame(pos: &mut Board, tt: &TT, thread_data: &mut [ThreadData]) -> Result<(), UciError> {
     parse_position("position startpos\n", pos)?;
+    clear_hash(tt, thread_data);
+    Ok(())
+}
+
+/// Forgets everything learned in previous searches: the transposition table, and all the per-thread
+/// history, killer, and counter-move tables.
+fn clear_hash(tt: &TT, thread_data: &mut [ThreadData]) {
     tt.clear(thread_data.len());
     thread_data.iter_mut().for_each(ThreadData::clear_tables);
-    Ok(())
 }
 
 /// Normalizes the internal value as reported by evaluate or search
@@ -838,3 +1103,66 @@ pub fn format_wdl(eval: i32, ply: usize) -> impl Display {
 pub fn pretty_format_wdl(eval: i32, ply: usize) -> impl Display {
     PrettyUciWdlFormat { eval, ply }
 }
+
+struct PrettyEvalBarFormat {
+    eval: i32,
+    ply: usize,
+    colour: Colour,
+}
+impl Display for PrettyEvalBarFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const WIDTH: i32 = 10;
+        let white_pov = if self.colour == Colour::WHITE { self.eval } else { -self.eval };
+        let (wdl_w, wdl_l) = win_rate_model(white_pov, self.ply);
+        // white's expected score, in per mille.
+        let expectation = wdl_w + (1000 - wdl_w - wdl_l) / 2;
+        let filled = (expectation * WIDTH + 500) / 1000;
+        write!(f, "\u{001b}[97m")?;
+        for _ in 0..filled {
+            write!(f, "\u{2588}")?;
+        }
+        write!(f, "\u{001b}[38;5;240m")?;
+        for _ in filled..WIDTH {
+            write!(f, "\u{2588}")?;
+        }
+        write!(f, "\u{001b}[0m")
+    }
+}
+
+/// A bar showing how the expected score is split between white and black.
+pub const fn pretty_format_eval_bar(eval: i32, ply: usize, colour: Colour) -> impl Display {
+    PrettyEvalBarFormat { eval, ply, colour }
+}
+
+struct PrettyBoardFormat<'a>(&'a Board);
+impl Display for PrettyBoardFormat<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for rank in (Rank::RANK_1..=Rank::RANK_8).rev() {
+            write!(f, " {} ", rank + 1)?;
+            for file in File::FILE_A..=File::FILE_H {
+                let sq = Square::from_rank_file(rank, file);
+                let background = if (rank + file) % 2 == 0 { 94 } else { 180 };
+                let piece = self.0.piece_at(sq);
+                let glyph = match piece.piece_type() {
+                    PieceType::PAWN => '\u{265F}',
+                    PieceType::KNIGHT => '\u{265E}',
+                    PieceType::BISHOP => '\u{265D}',
+                    PieceType::ROOK => '\u{265C}',
+                    PieceType::QUEEN => '\u{265B}',
+                    PieceType::KING => '\u{265A}',
+                    _ => ' ',
+                };
+                let foreground = if piece.colour() == Colour::WHITE { 231 } else { 16 };
+                write!(f, "\u{001b}[48;5;{background}m\u{001b}[38;5;{foreground}m{glyph} \u{001b}[0m")?;
+            }
+            writeln!(f)?;
+        }
+        writeln!(f, "   a b c d e f g h")?;
+        writeln!(f, "FEN: {}", self.0.fen())
+    }
+}
+
+/// The board drawn with coloured squares and Unicode pieces, for use in a terminal.
+pub const fn pretty_format_board(board: &Board) -> impl Display + '_ {
+    PrettyBoardFormat(board)
+}
@@ -0,0 +1,119 @@
+// Startup configuration, for deployments where nothing sends "setoption" before the first search,
+// or where doing so would need a wrapper script. The file is a flat TOML table of UCI option names
+// and values, e.g.
+//
+//     Hash = 256
+//     Threads = 4
+//     SyzygyPath = "/opt/syzygy"
+//
+// Only the part of TOML needed for that is understood: bare or quoted keys, and string, integer,
+// float and boolean values. Each entry is applied as if the GUI had sent the matching setoption.
+
+use std::path::Path;
+
+/// The file that is read from the working directory when no path is given with --config.
+pub const DEFAULT_CONFIG_FILE: &str = "viridithas.toml";
+
+/// Parses a configuration file into (option name, value) pairs, in file order.
+pub fn parse(text: &str) -> Result<Vec<(String, String)>, String> {
+    let mut options = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        let error = |msg: &str| format!("line {}: {msg}: \"{line}\"", i + 1);
+        if line.starts_with('[') {
+            return Err(error("tables are not supported, options must be at the top level"));
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(error("expected \"<option> = <value>\""));
+        };
+        let key = unquote(key.trim()).ok_or_else(|| error("malformed option name"))?;
+        let value = unquote(value.trim()).ok_or_else(|| error("malformed value"))?;
+        if key.is_empty() || key.contains(char::is_whitespace) {
+            return Err(error("option names may not be empty or contain whitespace"));
+        }
+        if value.is_empty() {
+            return Err(error("missing value"));
+        }
+        options.push((key, value));
+    }
+    Ok(options)
+}
+
+/// Reads the configuration file and turns it into setoption commands.
+/// With no explicit path, a missing default file is not an error.
+pub fn load(path: Option<&Path>) -> Result<Vec<String>, String> {
+    let text = match path {
+        Some(path) => std::fs::read_to_string(path).map_err(|e| format!("failed to read {}: {e}", path.display()))?,
+        None => match std::fs::read_to_string(DEFAULT_CONFIG_FILE) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(format!("failed to read {DEFAULT_CONFIG_FILE}: {e}")),
+        },
+    };
+    let source = path.map_or_else(|| DEFAULT_CONFIG_FILE.into(), |p| p.display().to_string());
+    let options = parse(&text).map_err(|e| format!("{source}: {e}"))?;
+    Ok(options.into_iter().map(|(name, value)| format!("setoption name {name} value {value}")).collect())
+}
+
+/// Removes a trailing '#' comment, unless the '#' is inside a string.
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Returns a bare token as-is, or the contents of a double-quoted string with escapes resolved.
+fn unquote(token: &str) -> Option<String> {
+    let Some(inner) = token.strip_prefix('"') else {
+        return (!token.contains('"')).then(|| token.to_string());
+    };
+    let inner = inner.strip_suffix('"')?;
+    let mut out = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next()? {
+                '\\' => out.push('\\'),
+                '"' => out.push('"'),
+                't' => out.push('\t'),
+                _ => return None,
+            },
+            '"' => return None,
+            c => out.push(c),
+        }
+    }
+    Some(out)
+}
+
+mod tests {
+    #[test]
+    fn parses_flat_tables() {
+        use super::parse;
+
+        let text = "# engine defaults\nHash = 256\n\nThreads=4 # one per core\nSyzygyPath = \"/opt/syzygy#1\"\nPrettyPrint = false\n";
+        let options = parse(text).expect("the example config is valid");
+        let expected = [("Hash", "256"), ("Threads", "4"), ("SyzygyPath", "/opt/syzygy#1"), ("PrettyPrint", "false")];
+        assert_eq!(options.len(), expected.len());
+        for ((key, value), (expected_key, expected_value)) in options.iter().zip(expected) {
+            assert_eq!(key, expected_key);
+            assert_eq!(value, expected_value);
+        }
+
+        assert!(parse("[uci]\nHash = 16").is_err());
+        assert!(parse("Hash 16").is_err());
+        assert!(parse("Hash = \"16").is_err());
+        assert!(parse("Hash =").is_err());
+    }
+}
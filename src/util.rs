@@ -7,11 +7,9 @@ use std::{
 };
 
 use crate::{
-    board::{
-        evaluation::MATE_SCORE,
-        movegen::bitboards::{BitBoard, Threats},
-    },
+    board::{evaluation::MATE_SCORE, movegen::bitboards::Threats},
     cfor,
+    chessmove::Move,
     historytable::ContHistIndex,
     piece::{Colour, Piece},
     squareset::SquareSet,
@@ -303,6 +301,8 @@ pub const WQCA: u8 = 0b0010;
 pub const BKCA: u8 = 0b0100;
 pub const BQCA: u8 = 0b1000;
 
+/// The state of the board that can't be recovered by reversing a move.
+/// One of these is copied onto the history stack for every move made.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Undo {
     pub castle_perm: CastlingRights,
@@ -310,9 +310,12 @@ pub struct Undo {
     pub fifty_move_counter: u8,
     pub threats: Threats,
     pub cont_hist_index: ContHistIndex,
-    pub bitboard: BitBoard,
-    pub piece_array: [Piece; 64],
+    /// The piece captured by the move, not counting en passant captures.
+    pub captured: Piece,
     pub key: u64,
+    pub pawn_key: u64,
+    /// The move that was made from this state, or the null move.
+    pub mov: Move,
 }
 
 impl Default for Undo {
@@ -321,11 +324,12 @@ impl Default for Undo {
             castle_perm: CastlingRights::NONE,
             ep_square: Square::NO_SQUARE,
             fifty_move_counter: 0,
-            threats: Threats { all: SquareSet::EMPTY, checkers: SquareSet::EMPTY },
+            threats: Threats { all: SquareSet::EMPTY, checkers: SquareSet::EMPTY, pinned: SquareSet::EMPTY },
             cont_hist_index: ContHistIndex::default(),
-            bitboard: BitBoard::NULL,
-            piece_array: [Piece::EMPTY; 64],
+            captured: Piece::EMPTY,
             key: 0,
+            pawn_key: 0,
+            mov: Move::NULL,
         }
     }
 }
@@ -472,6 +476,41 @@ pub static RAY_BETWEEN: [[SquareSet; 64]; 64] = {
     res
 };
 
+/// The full line through two squares, running from edge to edge of the board,
+/// or the empty set if the squares don't share a rank, file, or diagonal.
+const fn line_through(sq1: Square, sq2: Square) -> SquareSet {
+    let (r1, f1) = (sq1.rank() as i32, sq1.file() as i32);
+    let (r2, f2) = (sq2.rank() as i32, sq2.file() as i32);
+    let (rank_diff, file_diff) = (r2 - r1, f2 - f1);
+    if sq1.0 == sq2.0 || !(rank_diff == 0 || file_diff == 0 || rank_diff.abs() == file_diff.abs()) {
+        return SquareSet::EMPTY;
+    }
+    let (dr, df) = (rank_diff.signum(), file_diff.signum());
+    // walk back to the edge of the board, then forward to the other edge.
+    let (mut r, mut f) = (r1, f1);
+    while r - dr >= 0 && r - dr < 8 && f - df >= 0 && f - df < 8 {
+        r -= dr;
+        f -= df;
+    }
+    let mut line = 0;
+    while r >= 0 && r < 8 && f >= 0 && f < 8 {
+        line |= 1 << (r * 8 + f);
+        r += dr;
+        f += df;
+    }
+    SquareSet::from_inner(line)
+}
+
+pub static RAY_FULL: [[SquareSet; 64]; 64] = {
+    let mut res = [[SquareSet::EMPTY; 64]; 64];
+    cfor!(let mut from = Square::A1; from.0 < Square::NO_SQUARE.0; from = from.add_beyond_board(1); {
+        cfor!(let mut to = Square::A1; to.0 < Square::NO_SQUARE.0; to = to.add_beyond_board(1); {
+            res[from.index()][to.index()] = line_through(from, to);
+        });
+    });
+    res
+};
+
 #[derive(Debug, Clone, Copy)]
 pub struct BatchedAtomicCounter<'a> {
     buffer: u64,
@@ -552,6 +591,24 @@ mod tests {
         }
     }
 
+    #[test]
+    fn ray_full_test() {
+        use super::{Square, RAY_BETWEEN, RAY_FULL};
+        use crate::squareset::SquareSet;
+        assert_eq!(RAY_FULL[Square::A1.index()][Square::A1.index()], SquareSet::EMPTY);
+        assert_eq!(RAY_FULL[Square::A1.index()][Square::B3.index()], SquareSet::EMPTY);
+        assert_eq!(RAY_FULL[Square::C1.index()][Square::C4.index()], SquareSet::FILE_C);
+        assert_eq!(RAY_FULL[Square::D4.index()][Square::B2.index()].count(), 8);
+
+        for from in Square::all() {
+            for to in Square::all() {
+                let line = RAY_FULL[from.index()][to.index()];
+                assert_eq!(line, RAY_FULL[to.index()][from.index()]);
+                assert!((RAY_BETWEEN[from.index()][to.index()] & !line).is_empty());
+            }
+        }
+    }
+
     #[test]
     fn ray_diag_test() {
         use super::{Square, RAY_BETWEEN};
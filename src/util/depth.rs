@@ -6,6 +6,8 @@ use std::{
 
 use super::MAX_DEPTH;
 
+/// A search depth, in hundredths of a ply, so that reductions and extensions needn't be whole plies.
+/// Arithmetic saturates rather than overflowing.
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
 pub struct Depth(i32);
 
@@ -37,7 +39,8 @@ impl Depth {
         }
     }
 
-    pub const fn round(self) -> i32 {
+    /// The number of whole plies in this depth, rounding toward zero.
+    pub const fn trunc(self) -> i32 {
         self.0 / Self::INNER_INCR_BY_PLY
     }
 
@@ -63,14 +66,14 @@ impl Depth {
 impl Neg for Depth {
     type Output = Self;
     fn neg(self) -> Self::Output {
-        Self(-self.0)
+        Self(self.0.saturating_neg())
     }
 }
 
 impl Add<Self> for Depth {
     type Output = Self;
     fn add(self, other: Self) -> Self::Output {
-        Self(self.0 + other.0)
+        Self(self.0.saturating_add(other.0))
     }
 }
 
@@ -83,7 +86,7 @@ impl AddAssign<Self> for Depth {
 impl Add<i32> for Depth {
     type Output = Self;
     fn add(self, other: i32) -> Self::Output {
-        Self(self.0 + other * Self::INNER_INCR_BY_PLY)
+        Self(self.0.saturating_add(other.saturating_mul(Self::INNER_INCR_BY_PLY)))
     }
 }
 
@@ -96,7 +99,7 @@ impl AddAssign<i32> for Depth {
 impl Sub<Self> for Depth {
     type Output = Self;
     fn sub(self, other: Self) -> Self::Output {
-        Self(self.0 - other.0)
+        Self(self.0.saturating_sub(other.0))
     }
 }
 
@@ -109,7 +112,7 @@ impl SubAssign<Self> for Depth {
 impl Sub<i32> for Depth {
     type Output = Self;
     fn sub(self, other: i32) -> Self::Output {
-        Self(self.0 - other * Self::INNER_INCR_BY_PLY)
+        Self(self.0.saturating_sub(other.saturating_mul(Self::INNER_INCR_BY_PLY)))
     }
 }
 
@@ -122,7 +125,7 @@ impl SubAssign<i32> for Depth {
 impl Mul<i32> for Depth {
     type Output = Self;
     fn mul(self, other: i32) -> Self::Output {
-        Self(self.0 * other)
+        Self(self.0.saturating_mul(other))
     }
 }
 
@@ -205,7 +208,7 @@ impl Display for Depth {
             -1 => "-",
             _ => unreachable!(),
         };
-        write!(f, "{}{}.{}", sign, self.0.abs() / Self::INNER_INCR_BY_PLY, self.0.abs() % Self::INNER_INCR_BY_PLY)
+        write!(f, "{}{}.{:02}", sign, self.0.abs() / Self::INNER_INCR_BY_PLY, self.0.abs() % Self::INNER_INCR_BY_PLY)
     }
 }
 
@@ -218,24 +221,47 @@ impl FromStr for Depth {
     }
 }
 
+/// A depth as stored in the transposition table. Depths there are never negative,
+/// so this keeps the fractional part exactly, in the same units as `Depth`.
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
-pub struct CompactDepthStorage(u8);
+pub struct CompactDepthStorage(u16);
 
 impl CompactDepthStorage {
     pub const NULL: Self = Self(0);
 }
 
 impl TryFrom<Depth> for CompactDepthStorage {
-    type Error = <u8 as std::convert::TryFrom<i32>>::Error;
+    type Error = <u16 as std::convert::TryFrom<i32>>::Error;
     fn try_from(depth: Depth) -> Result<Self, Self::Error> {
-        let whole_depth = depth.0 / Depth::INNER_INCR_BY_PLY;
-        let inner = whole_depth.try_into()?;
-        Ok(Self(inner))
+        depth.0.try_into().map(Self)
     }
 }
 
 impl From<CompactDepthStorage> for Depth {
     fn from(depth: CompactDepthStorage) -> Self {
-        Self::new(i32::from(depth.0))
+        Self::from_raw(i32::from(depth.0))
+    }
+}
+
+mod tests {
+    #[test]
+    fn fractional_depths() {
+        use super::{CompactDepthStorage, Depth, ONE_PLY};
+
+        let depth = Depth::from(2.75);
+        assert_eq!(depth.to_string(), "2.75");
+        assert_eq!(Depth::from(1.05).to_string(), "1.05");
+        assert_eq!((depth - ONE_PLY / 4).to_string(), "2.50");
+        assert_eq!(depth.trunc(), 2);
+        assert_eq!((-depth).trunc(), -2);
+        assert_eq!(depth.nearest_full_ply(), Depth::new(3));
+
+        let stored = CompactDepthStorage::try_from(depth).unwrap();
+        assert_eq!(Depth::from(stored), depth);
+        assert!(CompactDepthStorage::try_from(-ONE_PLY).is_err());
+
+        let huge = Depth::from_raw(i32::MAX - 1);
+        assert_eq!(huge + ONE_PLY, Depth::from_raw(i32::MAX));
+        assert_eq!(-huge - 2, Depth::from_raw(i32::MIN));
     }
 }
@@ -0,0 +1,394 @@
+// An implementation of the Chess Engine Communication Protocol (CECP, protocol version 2),
+// as spoken by XBoard/WinBoard and a handful of older tournament managers. The engine enters
+// this mode when it receives "xboard" as a command, or when started with --xboard.
+//
+// Unlike UCI, the engine owns the game state here: it tracks which side it is playing,
+// the clocks, and the moves played, and decides on its own when it should start thinking.
+
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    board::{
+        evaluation::{is_mate_score, MATE_SCORE},
+        Board, GameOutcome,
+    },
+    chessmove::Move,
+    piece::Colour,
+    search::pv::PVariation,
+    searchinfo::SearchInfo,
+    threadlocal::ThreadData,
+    timemgmt::SearchLimit,
+    transpositiontable::TT,
+    uci::{self, CHESS960},
+    util::MEGABYTE,
+    NAME, VERSION,
+};
+
+/// Whether the engine is speaking CECP, rather than UCI.
+pub static ACTIVE: AtomicBool = AtomicBool::new(false);
+/// Whether to print thinking output while searching.
+static POST: AtomicBool = AtomicBool::new(true);
+
+/// Commands that can arrive while thinking without disturbing the search.
+const HARMLESS_COMMANDS: [&str; 11] =
+    ["time", "otim", "post", "nopost", "hard", "easy", "computer", "name", "rating", "accepted", "rejected"];
+
+struct GameState {
+    /// The position the game started from.
+    start_fen: String,
+    /// The moves played since the start position.
+    moves: Vec<Move>,
+    /// The side the engine is playing, or `None` in force mode.
+    engine_side: Option<Colour>,
+    analysing: bool,
+    /// The clocks, in milliseconds.
+    our_clock: u64,
+    their_clock: u64,
+    increment: u64,
+    moves_per_session: Option<u64>,
+    move_time: Option<u64>,
+    max_depth: Option<i32>,
+}
+
+impl GameState {
+    const fn new(start_fen: String) -> Self {
+        Self {
+            start_fen,
+            moves: Vec::new(),
+            engine_side: Some(Colour::BLACK),
+            analysing: false,
+            our_clock: 0,
+            their_clock: 0,
+            increment: 0,
+            moves_per_session: None,
+            move_time: None,
+            max_depth: None,
+        }
+    }
+
+    /// Plays a move in the game.
+    fn play(&mut self, pos: &mut Board, m: Move) {
+        pos.zero_height();
+        pos.make_move_simple(m);
+        self.moves.push(m);
+    }
+
+    /// Takes back up to `plies` moves. Searches reset the board's height, so its history
+    /// can't be unwound move by move; instead, the rest of the game is replayed from the start.
+    fn take_back(&mut self, pos: &mut Board, plies: usize) {
+        self.moves.truncate(self.moves.len().saturating_sub(plies));
+        pos.set_from_fen(&self.start_fen).expect("the start position was accepted before");
+        for &m in &self.moves {
+            pos.zero_height();
+            pos.make_move_simple(m);
+        }
+        pos.zero_height();
+    }
+
+    fn search_limit(&self, pos: &Board) -> SearchLimit {
+        if self.analysing {
+            SearchLimit::Infinite
+        } else if let Some(millis) = self.move_time {
+            SearchLimit::Time(millis)
+        } else if let Some(depth) = self.max_depth {
+            SearchLimit::Depth(depth.into())
+        } else {
+            SearchLimit::Dynamic {
+                our_clock: self.our_clock,
+                their_clock: self.their_clock,
+                our_inc: self.increment,
+                their_inc: self.increment,
+                moves_to_go: self.moves_per_session.map(|mps| mps - (pos.ply() as u64 / 2) % mps),
+            }
+        }
+    }
+}
+
+/// Formats a score the way CECP expects it: centipawns, or 100000 + N for a mate in N.
+const fn xboard_score(score: i32) -> i32 {
+    if is_mate_score(score) {
+        let moves_to_mate = (MATE_SCORE - score.abs() + 1) / 2;
+        score.signum() * (100_000 + moves_to_mate)
+    } else {
+        uci::normalise_score(score)
+    }
+}
+
+/// Prints a line of thinking output, if it has been asked for.
+pub fn print_thinking(board: &mut Board, pv: &PVariation, depth: usize, millis: u128, nodes: u64) {
+    if POST.load(Ordering::SeqCst) {
+        let pv_string = board.pv_san(pv).unwrap_or_default();
+        println_logged!("{depth} {} {} {nodes} {pv_string}", xboard_score(pv.score), millis / 10);
+    }
+}
+
+fn format_move(pos: &mut Board, m: Move) -> String {
+    // in FRC, XBoard wants castling moves in SAN, as king-takes-rook is ambiguous with king moves.
+    if m.is_castle() && CHESS960.load(Ordering::SeqCst) {
+        pos.san(m).unwrap_or_else(|| m.to_string())
+    } else {
+        m.to_string()
+    }
+}
+
+const fn result_string(outcome: GameOutcome) -> Option<&'static str> {
+    match outcome {
+        GameOutcome::WhiteWinMate => Some("1-0 {White mates}"),
+        GameOutcome::BlackWinMate => Some("0-1 {Black mates}"),
+        GameOutcome::DrawStalemate => Some("1/2-1/2 {Stalemate}"),
+        GameOutcome::DrawRepetition => Some("1/2-1/2 {Draw by repetition}"),
+        GameOutcome::DrawFiftyMoves => Some("1/2-1/2 {Draw by fifty move rule}"),
+        GameOutcome::DrawInsufficientMaterial => Some("1/2-1/2 {Insufficient material}"),
+        _ => None,
+    }
+}
+
+/// Runs a search on another thread, while listening for commands that interrupt it.
+/// Returns the best move, or `None` if the search was abandoned. Commands that
+/// arrive during the search are added to `queue`, to be handled afterwards.
+fn think(
+    pos: &mut Board,
+    info: &mut SearchInfo,
+    thread_data: &mut [ThreadData],
+    tt: &TT,
+    stdin: &Mutex<mpsc::Receiver<String>>,
+    queue: &mut VecDeque<String>,
+) -> Option<Move> {
+    let stopped = info.stopped;
+    let analysing = matches!(info.time_manager.limit(), SearchLimit::Infinite);
+    tt.increase_age();
+    let stdin = stdin.lock().expect("failed to take lock on stdin");
+    thread::scope(|s| {
+        let search = s.spawn(|| pos.search_position(info, thread_data, tt.view()).1);
+        let mut abandoned = false;
+        while !search.is_finished() {
+            match stdin.recv_timeout(Duration::from_millis(5)) {
+                Ok(line) => {
+                    let command = line.split_whitespace().next().unwrap_or_default();
+                    match command {
+                        "?" => stopped.store(true, Ordering::SeqCst),
+                        "." => {}
+                        "ping" if analysing => println_logged!("pong {}", line.trim_start_matches("ping").trim()),
+                        "ping" => queue.push_back(line),
+                        _ if HARMLESS_COMMANDS.contains(&command) => queue.push_back(line),
+                        _ => {
+                            abandoned = true;
+                            stopped.store(true, Ordering::SeqCst);
+                            queue.push_back(line);
+                        }
+                    }
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+                Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    abandoned = true;
+                    stopped.store(true, Ordering::SeqCst);
+                    queue.push_back("quit".into());
+                }
+            }
+        }
+        let best_move = search.join().expect("search thread panicked");
+        if abandoned {
+            None
+        } else {
+            Some(best_move)
+        }
+    })
+}
+
+#[allow(clippy::too_many_lines, clippy::cognitive_complexity)]
+pub fn main_loop(stdin: &Mutex<mpsc::Receiver<String>>) {
+    ACTIVE.store(true, Ordering::SeqCst);
+    uci::PRETTY_PRINT.store(false, Ordering::SeqCst);
+
+    let mut pos = Board::default();
+    let mut tt = TT::new();
+    tt.resize(16 * MEGABYTE);
+    let stopped = AtomicBool::new(false);
+    let nodes = AtomicU64::new(0);
+    let mut info = SearchInfo::new(&stopped, &nodes);
+    let mut threads = 1;
+    let mut thread_data = vec![ThreadData::new(0, &pos, tt.view())];
+    let mut state = GameState::new(pos.fen());
+    let mut queue = VecDeque::new();
+
+    loop {
+        let line = if let Some(line) = queue.pop_front() {
+            line
+        } else {
+            let Ok(line) = stdin.lock().expect("failed to take lock on stdin").recv() else {
+                break;
+            };
+            line
+        };
+        let mut parts = line.split_whitespace();
+        let Some(command) = parts.next() else {
+            continue;
+        };
+        let arg = line[command.len()..].trim();
+
+        match command {
+            "xboard" | "accepted" | "rejected" | "random" | "computer" | "hard" | "easy" | "name" | "rating"
+            | "ics" | "draw" | "white" | "black" | "." | "?" => {}
+            "protover" => {
+                println_logged!("feature done=0");
+                println_logged!("feature myname=\"{NAME} {VERSION}\"");
+                println_logged!("feature ping=1 setboard=1 usermove=1 analyze=1 colors=0 reuse=1 sigint=0 sigterm=0");
+                println_logged!("feature variants=\"normal,fischerandom\" memory=1 smp=1");
+                println_logged!("feature done=1");
+            }
+            "ping" => println_logged!("pong {arg}"),
+            "new" => {
+                CHESS960.store(false, Ordering::SeqCst);
+                pos.set_startpos();
+                state = GameState::new(pos.fen());
+                tt.clear(threads);
+                thread_data.iter_mut().for_each(ThreadData::clear_tables);
+            }
+            "variant" => match arg {
+                "normal" => CHESS960.store(false, Ordering::SeqCst),
+                "fischerandom" => CHESS960.store(true, Ordering::SeqCst),
+                _ => println_logged!("Error (unsupported variant): {arg}"),
+            },
+            "quit" => break,
+            "force" | "result" => state.engine_side = None,
+            "go" => state.engine_side = Some(pos.turn()),
+            "playother" => state.engine_side = Some(pos.turn().flip()),
+            "post" => POST.store(true, Ordering::SeqCst),
+            "nopost" => POST.store(false, Ordering::SeqCst),
+            "analyze" => {
+                state.analysing = true;
+                state.engine_side = None;
+            }
+            "exit" => state.analysing = false,
+            "level" => {
+                // level MPS BASE INC, where BASE is in minutes (or minutes:seconds), and INC is in seconds.
+                let fields = arg.split_whitespace().collect::<Vec<_>>();
+                if let [mps, _, inc] = fields[..] {
+                    state.moves_per_session = mps.parse().ok().filter(|&mps: &u64| mps > 0);
+                    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                    {
+                        state.increment = (inc.parse::<f64>().unwrap_or(0.0) * 1000.0) as u64;
+                    }
+                    state.move_time = None;
+                } else {
+                    println_logged!("Error (malformed level command): {line}");
+                }
+            }
+            "st" => state.move_time = arg.parse::<u64>().ok().map(|secs| secs * 1000),
+            "sd" => state.max_depth = arg.parse().ok(),
+            // the clocks are given in centiseconds.
+            "time" => state.our_clock = arg.parse::<u64>().unwrap_or(0) * 10,
+            "otim" => state.their_clock = arg.parse::<u64>().unwrap_or(0) * 10,
+            "memory" => {
+                if let Ok(megabytes) = arg.parse::<usize>() {
+                    std::mem::drop(thread_data);
+                    tt.resize(megabytes * MEGABYTE);
+                    thread_data = (0..threads).map(|i| ThreadData::new(i, &pos, tt.view())).collect();
+                }
+            }
+            "cores" => {
+                if let Ok(cores) = arg.parse::<usize>() {
+                    threads = cores.max(1);
+                    thread_data = (0..threads).map(|i| ThreadData::new(i, &pos, tt.view())).collect();
+                }
+            }
+            "setboard" => match pos.set_from_fen(arg) {
+                Ok(()) => {
+                    state.start_fen = pos.fen();
+                    state.moves.clear();
+                }
+                Err(e) => println_logged!("tellusererror Illegal position: {e}"),
+            },
+            "undo" | "remove" => {
+                let plies = if command == "undo" { 1 } else { 2 };
+                state.take_back(&mut pos, plies);
+            }
+            "usermove" => match pos.parse_move(arg) {
+                Ok(m) => state.play(&mut pos, m),
+                Err(_) => println_logged!("Illegal move: {arg}"),
+            },
+            _ => println_logged!("Error (unknown command): {command}"),
+        }
+
+        // once the command queue is drained, start thinking if it's our move.
+        if !queue.is_empty() {
+            continue;
+        }
+        let our_move = state.engine_side == Some(pos.turn());
+        if !(our_move || state.analysing) || pos.outcome() != GameOutcome::Ongoing {
+            continue;
+        }
+        for t in &mut thread_data {
+            t.nnue.reinit_from(&pos);
+        }
+        info.time_manager.set_limit(state.search_limit(&pos));
+        info.time_manager.start();
+        let best_move = think(&mut pos, &mut info, &mut thread_data, &tt, stdin, &mut queue);
+        if let (Some(m), false) = (best_move, state.analysing) {
+            println_logged!("move {}", format_move(&mut pos, m));
+            state.play(&mut pos, m);
+            if let Some(result) = result_string(pos.outcome()) {
+                println_logged!("{result}");
+            }
+        }
+    }
+
+    ACTIVE.store(false, Ordering::SeqCst);
+}
+
+mod tests {
+    #[test]
+    fn scores_use_xboard_mate_convention() {
+        use crate::board::evaluation::{mate_in, mated_in};
+        assert_eq!(super::xboard_score(mate_in(1)), 100_001);
+        assert_eq!(super::xboard_score(mated_in(4)), -100_002);
+        assert_eq!(super::xboard_score(0), 0);
+    }
+
+    #[test]
+    fn undo_after_the_engine_has_moved() {
+        use super::GameState;
+        use crate::{
+            board::Board,
+            searchinfo::SearchInfo,
+            threadlocal::ThreadData,
+            timemgmt::{SearchLimit, TimeManager},
+            transpositiontable::TT,
+            util::MEGABYTE,
+        };
+        use std::sync::atomic::{AtomicBool, AtomicU64};
+
+        let mut pos = Board::default();
+        let mut state = GameState::new(pos.fen());
+        let e4 = pos.parse_move("e2e4").unwrap();
+        state.play(&mut pos, e4);
+        let after_e4 = pos.fen();
+
+        let stopped = AtomicBool::new(false);
+        let nodes = AtomicU64::new(0);
+        let time_manager = TimeManager::default_with_limit(SearchLimit::Depth(3.into()));
+        let mut info = SearchInfo { time_manager, ..SearchInfo::new(&stopped, &nodes) };
+        let mut tt = TT::new();
+        tt.resize(MEGABYTE);
+        let mut t = ThreadData::new(0, &pos, tt.view());
+        let (_, reply) = pos.search_position(&mut info, std::array::from_mut(&mut t), tt.view());
+        state.play(&mut pos, reply);
+
+        state.take_back(&mut pos, 1);
+        assert_eq!(pos.fen(), after_e4);
+        state.play(&mut pos, reply);
+        state.take_back(&mut pos, 2);
+        assert_eq!(pos.fen(), Board::default().fen());
+        // taking back more moves than were played stops at the start position.
+        state.take_back(&mut pos, 2);
+        assert_eq!(pos.fen(), Board::default().fen());
+    }
+}